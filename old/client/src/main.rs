@@ -1,17 +1,24 @@
 extern crate reqwest;
 extern crate rustyline;
 extern crate protocol;
+extern crate pallasite;
 extern crate base64;
+extern crate cid;
 extern crate ring;
 extern crate untrusted;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 
+use cid::Cid;
 use protocol::*;
+use pallasite::document::Document;
+use pallasite::markdown;
+use pallasite::text;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::env;
 use std::str;
 use std::io::Read;
 use ring::{signature, rand};
@@ -56,12 +63,22 @@ fn do_server(args: &mut str::SplitWhitespace) -> ClientState {
 const CONVERSATION: &str = "/name/conversation";
 
 fn get_ipfs_doc(name: &str) {
-    // Using 'get' here 
-    let url = format!("http://localhost:5001/api/v0/cat?arg={}", name);
+    // Documents are stored as plain blocks, not files.
+    let url = format!("http://localhost:5001/api/v0/block/get?arg={}", name);
     let mut resp = reqwest::get(&url).expect("Could not get IPFS doc?");
-    let mut content = String::new();
-    resp.read_to_string(&mut content).unwrap();
-    println!("{}", content);
+    let mut content = Vec::new();
+    resp.read_to_end(&mut content).unwrap();
+    match Document::from_cbor(&content) {
+        Ok(doc) => {
+            let options = text::Options {
+                width: 80,
+                color: env::var_os("NO_COLOR").is_none(),
+            };
+            print!("{}", text::render(&doc, &options));
+        }
+        // Not a document, so just show whatever it is.
+        Err(_) => println!("{}", String::from_utf8_lossy(&content)),
+    }
 }
 
 fn do_get(client: &ClientState, args: &mut str::SplitWhitespace) {
@@ -81,50 +98,31 @@ fn do_get(client: &ClientState, args: &mut str::SplitWhitespace) {
 }
 
 #[derive(Deserialize, Debug)]
-struct IpfsAddResponse {
-    Hash: String,
+struct IpfsBlockPutResponse {
+    Key: String,
 }
 
-/// Adds a chunk of data to IPFS
-/// horrifically writing it out to a temp file and posting that
-/// and returns a string containing the IPFS hash of the new data.
-fn add_data_to_ipfs(data: &str) -> String {
-    let url = format!("http://localhost:5001/api/v0/add");
+/// Stores a DAG-CBOR block in IPFS as it is and returns its CID.
+/// `/api/v0/add` would wrap it in a UnixFS file, which gets a CID of
+/// its own that nothing else can link to.
+/// Horrifically writes it out to a temp file and posts that.
+fn put_block_to_ipfs(data: &[u8]) -> Cid {
+    let url = "http://localhost:5001/api/v0/block/put?cid-codec=dag-cbor&mhtype=sha2-256";
     let client = reqwest::Client::new();
     {
         // Write the stupid stuff out to a file
         use std::fs;
         use std::io::Write;
         let mut f = fs::File::create("tempfile.txt").unwrap();
-        f.write(data.as_bytes()).unwrap();
+        f.write_all(data).unwrap();
     }
-    // use reqwest::header::ContentType;
-    // use reqwest::mime;
-    // let form = reqwest::multipart::Form::new()
-    //     .file("foo", "tempfile.txt").unwrap()
-    //     .text("path", "bar");
-    // let req = client.post(&url)
-    //     .multipart(form)
-    //     .body("rawr")
-    //     .build().unwrap();
-    // println!("Request is: {:?}\nBody is: {:?}", req, req.body());
-
     let form = reqwest::multipart::Form::new()
-        .file("foo", "tempfile.txt").unwrap()
-        .text("path", "bar");
-    let mut resp = client.post(&url)
+        .file("data", "tempfile.txt").unwrap();
+    let mut resp = client.post(url)
         .multipart(form)
-        // .body("file")
-        // .header(ContentType(mime::MULTIPART_FORM_DATA))
-        // .body(data.to_owned())
         .send().unwrap();
-
-    let ipfs_response: IpfsAddResponse = resp.json().unwrap();
-
-    // let mut content = String::new();
-    // resp.read_to_string(&mut content).unwrap();
-    // println!("Got response: {:?}\n'{:?}'", resp, ipfs_response);
-    ipfs_response.Hash
+    let ipfs_response: IpfsBlockPutResponse = resp.json().unwrap();
+    Cid::from(ipfs_response.Key.as_str()).expect("IPFS returned an invalid CID?")
 }
 
 fn do_post(client: &ClientState, args: &mut str::SplitWhitespace) {
@@ -132,8 +130,24 @@ fn do_post(client: &ClientState, args: &mut str::SplitWhitespace) {
         let url = String::from("http://") + s.target_server.as_ref() + CONVERSATION;
         let mut rl = Editor::<()>::new();
         let dataline = rl.readline("Enter data to post: ").unwrap();
-        let ipfs_hash = add_data_to_ipfs(&dataline);
-        let data = UpdateMessage::signed_message(&s.key, &s.username, &ipfs_hash);
+        let doc = markdown::parse(&dataline).document;
+        let problems = doc.validate();
+        if !problems.is_empty() {
+            println!("Not posting, document is invalid:");
+            for problem in &problems {
+                println!("  {}", problem);
+            }
+            return;
+        }
+        for warning in &doc.lint() {
+            println!("Warning: {}", warning);
+        }
+        let cid = put_block_to_ipfs(&doc.to_cbor());
+        if cid != doc.cid() {
+            println!("Not posting, IPFS stored the document as {} instead of {}", cid, doc.cid());
+            return;
+        }
+        let data = UpdateMessage::signed_message(&s.key, &s.username, &cid.to_string());
         // let data = UpdateMessage {
         //     user: "rawr".into(),
         //     signature: "".into(),
@@ -6,11 +6,20 @@ extern crate rustc_serialize;
 extern crate ring;
 extern crate untrusted;
 extern crate base64;
+extern crate cid;
+extern crate pallasite;
+extern crate reqwest;
 
 
 use std::collections::HashMap;
+use std::env;
+use std::io::Read;
 use std::sync::RwLock;
 
+use cid::Cid;
+use pallasite::atom;
+use pallasite::html::Gateway;
+use pallasite::store::Lookup;
 use rouille::Response;
 
 extern crate protocol;
@@ -19,9 +28,35 @@ use protocol::*;
 use ring::{signature, rand};
 
 
+/// Where feed entries link to, since feed readers can't be expected
+/// to have an IPFS node of their own.
+const GATEWAY: &str = "https://ipfs.io";
+const IPFS_API: &str = "http://localhost:5001";
+/// How many of a name's latest updates its feed covers.
+const FEED_LENGTH: usize = 50;
+
+/// Fetches blocks from the local IPFS node.
+struct Ipfs;
+
+impl Lookup for Ipfs {
+    fn get(&self, cid: &Cid) -> Option<Vec<u8>> {
+        // Documents are stored as plain blocks, not files.
+        let url = format!("{}/api/v0/block/get?arg={}", IPFS_API, cid);
+        let mut resp = reqwest::get(&url).ok()?;
+        if !resp.status().is_success() {
+            return None;
+        }
+        let mut content = Vec::new();
+        resp.read_to_end(&mut content).ok()?;
+        Some(content)
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 struct ServerData {
     names: HashMap<String, UpdateMessage>,
+    /// The last `FEED_LENGTH` updates each name has had, oldest first.
+    history: HashMap<String, Vec<UpdateMessage>>,
     keys: HashMap<String, Vec<u8>>,
 }
 
@@ -30,6 +65,10 @@ impl ServerData {
         self.names.get(name)
     }
 
+    fn get_history(&self, name: &str) -> Option<&[UpdateMessage]> {
+        self.history.get(name).map(|x| x.as_ref())
+    }
+
     fn get_id_key(&self, id: &str) -> Option<&[u8]> {
         self.keys.get(id).map(|x| x.as_ref())
     }
@@ -47,6 +86,20 @@ impl ServerData {
 
     fn update_name(&mut self, name: &str, contents: &UpdateMessage) {
         self.names.insert(name.to_string(), contents.clone());
+        let history = self.history.entry(name.to_string()).or_insert_with(Vec::new);
+        history.push(contents.clone());
+        // Only the feed looks further back than the latest update.
+        if history.len() > FEED_LENGTH {
+            history.remove(0);
+        }
+    }
+
+    /// The documents a name's feed should cover, oldest first, leaving
+    /// out any updates that aren't a CID.
+    fn feed_updates(&self, name: &str) -> Option<Vec<Cid>> {
+        Some(self.get_history(name)?.iter()
+            .filter_map(|msg| Cid::from(msg.new_contents.as_str()).ok())
+            .collect())
     }
 
     fn apply_update_if_valid(&mut self, dest: &str, msg: &UpdateMessage) -> Result<(), ValidationError> {
@@ -70,10 +123,14 @@ impl ServerData {
 
     }
 
-    fn run(server: ServerData, addr: &str) {
+    /// Serves on `addr`.  `public_url` is where clients can reach the
+    /// server, which is what feeds are identified by.
+    fn run(server: ServerData, addr: &str, public_url: &str) {
         let server = RwLock::new(server);
         server.write().unwrap().add_user("icefox");
-        rouille::start_server(addr, move |request| {
+        let addr = addr.to_string();
+        let public_url = public_url.to_string();
+        rouille::start_server(addr.clone(), move |request| {
             router!(
                 request,
                 (GET) (/id/{name:String}) => {
@@ -91,10 +148,31 @@ impl ServerData {
                         Response::empty_404()
                     }
                 },
+                (GET) (/name/{name:String}/feed) => {
+                    println!("Got get to feed for {}", &name);
+                    // Copied out so the lock isn't held while the
+                    // documents are fetched from IPFS.
+                    let updates = server.read().unwrap().feed_updates(&name);
+                    match updates {
+                        Some(updates) => {
+                            Response::from_data("application/atom+xml", feed(&public_url, request.header("Host"), &name, &updates))
+                        }
+                        None => Response::empty_404(),
+                    }
+                },
                 (POST) (/name/{name:String}) => {
                     println!("Got post to {}", &name);
                     let rename_request: UpdateMessage = try_or_400!(rouille::input::json_input(request));
                     println!("Got post to {}: {:?}", &name, rename_request);
+                    // The document is fetched from IPFS without holding
+                    // the lock, and only for someone allowed to post.
+                    let allowed = server.read().unwrap().validate_update(&rename_request);
+                    if let Err(v) = allowed {
+                        return Response::text(format!("{:?}", v)).with_status_code(403);
+                    }
+                    if let Err(problems) = check_contents(&rename_request.new_contents) {
+                        return Response::text(problems).with_status_code(422);
+                    }
                     match server.write().unwrap().apply_update_if_valid(&name, &rename_request) {
                         Ok(_) => Response::text("ok"),
                         Err(v) => Response::text(format!("{:?}", v)).with_status_code(403),
@@ -106,9 +184,62 @@ impl ServerData {
     }
 }
 
+/// An Atom feed of the documents a name has pointed to.  Its id comes
+/// from the server's public URL, so it stays the same however clients
+/// reach it, but the self link uses the `Host` they asked for, if any.
+/// Updates that can't be fetched are left out.
+fn feed(public_url: &str, host: Option<&str>, name: &str, updates: &[Cid]) -> String {
+    let path = format!("/name/{}/feed", encode_segment(name));
+    let feed = atom::Feed {
+        id: format!("{}{}", public_url.trim_end_matches('/'), path),
+        title: name.to_string(),
+        link: Some(match host {
+            Some(host) => format!("http://{}{}", host, path),
+            None => format!("{}{}", public_url.trim_end_matches('/'), path),
+        }),
+    };
+    let history = atom::render_history(&feed, &Ipfs, updates, &Gateway(GATEWAY.to_string()));
+    for e in &history.errors {
+        println!("Left out of the feed for {}: {}", name, e);
+    }
+    history.feed
+}
+
+/// Checks that an update pointing at a document points at a valid
+/// one, returning what's wrong with it if not.  Contents that aren't a
+/// CID aren't documents, so they're let through as they are.
+fn check_contents(contents: &str) -> Result<(), String> {
+    let cid = match Cid::from(contents) {
+        Ok(cid) => cid,
+        Err(_) => return Ok(()),
+    };
+    let doc = Ipfs.document(&cid).map_err(|e| e.to_string())?;
+    let problems = doc.validate();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+/// Percent-encodes a name for use as one segment of a URL path.
+fn encode_segment(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
 fn main() {
     let s = ServerData::default();
-    ServerData::run(s, "127.0.0.1:8888");
+    // Where people reach the server, which may not be where it listens,
+    // behind a proxy say.
+    let public_url = env::var("PUBLIC_URL").unwrap_or_else(|_| "http://localhost:8888".to_string());
+    ServerData::run(s, "127.0.0.1:8888", &public_url);
 }
 
 
@@ -133,7 +264,7 @@ mod tests {
         let pubkey_bytes = KEYPAIR.public_key_bytes();
         s.add_id(UNITTEST_USER, pubkey_bytes);
         s.update_name(UNITTEST_NAME, UNITTEST_NAME_VALUE);
-        ServerData::run(s, "127.0.0.1:8888");
+        ServerData::run(s, "127.0.0.1:8888", "http://localhost:8888");
 
     }
 
@@ -201,6 +332,48 @@ mod tests {
         assert_eq!(content, UNITTEST_NAME_VALUE);
     }
 
+    #[test]
+    fn test_feed() {
+        let resp = spawn_server_and_get("/name/test_no_name/feed");
+        assert_eq!(resp.status(), reqwest::StatusCode::NotFound);
+
+        // The test name isn't set to a CID, so there's nothing to fetch
+        // from IPFS, but the feed is still there.
+        let mut resp = spawn_server_and_get((String::from("/name/") + UNITTEST_NAME + "/feed").as_str());
+        assert!(resp.status().is_success());
+        let mut content = String::new();
+        resp.read_to_string(&mut content).unwrap();
+        assert!(content.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\""));
+        assert!(content.contains(&format!("<title>{}</title>", UNITTEST_NAME)));
+        assert!(!content.contains("<entry>"));
+    }
+
+    #[test]
+    fn test_feed_length() {
+        use super::{ServerData, FEED_LENGTH};
+        let mut s = ServerData::default();
+        for i in 0..FEED_LENGTH + 10 {
+            // One update in the last FEED_LENGTH isn't a CID.
+            let contents = if i == FEED_LENGTH { "not a cid" } else { "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG" };
+            s.update_name("many", &super::UpdateMessage::signed_message(&KEYPAIR, UNITTEST_USER, contents));
+        }
+        assert_eq!(s.get_history("many").unwrap().len(), FEED_LENGTH);
+        assert_eq!(s.feed_updates("many").unwrap().len(), FEED_LENGTH - 1);
+        assert_eq!(s.feed_updates("nobody"), None);
+    }
+
+    #[test]
+    fn test_check_contents() {
+        assert_eq!(super::check_contents("foo!"), Ok(()));
+    }
+
+    #[test]
+    fn test_feed_id() {
+        let atom = super::feed("https://names.example/", Some("localhost:8888"), "a b/c", &[]);
+        assert!(atom.contains("<id>https://names.example/name/a%20b%2Fc/feed</id>"));
+        assert!(atom.contains("href=\"http://localhost:8888/name/a%20b%2Fc/feed\""));
+    }
+
     
     #[test]
     fn test_post_name() {
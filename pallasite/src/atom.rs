@@ -0,0 +1,304 @@
+//! Atom 1.0 feeds (RFC 4287), so people can follow a name with an
+//! ordinary feed reader.
+//!
+//! Each document is one entry, with its contents rendered to HTML.
+//! Replies say what they're replying to with the thread extension
+//! (RFC 4685), so readers that understand it can show conversations.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use cid::Cid;
+
+use document::{plain_text, Document, Segment};
+use html::{self, escape, Linker};
+use path::Path;
+use store::{self, CidKey, Lookup};
+use visit::{self, Visit};
+
+/// The longest a title made from a document's text gets, in characters.
+const EXCERPT_LENGTH: usize = 60;
+
+/// What a feed says about itself.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Feed {
+    /// A permanent IRI for the feed, which doesn't change when it
+    /// moves.
+    pub id: String,
+    pub title: String,
+    /// Where the feed itself is published, if it's anywhere in
+    /// particular.
+    pub link: Option<String>,
+}
+
+/// A feed of a name's update history.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct History {
+    pub feed: String,
+    /// Updates that couldn't be fetched or decoded, which have no
+    /// entry in the feed.
+    pub errors: Vec<store::Error>,
+}
+
+/// Renders documents as a feed, newest first.  Documents without a
+/// date go last.
+pub fn render(feed: &Feed, documents: &[Document], linker: &dyn Linker) -> String {
+    let entries: Vec<(Cid, &Document)> = documents.iter().map(|d| (d.cid(), d)).collect();
+    render_entries(feed, entries, linker)
+}
+
+/// Renders the documents a name has pointed to, given oldest first as
+/// in the name server's update history.  A document the name pointed
+/// to more than once only gets one entry.  Entries are identified by
+/// the CIDs the name pointed to, which is where readers can find them,
+/// even if the blocks there would encode differently now.
+pub fn render_history<L>(feed: &Feed, store: &L, updates: &[Cid], linker: &dyn Linker) -> History
+    where L: Lookup + ?Sized
+{
+    let mut seen = HashSet::new();
+    let mut documents = Vec::new();
+    let mut errors = Vec::new();
+    for cid in updates {
+        if seen.insert(CidKey::new(cid)) {
+            match store.document(cid) {
+                Ok(doc) => documents.push((cid.clone(), doc)),
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+    let entries = documents.iter().map(|(cid, doc)| (cid.clone(), doc)).collect();
+    History { feed: render_entries(feed, entries, linker), errors }
+}
+
+fn render_entries(feed: &Feed, mut entries: Vec<(Cid, &Document)>, linker: &dyn Linker) -> String {
+    entries.sort_by_key(|&(_, d)| Reverse(d.date));
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                  <feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:thr=\"http://purl.org/syndication/thread/1.0\">\n");
+    writeln!(out, "<id>{}</id>", escape(&feed.id)).unwrap();
+    writeln!(out, "<title>{}</title>", escape(&feed.title)).unwrap();
+    // Atom insists on a date, and making one up from the clock would
+    // make the feed look changed every time it's fetched.
+    let updated = entries.iter().filter_map(|&(_, d)| d.date).max()
+        .unwrap_or_else(|| DateTime::from_timestamp(0, 0).expect("the epoch"));
+    writeln!(out, "<updated>{}</updated>", updated.to_rfc3339_opts(SecondsFormat::Secs, true)).unwrap();
+    if let Some(ref link) = feed.link {
+        writeln!(out, "<link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>", escape(link)).unwrap();
+    }
+    for (cid, doc) in entries {
+        entry(&mut out, &cid, doc, linker, updated);
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+fn entry(out: &mut String, cid: &Cid, doc: &Document, linker: &dyn Linker, feed_updated: DateTime<Utc>) {
+    match doc.language {
+        Some(ref lang) => writeln!(out, "<entry xml:lang=\"{}\">", escape(lang.as_str())).unwrap(),
+        None => out.push_str("<entry>\n"),
+    }
+    writeln!(out, "<id>ipfs://{}</id>", cid).unwrap();
+    writeln!(out, "<title>{}</title>", escape(&title(doc))).unwrap();
+    // Every entry needs an author, unless the feed has one, and a
+    // name server's feed can have posts from anybody.
+    let author = doc.author.clone().or_else(|| doc.author_id.as_ref().map(|a| a.to_string()));
+    writeln!(out, "<author><name>{}</name></author>", escape(author.as_ref().map_or("Anonymous", |a| &**a))).unwrap();
+    let updated = match doc.local_date_time() {
+        Some(date) => date.to_rfc3339_opts(SecondsFormat::Secs, true),
+        None => feed_updated.to_rfc3339_opts(SecondsFormat::Secs, true),
+    };
+    writeln!(out, "<updated>{}</updated>", updated).unwrap();
+    // No type, since the linker might point at the raw block rather
+    // than a page.
+    writeln!(out, "<link rel=\"alternate\" href=\"{}\"/>", escape(&linker.url(cid))).unwrap();
+    if let Some(ref parent) = doc.in_response_to {
+        writeln!(out, "<thr:in-reply-to ref=\"ipfs://{}\" href=\"{}\"/>", parent, escape(&linker.url(parent))).unwrap();
+    }
+    for tag in &doc.tags {
+        write!(out, "<category term=\"{}\"", escape(&tag.term)).unwrap();
+        if let Some(ref vocabulary) = tag.vocabulary {
+            write!(out, " scheme=\"ipfs://{}\"", vocabulary).unwrap();
+        }
+        out.push_str("/>\n");
+    }
+    writeln!(out, "<content type=\"html\">{}</content>", escape(&html::render_fragment(doc, linker))).unwrap();
+    out.push_str("</entry>\n");
+}
+
+/// The title, or the subject, or failing that the start of the first
+/// paragraph, since replies in a conversation don't usually have
+/// either.
+fn title(doc: &Document) -> String {
+    struct FirstPara(Option<String>);
+
+    impl<'ast> Visit<'ast> for FirstPara {
+        fn visit_segment(&mut self, path: &mut Path, node: &'ast Segment) {
+            if self.0.is_some() {
+                return;
+            }
+            if let Segment::Para(ref elements) = *node {
+                let text = plain_text(elements);
+                if !text.trim().is_empty() {
+                    self.0 = Some(text);
+                }
+                return;
+            }
+            visit::visit_segment(self, path, node);
+        }
+    }
+
+    if let Some(title) = doc.title.as_ref().or(doc.subject.as_ref()) {
+        return title.clone();
+    }
+    let mut first = FirstPara(None);
+    first.visit_document(&mut Path::new(), doc);
+    let text = match first.0 {
+        Some(text) => text,
+        None => return "Untitled".to_string(),
+    };
+    let mut excerpt = String::new();
+    for word in text.split_whitespace() {
+        if !excerpt.is_empty() && excerpt.chars().count() + 1 + word.chars().count() > EXCERPT_LENGTH {
+            excerpt.push('…');
+            break;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+    excerpt
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::prelude::*;
+
+    use document::tests::{cid, text, CID_A};
+    use document::*;
+    use html::Gateway;
+    use language::LanguageTag;
+    use store::MemoryStore;
+    use store::tests::Liar;
+    use super::*;
+
+    fn post(author: &str, body: &str, day: u32) -> Document {
+        let mut doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![text(body)])])]);
+        doc.author = Some(author.to_string());
+        doc.date = Some(Utc.with_ymd_and_hms(2018, 3, day, 12, 0, 0).unwrap());
+        doc
+    }
+
+    fn feed() -> Feed {
+        Feed {
+            id: "tag:example.com,2018:conversation".to_string(),
+            title: "Conversation & stuff".to_string(),
+            link: Some("http://example.com/name/conversation/feed".to_string()),
+        }
+    }
+
+    fn gateway() -> Gateway {
+        Gateway("https://ipfs.io".to_string())
+    }
+
+    #[test]
+    fn test_feed() {
+        let mut first = post("Ada", "Hello <everyone>", 1);
+        first.title = Some("Hello".to_string());
+        first.local_date = Some(FixedOffset::west_opt(5 * 3600).unwrap());
+        first.tags = vec![Tag::new("greetings"), Tag::from_vocabulary("misc", &cid(CID_A))];
+        let mut reply = post("Simon", "Hi!", 2);
+        reply.in_response_to = Some(first.cid());
+        reply.language = Some(LanguageTag::parse("en").unwrap());
+
+        let atom = render(&feed(), &[first.clone(), reply.clone()], &gateway());
+        assert_eq!(atom, format!("\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<feed xmlns=\"http://www.w3.org/2005/Atom\" xmlns:thr=\"http://purl.org/syndication/thread/1.0\">
+<id>tag:example.com,2018:conversation</id>
+<title>Conversation &amp; stuff</title>
+<updated>2018-03-02T12:00:00Z</updated>
+<link rel=\"self\" type=\"application/atom+xml\" href=\"http://example.com/name/conversation/feed\"/>
+<entry xml:lang=\"en\">
+<id>ipfs://{reply}</id>
+<title>Hi!</title>
+<author><name>Simon</name></author>
+<updated>2018-03-02T12:00:00Z</updated>
+<link rel=\"alternate\" href=\"https://ipfs.io/ipfs/{reply}\"/>
+<thr:in-reply-to ref=\"ipfs://{first}\" href=\"https://ipfs.io/ipfs/{first}\"/>
+<content type=\"html\">&lt;p&gt;Hi!&lt;/p&gt;
+</content>
+</entry>
+<entry>
+<id>ipfs://{first}</id>
+<title>Hello</title>
+<author><name>Ada</name></author>
+<updated>2018-03-01T07:00:00-05:00</updated>
+<link rel=\"alternate\" href=\"https://ipfs.io/ipfs/{first}\"/>
+<category term=\"greetings\"/>
+<category term=\"misc\" scheme=\"ipfs://{vocabulary}\"/>
+<content type=\"html\">&lt;p&gt;Hello &amp;lt;everyone&amp;gt;&lt;/p&gt;
+</content>
+</entry>
+</feed>
+", first = first.cid(), reply = reply.cid(), vocabulary = CID_A));
+    }
+
+    #[test]
+    fn test_titles() {
+        let long = post("Ada", "This is a rather long first paragraph, which goes on for a while after the title would end", 1);
+        assert_eq!(title(&long), "This is a rather long first paragraph, which goes on for a…");
+        let mut doc = Document::new(vec![Part::Section { level: 1, heading: vec![text("Heading")], contents: vec![
+            Segment::Para(vec![]),
+            Segment::Para(vec![text("  Words  here ")]),
+        ] }]);
+        assert_eq!(title(&doc), "Words here");
+        doc.subject = Some("Re: words".to_string());
+        assert_eq!(title(&doc), "Re: words");
+        assert_eq!(title(&Document::new(vec![])), "Untitled");
+
+        // No date or author: the entry still has everything Atom requires.
+        let atom = render(&feed(), &[Document::new(vec![])], &gateway());
+        assert!(atom.contains("<updated>1970-01-01T00:00:00Z</updated>\n<link rel=\"self\""));
+        assert!(atom.contains("<title>Untitled</title>\n<author><name>Anonymous</name></author>\n\
+                               <updated>1970-01-01T00:00:00Z</updated>\n"));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut store = MemoryStore::new();
+        let one = store.put_document(&post("Ada", "One", 1));
+        let two = store.put_document(&post("Ada", "Two", 2));
+        let history = render_history(&feed(), &store, &[one.clone(), two.clone(), one.clone()], &gateway());
+        assert_eq!(history.errors, vec![]);
+        let atom = history.feed;
+        assert_eq!(atom.matches("<entry>").count(), 2);
+        assert!(atom.find(&format!("<id>ipfs://{}</id>", two)).unwrap() <
+                atom.find(&format!("<id>ipfs://{}</id>", one)).unwrap());
+
+        // A missing revision doesn't stop the rest of the feed.
+        let gone = cid(CID_A);
+        let history = render_history(&feed(), &store, &[one.clone(), gone.clone()], &gateway());
+        assert_eq!(history.errors, vec![store::Error::Missing(gone)]);
+        assert!(history.feed.contains(&format!("<id>ipfs://{}</id>", one)));
+    }
+
+    #[test]
+    fn test_history_cids() {
+        // The name points at a CID other than the one we'd compute, so
+        // that's the one readers need.
+        let doc = post("Ada", "One", 1);
+        let mut blocks = HashMap::new();
+        blocks.insert(CID_A.to_string(), doc.to_cbor());
+        let atom = render_history(&feed(), &Liar(blocks), &[cid(CID_A)], &gateway()).feed;
+        assert!(atom.contains(&format!("<id>ipfs://{}</id>\n", CID_A)));
+        assert!(atom.contains(&format!("<link rel=\"alternate\" href=\"https://ipfs.io/ipfs/{}\"/>", CID_A)));
+        assert!(!atom.contains(&doc.cid().to_string()));
+    }
+}
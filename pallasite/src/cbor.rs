@@ -0,0 +1,831 @@
+//! Canonical DAG-CBOR encoding.
+//!
+//! Anything that implements `Serialize` can be turned into DAG-CBOR,
+//! with the restrictions that make the encoding deterministic:
+//!
+//!  * Map keys must be strings, and are sorted length-first, then bytewise
+//!  * Integers and lengths always use the shortest possible encoding
+//!  * No indefinite-length items
+//!  * No floats
+//!  * The only tag is 42, for CIDs
+//!
+//! Decoding is just as picky, so any bytes that decode to a `Value` are
+//! exactly the bytes you get by encoding that `Value` again.  Types
+//! going through serde can still lose things on the way, like fields
+//! they don't know about or an explicit null where a field is usually
+//! left out, so use `from_block()` for anything whose CID matters.  That
+//! way the hash of a block is the hash of its contents, no matter who
+//! made it.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+
+use cid::{Cid, Codec, Version};
+use multihash;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+use serde_cid;
+
+/// The CBOR tag IPLD uses for links.
+const CID_TAG: u64 = 42;
+
+/// How deeply arrays, maps and tags can nest.  Decoding recurses, so
+/// without a limit a block of nothing but array headers would overflow
+/// the stack.
+pub const MAX_DEPTH: usize = 256;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Ran out of input partway through an item
+    UnexpectedEof,
+    /// There's stuff left over after the top-level item
+    TrailingBytes,
+    /// Something well-formed, but not canonical DAG-CBOR
+    NotCanonical(&'static str),
+    /// Something DAG-CBOR has no way of representing
+    Unsupported(&'static str),
+    InvalidUtf8,
+    InvalidCid,
+    /// Items nested more than `MAX_DEPTH` deep
+    TooDeep,
+    /// Errors from serde, such as a missing field
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnexpectedEof => write!(f, "unexpected end of input"),
+            Error::TrailingBytes => write!(f, "trailing bytes after item"),
+            Error::NotCanonical(s) => write!(f, "not canonical DAG-CBOR: {}", s),
+            Error::Unsupported(s) => write!(f, "unsupported in DAG-CBOR: {}", s),
+            Error::InvalidUtf8 => write!(f, "invalid UTF-8 in text string"),
+            Error::InvalidCid => write!(f, "invalid CID in link"),
+            Error::TooDeep => write!(f, "items nested more than {} deep", MAX_DEPTH),
+            Error::Message(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// The IPLD data model, more or less.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// CBOR can store anything from -2^64 to 2^64-1
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// Ordered by key in the usual string order; the canonical
+    /// length-first order is applied when encoding.
+    Map(BTreeMap<String, Value>),
+    Link(Cid),
+}
+
+/// Serialize a value into canonical DAG-CBOR.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    let v = to_value(value)?;
+    let mut out = Vec::new();
+    encode(&v, &mut out);
+    Ok(out)
+}
+
+/// Deserialize a value from canonical DAG-CBOR.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    let v = decode(bytes)?;
+    T::deserialize(v)
+}
+
+/// Deserialize a value from a block, and make sure encoding it again
+/// gives exactly the same bytes, so that the block's CID really is the
+/// CID of the value.
+pub fn from_block<T: DeserializeOwned + Serialize>(bytes: &[u8]) -> Result<T, Error> {
+    let v = from_slice(bytes)?;
+    if to_vec(&v)? != bytes {
+        return Err(Error::NotCanonical("fields that don't survive decoding, like unknown fields or nulls"));
+    }
+    Ok(v)
+}
+
+/// The CIDv1 of a DAG-CBOR block, using a sha2-256 multihash.
+pub fn block_cid(bytes: &[u8]) -> Cid {
+    let hash = multihash::encode(multihash::Hash::SHA2256, bytes)
+        .expect("sha2-256 is always supported");
+    Cid::new(Codec::DagCBOR, Version::V1, &hash)
+}
+
+/// Turn anything serializable into a `Value`.
+pub fn to_value<T: Serialize>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+// Encoding
+
+fn write_head(major: u8, n: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if n <= 0xFF {
+        out.push(major | 24);
+        out.push(n as u8);
+    } else if n <= 0xFFFF {
+        out.push(major | 25);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= 0xFFFF_FFFF {
+        out.push(major | 26);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+/// The canonical key order: shorter keys first, then bytewise.
+fn key_order(a: &str, b: &str) -> ::std::cmp::Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.as_bytes().cmp(b.as_bytes()))
+}
+
+/// Encode a `Value` as canonical DAG-CBOR.
+pub fn encode(value: &Value, out: &mut Vec<u8>) {
+    match *value {
+        Value::Null => out.push(0xF6),
+        Value::Bool(false) => out.push(0xF4),
+        Value::Bool(true) => out.push(0xF5),
+        Value::Integer(i) if i >= 0 => write_head(0, i as u64, out),
+        Value::Integer(i) => write_head(1, (-1 - i) as u64, out),
+        Value::Bytes(ref b) => {
+            write_head(2, b.len() as u64, out);
+            out.extend_from_slice(b);
+        }
+        Value::Text(ref s) => {
+            write_head(3, s.len() as u64, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(ref items) => {
+            write_head(4, items.len() as u64, out);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Map(ref map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| key_order(a.0, b.0));
+            write_head(5, entries.len() as u64, out);
+            for (k, v) in entries {
+                write_head(3, k.len() as u64, out);
+                out.extend_from_slice(k.as_bytes());
+                encode(v, out);
+            }
+        }
+        Value::Link(ref cid) => {
+            // The leading zero is the multibase prefix for "raw binary".
+            let bytes = cid.to_bytes();
+            write_head(6, CID_TAG, out);
+            write_head(2, bytes.len() as u64 + 1, out);
+            out.push(0);
+            out.extend_from_slice(&bytes);
+        }
+    }
+}
+
+// Decoding
+
+/// Decode a single canonical DAG-CBOR item, which must take up all of `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<Value, Error> {
+    let mut d = Decoder { input: bytes, pos: 0, depth: 0 };
+    let v = d.value()?;
+    if d.pos != bytes.len() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(v)
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    /// How many arrays, maps and tags we're inside.
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.input.len() - self.pos < n {
+            return Err(Error::UnexpectedEof);
+        }
+        let s = &self.input[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(s)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64, Error> {
+        Ok(self.take(n)?.iter().fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+    }
+
+    /// Returns the major type and argument of the next item.
+    fn head(&mut self) -> Result<(u8, u64), Error> {
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let info = initial & 0x1F;
+        if major == 7 && info >= 24 {
+            return Err(Error::Unsupported("float or simple value"));
+        }
+        let (n, min) = match info {
+            0..=23 => return Ok((major, u64::from(info))),
+            24 => (self.uint(1)?, 24),
+            25 => (self.uint(2)?, 0x100),
+            26 => (self.uint(4)?, 0x1_0000),
+            27 => (self.uint(8)?, 0x1_0000_0000),
+            31 => return Err(Error::NotCanonical("indefinite length item")),
+            _ => return Err(Error::Unsupported("reserved additional info")),
+        };
+        if n < min {
+            return Err(Error::NotCanonical("integer not minimally encoded"));
+        }
+        Ok((major, n))
+    }
+
+    fn length(&mut self, n: u64) -> Result<usize, Error> {
+        // Anything longer than the input can't possibly be valid, and this
+        // keeps us from allocating huge buffers on malicious input.
+        if n > (self.input.len() - self.pos) as u64 {
+            return Err(Error::UnexpectedEof);
+        }
+        Ok(n as usize)
+    }
+
+    fn text(&mut self, n: u64) -> Result<String, Error> {
+        let len = self.length(n)?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidUtf8)
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.depth += 1;
+        let v = self.item();
+        self.depth -= 1;
+        v
+    }
+
+    fn item(&mut self) -> Result<Value, Error> {
+        let (major, n) = self.head()?;
+        match major {
+            0 => Ok(Value::Integer(i128::from(n))),
+            1 => Ok(Value::Integer(-1 - i128::from(n))),
+            2 => {
+                let len = self.length(n)?;
+                Ok(Value::Bytes(self.take(len)?.to_vec()))
+            }
+            3 => Ok(Value::Text(self.text(n)?)),
+            4 => {
+                let len = self.length(n)?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(self.value()?);
+                }
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let len = self.length(n)?;
+                let mut map = BTreeMap::new();
+                let mut last: Option<String> = None;
+                for _ in 0..len {
+                    let (kmajor, kn) = self.head()?;
+                    if kmajor != 3 {
+                        return Err(Error::Unsupported("map key that is not a string"));
+                    }
+                    let key = self.text(kn)?;
+                    if let Some(ref prev) = last {
+                        if key_order(prev, &key) != ::std::cmp::Ordering::Less {
+                            return Err(Error::NotCanonical("map keys out of order or duplicated"));
+                        }
+                    }
+                    let value = self.value()?;
+                    map.insert(key.clone(), value);
+                    last = Some(key);
+                }
+                Ok(Value::Map(map))
+            }
+            6 => {
+                if n != CID_TAG {
+                    return Err(Error::Unsupported("tag other than 42"));
+                }
+                match self.value()? {
+                    Value::Bytes(ref b) if !b.is_empty() && b[0] == 0 => {
+                        let cid = Cid::from(&b[1..]).map_err(|_| Error::InvalidCid)?;
+                        Ok(Value::Link(cid))
+                    }
+                    _ => Err(Error::InvalidCid),
+                }
+            }
+            _ => match n {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 => Ok(Value::Null),
+                _ => Err(Error::Unsupported("float or simple value")),
+            },
+        }
+    }
+}
+
+// Serializing into a Value
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeVariant<SerializeVec>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::Integer(i128::from(v)))
+    }
+    fn serialize_f32(self, _v: f32) -> Result<Value, Error> {
+        Err(Error::Unsupported("float"))
+    }
+    fn serialize_f64(self, _v: f64) -> Result<Value, Error> {
+        Err(Error::Unsupported("float"))
+    }
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Text(v.to_string()))
+    }
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::Text(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str)
+                              -> Result<Value, Error> {
+        Ok(Value::Text(variant.to_string()))
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, name: &'static str, value: &T)
+                                                      -> Result<Value, Error> {
+        let inner = value.serialize(ValueSerializer)?;
+        if name == serde_cid::TOKEN {
+            // Links get their own CBOR tag rather than being strings.
+            if let Value::Text(ref s) = inner {
+                return Cid::from(s.as_str()).map(Value::Link).map_err(|_| Error::InvalidCid);
+            }
+            return Err(Error::InvalidCid);
+        }
+        Ok(inner)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _name: &'static str, _index: u32,
+                                                       variant: &'static str, value: &T)
+                                                       -> Result<Value, Error> {
+        let mut map = BTreeMap::new();
+        map.insert(variant.to_string(), value.serialize(ValueSerializer)?);
+        Ok(Value::Map(map))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, Error> {
+        Ok(SerializeVec(Vec::with_capacity(len.unwrap_or(0))))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+                               len: usize) -> Result<SerializeVariant<SerializeVec>, Error> {
+        Ok(SerializeVariant(variant, SerializeVec(Vec::with_capacity(len))))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap(BTreeMap::new(), None))
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<SerializeMap, Error> {
+        self.serialize_map(None)
+    }
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+                                _len: usize) -> Result<SerializeVariant<SerializeMap>, Error> {
+        Ok(SerializeVariant(variant, SerializeMap(BTreeMap::new(), None)))
+    }
+}
+
+struct SerializeVec(Vec<Value>);
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.0.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// A map under construction, plus the key we're waiting on a value for.
+struct SerializeMap(BTreeMap<String, Value>, Option<String>);
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(s) => {
+                self.1 = Some(s);
+                Ok(())
+            }
+            _ => Err(Error::Unsupported("map key that is not a string")),
+        }
+    }
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.1.take().expect("serialize_value() called before serialize_key()");
+        let value = value.serialize(ValueSerializer)?;
+        if self.0.insert(key, value).is_some() {
+            return Err(Error::NotCanonical("duplicate map key"));
+        }
+        Ok(())
+    }
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Map(self.0))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T)
+                                              -> Result<(), Error> {
+        ser::SerializeMap::serialize_entry(self, key, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Enum variants with contents become a single-entry map of
+/// `{ variant: contents }`, same as serde_json does it.
+struct SerializeVariant<T>(&'static str, T);
+
+impl<T> SerializeVariant<T> {
+    fn wrap(variant: &'static str, value: Value) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert(variant.to_string(), value);
+        Value::Map(map)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeVec> {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        ser::SerializeSeq::serialize_element(&mut self.1, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        let inner = ser::SerializeSeq::end(self.1)?;
+        Ok(Self::wrap(self.0, inner))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T)
+                                              -> Result<(), Error> {
+        ser::SerializeMap::serialize_entry(&mut self.1, key, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        let inner = ser::SerializeMap::end(self.1)?;
+        Ok(Self::wrap(self.0, inner))
+    }
+}
+
+// Deserializing from a Value
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Integer(i) if i >= 0 && i <= i128::from(u64::MAX) =>
+                visitor.visit_u64(i as u64),
+            Value::Integer(i) if i >= i128::from(i64::MIN) => visitor.visit_i64(i as i64),
+            Value::Integer(i) => visitor.visit_i128(i),
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            Value::Text(s) => visitor.visit_string(s),
+            Value::Array(items) => {
+                let mut seq = de::value::SeqDeserializer::new(items.into_iter());
+                let v = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(v)
+            }
+            Value::Map(map) => {
+                let mut m = de::value::MapDeserializer::new(map.into_iter());
+                let v = visitor.visit_map(&mut m)?;
+                m.end()?;
+                Ok(v)
+            }
+            Value::Link(cid) => visitor.visit_byte_buf(cid.to_bytes()),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+                                                   -> Result<V::Value, Error> {
+        match self {
+            Value::Link(cid) => visitor.visit_byte_buf(cid.to_bytes()),
+            other => visitor.visit_newtype_struct(other),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str,
+                                         _variants: &'static [&'static str], visitor: V)
+                                         -> Result<V::Value, Error> {
+        match self {
+            Value::Text(variant) => visitor.visit_enum(EnumDeserializer(variant, None)),
+            Value::Map(map) => {
+                if map.len() != 1 {
+                    return Err(de::Error::invalid_length(map.len(), &"a map with one entry"));
+                }
+                let (variant, value) = map.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer(variant, Some(value)))
+            }
+            _ => Err(de::Error::custom("expected an enum variant")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct EnumDeserializer(String, Option<Value>);
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, VariantDeserializer), Error>
+        where V: de::DeserializeSeed<'de>
+    {
+        let variant: de::value::StringDeserializer<Error> = self.0.into_deserializer();
+        let v = seed.deserialize(variant)?;
+        Ok((v, VariantDeserializer(self.1)))
+    }
+}
+
+struct VariantDeserializer(Option<Value>);
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.0 {
+            None => Ok(()),
+            Some(_) => Err(de::Error::custom("expected a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+        where T: de::DeserializeSeed<'de>
+    {
+        match self.0 {
+            Some(v) => seed.deserialize(v),
+            None => Err(de::Error::custom("expected a newtype variant")),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.0 {
+            Some(v @ Value::Array(_)) => de::Deserializer::deserialize_any(v, visitor),
+            _ => Err(de::Error::custom("expected a tuple variant")),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V)
+                                       -> Result<V::Value, Error> {
+        match self.0 {
+            Some(v @ Value::Map(_)) => de::Deserializer::deserialize_any(v, visitor),
+            _ => Err(de::Error::custom("expected a struct variant")),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use document::tests::{cid, CID_A};
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn roundtrip(v: Value) {
+        let mut out = Vec::new();
+        encode(&v, &mut out);
+        assert_eq!(decode(&out).unwrap(), v);
+    }
+
+    #[test]
+    fn test_integers() {
+        let cases: &[(i128, &str)] = &[
+            (0, "00"),
+            (23, "17"),
+            (24, "1818"),
+            (255, "18ff"),
+            (256, "190100"),
+            (65536, "1a00010000"),
+            (4294967296, "1b0000000100000000"),
+            (-1, "20"),
+            (-25, "3818"),
+            (-18446744073709551616, "3bffffffffffffffff"),
+        ];
+        for &(i, expected) in cases {
+            let mut out = Vec::new();
+            encode(&Value::Integer(i), &mut out);
+            assert_eq!(hex(&out), expected);
+            roundtrip(Value::Integer(i));
+        }
+    }
+
+    #[test]
+    fn test_map_key_order() {
+        let mut map = BTreeMap::new();
+        map.insert("bb".to_string(), Value::Null);
+        map.insert("a".to_string(), Value::Bool(true));
+        map.insert("aa".to_string(), Value::Bool(false));
+        let mut out = Vec::new();
+        encode(&Value::Map(map.clone()), &mut out);
+        // a, aa, bb
+        assert_eq!(hex(&out), "a36161f5626161f4626262f6");
+        roundtrip(Value::Map(map));
+    }
+
+    #[test]
+    fn test_link() {
+        let v = Value::Link(cid(CID_A));
+        let mut out = Vec::new();
+        encode(&v, &mut out);
+        assert_eq!(&out[..4], &[0xD8, 0x2A, 0x58, 0x23][..]);
+        assert_eq!(out[4], 0);
+        roundtrip(v);
+    }
+
+    #[test]
+    fn test_reject_noncanonical() {
+        // 1 encoded in two bytes
+        assert_eq!(decode(&[0x18, 0x01]), Err(Error::NotCanonical("integer not minimally encoded")));
+        // Keys in the wrong order
+        let bad = [0xA2, 0x62, 0x61, 0x61, 0xF6, 0x61, 0x62, 0xF6];
+        assert!(decode(&bad).is_err());
+        // Indefinite-length array
+        assert!(decode(&[0x9F, 0xFF]).is_err());
+        // Float
+        assert!(decode(&[0xF9, 0x3C, 0x00]).is_err());
+        // Trailing junk
+        assert_eq!(decode(&[0xF6, 0xF6]), Err(Error::TrailingBytes));
+        // Length longer than input
+        assert_eq!(decode(&[0x5A, 0xFF, 0xFF, 0xFF, 0xFF]), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let mut nested = vec![0x81; MAX_DEPTH - 1];
+        nested.push(0x00);
+        assert!(decode(&nested).is_ok());
+        nested.insert(0, 0x81);
+        assert_eq!(decode(&nested), Err(Error::TooDeep));
+
+        let mut deep = vec![0x81; 1_000_000];
+        deep.push(0x00);
+        assert_eq!(decode(&deep), Err(Error::TooDeep));
+    }
+
+    #[test]
+    fn test_from_block() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Thing {
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            name: Option<String>,
+        }
+        let empty = Value::Map(BTreeMap::new());
+        let mut out = Vec::new();
+        encode(&empty, &mut out);
+        assert_eq!(from_block::<Thing>(&out), Ok(Thing { name: None }));
+
+        let mut map = BTreeMap::new();
+        map.insert("name".to_string(), Value::Null);
+        let mut out = Vec::new();
+        encode(&Value::Map(map.clone()), &mut out);
+        assert!(from_slice::<Thing>(&out).is_ok());
+        assert!(from_block::<Thing>(&out).is_err());
+
+        map.insert("name".to_string(), Value::Text("x".to_string()));
+        map.insert("colour".to_string(), Value::Text("red".to_string()));
+        let mut out = Vec::new();
+        encode(&Value::Map(map), &mut out);
+        assert!(from_block::<Thing>(&out).is_err());
+    }
+
+    #[test]
+    fn test_no_floats() {
+        assert_eq!(to_vec(&1.5f64), Err(Error::Unsupported("float")));
+    }
+}
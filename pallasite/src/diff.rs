@@ -0,0 +1,694 @@
+//! Differences between two revisions of a document.
+//!
+//! Parts and segments are lined up with a longest common subsequence.
+//! Where a paragraph changed into another paragraph (or a section into
+//! a section of the same level, and so on), we look inside it instead
+//! of replacing it wholesale, down to individual words of text.
+
+use std::cmp;
+
+use document::{Document, Element, Elements, Encoding, Part, Segment};
+use path::{Path, Step};
+
+/// A piece of a run of elements, as far as word diffs are concerned.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Token {
+    /// A word, or the whitespace between words.
+    Text(String),
+    /// Anything that isn't plain text, compared as a whole.
+    Element(Element),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WordEdit {
+    Keep(Token),
+    Insert(Token),
+    Delete(Token),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    Part(Part),
+    Segment(Segment),
+}
+
+/// One step of turning the old document into the new one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Edit {
+    /// A metadata field such as `title` changed.
+    Field {
+        name: &'static str,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// Something is only in the old document.  `path` points into the
+    /// old document.
+    Delete { path: Path, node: Node },
+    /// Something is only in the new document.  `path` points into the
+    /// new document.
+    Insert { path: Path, node: Node },
+    /// The text of a paragraph or heading changed.
+    Change {
+        old: Path,
+        new: Path,
+        words: Vec<WordEdit>,
+    },
+}
+
+/// What it takes to turn `old` into `new`.  Empty if they have the
+/// same contents and metadata.
+pub fn diff(old: &Document, new: &Document) -> Vec<Edit> {
+    let mut d = Differ { edits: Vec::new() };
+    d.fields(old, new);
+    d.parts(&old.contents, &new.contents);
+    d.edits
+}
+
+/// The new document, with everything that was added marked as
+/// `Element::Insertion` and everything that was removed put back in and
+/// marked as `Element::Deletion`.  Removed code blocks turn into
+/// preformatted paragraphs, since they can't hold markup.
+pub fn redline(old: &Document, new: &Document) -> Document {
+    let mut d = Differ { edits: Vec::new() };
+    let contents = d.parts(&old.contents, &new.contents);
+    Document {
+        contents,
+        ..new.clone()
+    }
+}
+
+/// Splits a run of elements into words, whitespace and whole elements.
+pub fn tokenize(elements: &[Element]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for e in elements {
+        match *e {
+            Element::Text(ref s) => {
+                let mut start = 0;
+                let mut in_space = None;
+                for (i, c) in s.char_indices() {
+                    let space = c.is_whitespace();
+                    if in_space.is_some() && in_space != Some(space) {
+                        tokens.push(Token::Text(s[start..i].to_string()));
+                        start = i;
+                    }
+                    in_space = Some(space);
+                }
+                if start < s.len() {
+                    tokens.push(Token::Text(s[start..].to_string()));
+                }
+            }
+            ref other => tokens.push(Token::Element(other.clone())),
+        }
+    }
+    tokens
+}
+
+/// How two lists line up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Align {
+    Both(usize, usize),
+    Old(usize),
+    New(usize),
+}
+
+/// Longest common subsequence of `old` and `new`.  When there's a
+/// choice, deletions come before insertions.
+pub(crate) fn align<T, F>(old: &[T], new: &[T], eq: F) -> Vec<Align>
+    where F: Fn(&T, &T) -> bool
+{
+    // Trimming off the common ends first keeps the table small for the
+    // usual case of a few changes in a long document.
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| eq(a, b)).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| eq(a, b))
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // lengths[i * w + j] is the length of the LCS of a[i..] and b[j..]
+    let w = b.len() + 1;
+    let mut lengths = vec![0usize; (a.len() + 1) * w];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i * w + j] = if eq(&a[i], &b[j]) {
+                lengths[(i + 1) * w + j + 1] + 1
+            } else {
+                cmp::max(lengths[(i + 1) * w + j], lengths[i * w + j + 1])
+            };
+        }
+    }
+
+    let mut out: Vec<Align> = (0..prefix).map(|i| Align::Both(i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if eq(&a[i], &b[j]) {
+            out.push(Align::Both(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * w + j] >= lengths[i * w + j + 1] {
+            out.push(Align::Old(prefix + i));
+            i += 1;
+        } else {
+            out.push(Align::New(prefix + j));
+            j += 1;
+        }
+    }
+    out.extend((i..a.len()).map(|i| Align::Old(prefix + i)));
+    out.extend((j..b.len()).map(|j| Align::New(prefix + j)));
+    out.extend((0..suffix).map(|k| Align::Both(old.len() - suffix + k, new.len() - suffix + k)));
+    out
+}
+
+enum Chunk {
+    /// The index in the new list of something in both.
+    Same(usize),
+    /// Old indices removed and new indices added between two `Same`s.
+    Changed(Vec<usize>, Vec<usize>),
+}
+
+fn chunks(alignment: &[Align]) -> Vec<Chunk> {
+    let mut out = Vec::new();
+    let mut old = Vec::new();
+    let mut new = Vec::new();
+    for a in alignment {
+        match *a {
+            Align::Both(_, j) => {
+                if !old.is_empty() || !new.is_empty() {
+                    out.push(Chunk::Changed(old, new));
+                    old = Vec::new();
+                    new = Vec::new();
+                }
+                out.push(Chunk::Same(j));
+            }
+            Align::Old(i) => old.push(i),
+            Align::New(j) => new.push(j),
+        }
+    }
+    if !old.is_empty() || !new.is_empty() {
+        out.push(Chunk::Changed(old, new));
+    }
+    out
+}
+
+/// Whether it makes sense to diff the insides of two parts rather than
+/// replacing one with the other.
+pub(crate) fn parts_match(old: &Part, new: &Part) -> bool {
+    match (old, new) {
+        (Part::Body(_), Part::Body(_)) => true,
+        (Part::Section { level: a, .. }, Part::Section { level: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+pub(crate) fn segments_match(old: &Segment, new: &Segment) -> bool {
+    match (old, new) {
+        (Segment::Para(_), Segment::Para(_)) |
+        (Segment::Abstract(_), Segment::Abstract(_)) => true,
+        (Segment::Quote(a), Segment::Quote(b)) => segments_match(a, b),
+        (Segment::List { type_: a, .. }, Segment::List { type_: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+fn wrap(elements: Elements, mark: fn(Elements) -> Element) -> Elements {
+    if elements.is_empty() {
+        elements
+    } else {
+        vec![mark(elements)]
+    }
+}
+
+/// Marks everything in a part as inserted or deleted.
+fn mark_part(part: Part, mark: fn(Elements) -> Element) -> Part {
+    match part {
+        Part::Body(segments) => {
+            Part::Body(segments.into_iter().map(|s| mark_segment(s, mark)).collect())
+        }
+        Part::Section { level, heading, contents } => Part::Section {
+            level,
+            heading: wrap(heading, mark),
+            contents: contents.into_iter().map(|s| mark_segment(s, mark)).collect(),
+        },
+    }
+}
+
+fn mark_segment(segment: Segment, mark: fn(Elements) -> Element) -> Segment {
+    let mark_all = |segments: Vec<Segment>| -> Vec<Segment> {
+        segments.into_iter().map(|s| mark_segment(s, mark)).collect()
+    };
+    match segment {
+        Segment::Para(e) => Segment::Para(wrap(e, mark)),
+        Segment::Abstract(e) => Segment::Abstract(wrap(e, mark)),
+        Segment::Table { header, body, footer } => Segment::Table {
+            header: header.into_iter().map(|c| wrap(c, mark)).collect(),
+            body: body.into_iter().map(&mark_all).collect(),
+            footer: footer.into_iter().map(|c| wrap(c, mark)).collect(),
+        },
+        Segment::Figure { caption, source, media_type, alt, dimensions, size } => Segment::Figure {
+            caption: caption.into_iter().map(|c| wrap(c, mark)).collect(),
+            source,
+            media_type,
+            alt,
+            dimensions,
+            size,
+        },
+        Segment::List { type_, elements } => Segment::List {
+            type_,
+            elements: mark_all(elements),
+        },
+        Segment::Code { contents, .. } => {
+            let code = Element::Preformatted(vec![Element::Text(contents)]);
+            Segment::Para(vec![mark(vec![code])])
+        }
+        Segment::Math(tex) => Segment::Para(vec![mark(vec![Element::Math(tex)])]),
+        Segment::Transclusion { source, fragment } => {
+            let link = Element::Xref { contents: vec![Element::Text(source.to_string())], target: source, fragment };
+            Segment::Para(vec![mark(vec![link])])
+        }
+        Segment::Quote(s) => Segment::Quote(Box::new(mark_segment(*s, mark))),
+        Segment::Conflict { ours, theirs } => Segment::Conflict {
+            ours: mark_all(ours),
+            theirs: mark_all(theirs),
+        },
+    }
+}
+
+/// Appends a token to a run of elements, merging adjacent text.
+fn push_token(out: &mut Elements, token: Token) {
+    match token {
+        Token::Text(s) => {
+            if let Some(&mut Element::Text(ref mut last)) = out.last_mut() {
+                last.push_str(&s);
+                return;
+            }
+            out.push(Element::Text(s));
+        }
+        Token::Element(e) => out.push(e),
+    }
+}
+
+/// Every metadata field of a document, as text, in the order they're
+/// declared.
+fn fields(doc: &Document) -> Vec<(&'static str, Option<String>)> {
+    let list = |items: Vec<String>| if items.is_empty() { None } else { Some(items.join(", ")) };
+    let encoding = match doc.character_encoding {
+        Encoding::Utf8 => "utf-8",
+    };
+    let tags = doc.tags.iter().map(|t| match t.vocabulary {
+        Some(ref v) => format!("{} ({})", t.term, v),
+        None => t.term.clone(),
+    }).collect();
+    vec![
+        ("character_encoding", Some(encoding.to_string())),
+        ("title", doc.title.clone()),
+        ("date", doc.date.map(|d| d.to_rfc3339())),
+        ("local_date", doc.local_date.map(|o| o.to_string())),
+        ("author", doc.author.clone()),
+        ("author_id", doc.author_id.as_ref().map(|i| i.to_string())),
+        ("previous_revisions", doc.previous_revisions.as_ref().map(|r| r.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(", "))),
+        ("subject", doc.subject.clone()),
+        ("tags", list(tags)),
+        ("in_response_to", doc.in_response_to.as_ref().map(|c| c.to_string())),
+        ("reply_to", doc.reply_to.as_ref().map(|i| i.to_string())),
+        ("language", doc.language.as_ref().map(|l| l.to_string())),
+    ]
+}
+
+struct Differ {
+    edits: Vec<Edit>,
+}
+
+impl Differ {
+    fn fields(&mut self, old: &Document, new: &Document) {
+        for ((name, old), (_, new)) in fields(old).into_iter().zip(fields(new)) {
+            if old != new {
+                self.edits.push(Edit::Field { name, old, new });
+            }
+        }
+    }
+
+    fn parts(&mut self, old: &[Part], new: &[Part]) -> Vec<Part> {
+        let mut out = Vec::new();
+        for chunk in chunks(&align(old, new, |a, b| a == b)) {
+            match chunk {
+                Chunk::Same(j) => out.push(new[j].clone()),
+                Chunk::Changed(dels, ins) => {
+                    for k in 0..cmp::max(dels.len(), ins.len()) {
+                        match (dels.get(k), ins.get(k)) {
+                            (Some(&i), Some(&j)) if parts_match(&old[i], &new[j]) => {
+                                let part = self.part(Path(vec![Step::Part(i)]),
+                                                     Path(vec![Step::Part(j)]),
+                                                     &old[i], &new[j]);
+                                out.push(part);
+                            }
+                            (del, ins) => {
+                                if let Some(&i) = del {
+                                    self.edits.push(Edit::Delete {
+                                        path: Path(vec![Step::Part(i)]),
+                                        node: Node::Part(old[i].clone()),
+                                    });
+                                    out.push(mark_part(old[i].clone(), Element::Deletion));
+                                }
+                                if let Some(&j) = ins {
+                                    self.edits.push(Edit::Insert {
+                                        path: Path(vec![Step::Part(j)]),
+                                        node: Node::Part(new[j].clone()),
+                                    });
+                                    out.push(mark_part(new[j].clone(), Element::Insertion));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn part(&mut self, old_path: Path, new_path: Path, old: &Part, new: &Part) -> Part {
+        match (old, new) {
+            (Part::Body(a), Part::Body(b)) => {
+                Part::Body(self.segments(&old_path, &new_path, a, b))
+            }
+            (Part::Section { heading: old_heading, contents: a, .. },
+             Part::Section { level, heading: new_heading, contents: b }) => {
+                let heading = self.words(old_path.join(Step::Heading),
+                                         new_path.join(Step::Heading),
+                                         old_heading, new_heading);
+                Part::Section {
+                    level: *level,
+                    heading,
+                    contents: self.segments(&old_path, &new_path, a, b),
+                }
+            }
+            _ => unreachable!("parts_match() said these parts could be compared"),
+        }
+    }
+
+    fn segments(&mut self, old_path: &Path, new_path: &Path, old: &[Segment], new: &[Segment])
+        -> Vec<Segment>
+    {
+        let mut out = Vec::new();
+        for chunk in chunks(&align(old, new, |a, b| a == b)) {
+            match chunk {
+                Chunk::Same(j) => out.push(new[j].clone()),
+                Chunk::Changed(dels, ins) => {
+                    for k in 0..cmp::max(dels.len(), ins.len()) {
+                        match (dels.get(k), ins.get(k)) {
+                            (Some(&i), Some(&j)) if segments_match(&old[i], &new[j]) => {
+                                let segment = self.segment(old_path.join(Step::Segment(i)),
+                                                           new_path.join(Step::Segment(j)),
+                                                           &old[i], &new[j]);
+                                out.push(segment);
+                            }
+                            (del, ins) => {
+                                if let Some(&i) = del {
+                                    self.edits.push(Edit::Delete {
+                                        path: old_path.join(Step::Segment(i)),
+                                        node: Node::Segment(old[i].clone()),
+                                    });
+                                    out.push(mark_segment(old[i].clone(), Element::Deletion));
+                                }
+                                if let Some(&j) = ins {
+                                    self.edits.push(Edit::Insert {
+                                        path: new_path.join(Step::Segment(j)),
+                                        node: Node::Segment(new[j].clone()),
+                                    });
+                                    out.push(mark_segment(new[j].clone(), Element::Insertion));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn segment(&mut self, old_path: Path, new_path: Path, old: &Segment, new: &Segment) -> Segment {
+        match (old, new) {
+            (Segment::Para(a), Segment::Para(b)) => {
+                Segment::Para(self.words(old_path, new_path, a, b))
+            }
+            (Segment::Abstract(a), Segment::Abstract(b)) => {
+                Segment::Abstract(self.words(old_path, new_path, a, b))
+            }
+            (Segment::Quote(a), Segment::Quote(b)) => {
+                let s = self.segment(old_path.join(Step::Quote), new_path.join(Step::Quote), a, b);
+                Segment::Quote(Box::new(s))
+            }
+            (Segment::List { elements: a, .. }, Segment::List { type_, elements: b }) => {
+                Segment::List {
+                    type_: type_.clone(),
+                    elements: self.segments(&old_path, &new_path, a, b),
+                }
+            }
+            _ => unreachable!("segments_match() said these segments could be compared"),
+        }
+    }
+
+    fn words(&mut self, old_path: Path, new_path: Path, old: &[Element], new: &[Element]) -> Elements {
+        if old == new {
+            return new.to_vec();
+        }
+        let a = tokenize(old);
+        let b = tokenize(new);
+        let words: Vec<WordEdit> = align(&a, &b, |x, y| x == y).into_iter().map(|al| match al {
+            Align::Both(_, j) => WordEdit::Keep(b[j].clone()),
+            Align::Old(i) => WordEdit::Delete(a[i].clone()),
+            Align::New(j) => WordEdit::Insert(b[j].clone()),
+        }).collect();
+
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            match words[i] {
+                WordEdit::Keep(ref t) => {
+                    push_token(&mut out, t.clone());
+                    i += 1;
+                }
+                WordEdit::Delete(_) | WordEdit::Insert(_) => {
+                    let deleting = matches!(words[i], WordEdit::Delete(_));
+                    let mut run = Vec::new();
+                    while let Some(w) = words.get(i) {
+                        match (w, deleting) {
+                            (&WordEdit::Delete(ref t), true) | (&WordEdit::Insert(ref t), false) => {
+                                push_token(&mut run, t.clone())
+                            }
+                            _ => break,
+                        }
+                        i += 1;
+                    }
+                    out.push(if deleting { Element::Deletion(run) } else { Element::Insertion(run) });
+                }
+            }
+        }
+        self.edits.push(Edit::Change { old: old_path, new: new_path, words });
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use document::tests::{cid, kitchen_sink, text, CID_A, CID_B};
+    use document::*;
+    use identity::Identity;
+    use path::{Path, Step};
+    use super::*;
+
+    fn para(s: &str) -> Segment {
+        Segment::Para(vec![text(s)])
+    }
+
+    fn word(s: &str) -> Token {
+        Token::Text(s.to_string())
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize(&[text("two  words "), Element::Anchor("a".to_string()), text("x")]),
+                   vec![word("two"), word("  "), word("words"), word(" "),
+                        Token::Element(Element::Anchor("a".to_string())), word("x")]);
+        assert_eq!(tokenize(&[text("")]), vec![]);
+    }
+
+    #[test]
+    fn test_same() {
+        let doc = kitchen_sink();
+        assert_eq!(diff(&doc, &doc), vec![]);
+        assert_eq!(redline(&doc, &doc), doc);
+    }
+
+    #[test]
+    fn test_word_diff() {
+        let old = Document::new(vec![Part::Body(vec![para("intro"), para("the quick brown fox")])]);
+        let new = Document::new(vec![Part::Body(vec![para("intro"), para("the slow brown fox jumps")])]);
+        let path = Path(vec![Step::Part(0), Step::Segment(1)]);
+        assert_eq!(diff(&old, &new), vec![Edit::Change {
+            old: path.clone(),
+            new: path.clone(),
+            words: vec![
+                WordEdit::Keep(word("the")),
+                WordEdit::Keep(word(" ")),
+                WordEdit::Delete(word("quick")),
+                WordEdit::Insert(word("slow")),
+                WordEdit::Keep(word(" ")),
+                WordEdit::Keep(word("brown")),
+                WordEdit::Keep(word(" ")),
+                WordEdit::Keep(word("fox")),
+                WordEdit::Insert(word(" ")),
+                WordEdit::Insert(word("jumps")),
+            ],
+        }]);
+        assert_eq!(redline(&old, &new).contents, vec![Part::Body(vec![
+            para("intro"),
+            Segment::Para(vec![
+                text("the "),
+                Element::Deletion(vec![text("quick")]),
+                Element::Insertion(vec![text("slow")]),
+                text(" brown fox"),
+                Element::Insertion(vec![text(" jumps")]),
+            ]),
+        ])]);
+    }
+
+    #[test]
+    fn test_segments() {
+        let code = Segment::Code { language: None, contents: "x = 1".to_string() };
+        let old = Document::new(vec![Part::Body(vec![para("a"), code.clone(), para("c")])]);
+        let new = Document::new(vec![Part::Body(vec![para("a"), para("c"), para("d")])]);
+        assert_eq!(diff(&old, &new), vec![
+            Edit::Delete {
+                path: Path(vec![Step::Part(0), Step::Segment(1)]),
+                node: Node::Segment(code),
+            },
+            Edit::Insert {
+                path: Path(vec![Step::Part(0), Step::Segment(2)]),
+                node: Node::Segment(para("d")),
+            },
+        ]);
+        assert_eq!(redline(&old, &new).contents, vec![Part::Body(vec![
+            para("a"),
+            Segment::Para(vec![Element::Deletion(vec![
+                Element::Preformatted(vec![text("x = 1")]),
+            ])]),
+            para("c"),
+            Segment::Para(vec![Element::Insertion(vec![text("d")])]),
+        ])]);
+    }
+
+    #[test]
+    fn test_sections() {
+        let section = |level, heading: &str, contents| Part::Section {
+            level,
+            heading: vec![text(heading)],
+            contents,
+        };
+        let mut old = Document::new(vec![
+            section(1, "Old title", vec![para("same")]),
+            section(2, "Gone", vec![]),
+        ]);
+        old.title = Some("Draft".to_string());
+        let mut new = Document::new(vec![
+            section(1, "New title", vec![para("same")]),
+            section(3, "Gone", vec![]),
+        ]);
+        new.title = Some("Final".to_string());
+
+        let edits = diff(&old, &new);
+        assert_eq!(edits.len(), 4);
+        assert_eq!(edits[0], Edit::Field {
+            name: "title",
+            old: Some("Draft".to_string()),
+            new: Some("Final".to_string()),
+        });
+        match edits[1] {
+            Edit::Change { ref old, ref new, .. } => {
+                assert_eq!(old.to_string(), "part[0]/heading");
+                assert_eq!(new.to_string(), "part[0]/heading");
+            }
+            ref other => panic!("expected a change, got {:?}", other),
+        }
+        // Sections of different levels aren't compared, just replaced.
+        assert_eq!(edits[2], Edit::Delete {
+            path: Path(vec![Step::Part(1)]),
+            node: Node::Part(section(2, "Gone", vec![])),
+        });
+        assert_eq!(edits[3], Edit::Insert {
+            path: Path(vec![Step::Part(1)]),
+            node: Node::Part(section(3, "Gone", vec![])),
+        });
+
+        let red = redline(&old, &new);
+        assert_eq!(red.title, Some("Final".to_string()));
+        assert_eq!(red.contents[1], Part::Section {
+            level: 2,
+            heading: vec![Element::Deletion(vec![text("Gone")])],
+            contents: vec![],
+        });
+    }
+
+    #[test]
+    fn test_metadata() {
+        let old = Document::new(vec![]);
+        let mut new = old.clone();
+        new.date = Some(Utc.with_ymd_and_hms(2018, 1, 2, 3, 4, 5).unwrap());
+        new.local_date = Some(FixedOffset::east_opt(3600).unwrap());
+        new.author_id = Some(Identity::new("simon", "example.com"));
+        new.previous_revisions = Some(vec![cid(CID_A), cid(CID_B)]);
+        new.tags = vec![Tag::new("a"), Tag::from_vocabulary("b", &cid(CID_A))];
+        new.in_response_to = Some(cid(CID_B));
+        new.reply_to = Some(Identity::new("ada", "example.com"));
+        let changed: Vec<_> = diff(&old, &new).into_iter().map(|e| match e {
+            Edit::Field { name, old: None, new: Some(new) } => (name, new),
+            other => panic!("unexpected edit {:?}", other),
+        }).collect();
+        assert_eq!(changed, vec![
+            ("date", "2018-01-02T03:04:05+00:00".to_string()),
+            ("local_date", "+01:00".to_string()),
+            ("author_id", "simon@example.com".to_string()),
+            ("previous_revisions", format!("{}, {}", CID_A, CID_B)),
+            ("tags", format!("a, b ({})", CID_A)),
+            ("in_response_to", CID_B.to_string()),
+            ("reply_to", "ada@example.com".to_string()),
+        ]);
+
+        // The same term from a different vocabulary is a different tag.
+        let mut a = old.clone();
+        a.tags = vec![Tag::from_vocabulary("a", &cid(CID_A))];
+        let mut b = old.clone();
+        b.tags = vec![Tag::from_vocabulary("a", &cid(CID_B))];
+        assert_eq!(diff(&a, &b).len(), 1);
+        assert_eq!(diff(&new, &new), vec![]);
+    }
+
+    #[test]
+    fn test_nested() {
+        let list = |items: Vec<Segment>| Segment::List { type_: ListType::Bulleted, elements: items };
+        let old = Document::new(vec![Part::Body(vec![
+            Segment::Quote(Box::new(list(vec![para("one"), para("two")]))),
+        ])]);
+        let new = Document::new(vec![Part::Body(vec![
+            Segment::Quote(Box::new(list(vec![para("one"), para("three")]))),
+        ])]);
+        match diff(&old, &new)[..] {
+            [Edit::Change { ref old, .. }] => {
+                assert_eq!(old.to_string(), "part[0]/segment[0]/quote/segment[1]");
+            }
+            ref other => panic!("unexpected edits {:?}", other),
+        }
+        assert_eq!(redline(&old, &new).contents, vec![Part::Body(vec![
+            Segment::Quote(Box::new(list(vec![
+                para("one"),
+                Segment::Para(vec![
+                    Element::Deletion(vec![text("two")]),
+                    Element::Insertion(vec![text("three")]),
+                ]),
+            ]))),
+        ])]);
+    }
+}
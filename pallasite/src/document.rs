@@ -1,43 +1,222 @@
 use chrono::prelude::*;
 use cid::Cid;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error;
+use serde::ser::SerializeStruct;
 
+use cbor;
 use identity::Identity;
+use language::LanguageTag;
+use serde_cid;
+use url::Url;
 
-#[derive(Clone, PartialEq, Eq)]
+/// Version of the serialized form of a `Document`.
+///
+/// Bump this whenever a change means documents that have already been
+/// written would be read differently, or not at all, such as renaming
+/// or removing a field or variant, or changing what one means.  Adding
+/// a new optional field or a new variant doesn't need a bump: every
+/// existing document still reads the same, and older readers reject
+/// documents that use the new things instead of quietly dropping them,
+/// since `from_cbor()` refuses anything it can't re-encode exactly.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Serialized as `{ "version": FORMAT_VERSION, "document": { ... } }`,
+/// so readers can tell what they're looking at before they try to
+/// make sense of the rest of it.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct Document {
-    contents: Vec<Part>,
-    character_encoding: Encoding,
-    title: Option<String>,
-    date: Option<DateTime<Utc>>, // Must be in UTC
-    local_date: Option<FixedOffset>, // Offset of author's timezone from UTC
-    author: Option<String>,
-    author_id: Option<Identity>,
-    previous_revisions: Option<Vec<Cid>>,
-    subject: Option<String>, // Like an email subject... is this the same as "title"?
-    // categories/tags (with vocabularies?)
-    in_response_to: Option<Cid>,
-    reply_to: Option<Identity>,
-    language: Option<String>,
+    pub contents: Vec<Part>,
+    pub character_encoding: Encoding,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<DateTime<Utc>>, // Must be in UTC
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_offset")]
+    pub local_date: Option<FixedOffset>, // Offset of author's timezone from UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<Identity>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_cid::option_vec")]
+    pub previous_revisions: Option<Vec<Cid>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>, // Like an email subject... is this the same as "title"?
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_cid::option")]
+    pub in_response_to: Option<Cid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Identity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<LanguageTag>,
+}
+
+impl Document {
+    /// A UTF-8 document with the given contents and no metadata.
+    pub fn new(contents: Vec<Part>) -> Document {
+        Document {
+            contents,
+            character_encoding: Encoding::Utf8,
+            title: None,
+            date: None,
+            local_date: None,
+            author: None,
+            author_id: None,
+            previous_revisions: None,
+            subject: None,
+            tags: Vec::new(),
+            in_response_to: None,
+            reply_to: None,
+            language: None,
+        }
+    }
+
+    /// The date the document was written, in the author's timezone
+    /// if we know it.
+    pub fn local_date_time(&self) -> Option<DateTime<FixedOffset>> {
+        let offset = self.local_date.unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+        self.date.map(|d| d.with_timezone(&offset))
+    }
+
+    /// The canonical DAG-CBOR encoding of the document, which is what
+    /// gets stored and hashed.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        cbor::to_vec(self).expect("documents contain nothing DAG-CBOR can't represent")
+    }
+
+    /// Decodes a document, insisting that it's exactly what `to_cbor()`
+    /// would give, so its CID is the hash of `bytes`.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Document, cbor::Error> {
+        cbor::from_block(bytes)
+    }
+
+    /// The content address of the document.  The same document always
+    /// has the same CID, no matter who computes it.
+    pub fn cid(&self) -> Cid {
+        cbor::block_cid(&self.to_cbor())
+    }
+}
+
+/// Lets us hand the derived (unversioned) impl to `serialize_field()`.
+struct Unversioned<'a>(&'a Document);
+
+impl<'a> Serialize for Unversioned<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        Document::serialize(self.0, serializer)
+    }
+}
+
+impl Serialize for Document {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("VersionedDocument", 2)?;
+        s.serialize_field("version", &FORMAT_VERSION)?;
+        s.serialize_field("document", &Unversioned(self))?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D>(deserializer: D) -> Result<Document, D::Error>
+        where D: Deserializer<'de>
+    {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u32,
+            #[serde(deserialize_with = "Document::deserialize")]
+            document: Document,
+        }
+        let v = Versioned::deserialize(deserializer)?;
+        if v.version != FORMAT_VERSION {
+            return Err(D::Error::custom(format!("unsupported document format version {}", v.version)));
+        }
+        Ok(v.document)
+    }
+}
+
+/// `FixedOffset` is serialized as its offset east of UTC, in seconds.
+mod serde_offset {
+    use chrono::FixedOffset;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    pub fn serialize<S>(offset: &Option<FixedOffset>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        match *offset {
+            Some(ref o) => serializer.serialize_some(&o.local_minus_utc()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<FixedOffset>, D::Error>
+        where D: Deserializer<'de>
+    {
+        match Option::<i32>::deserialize(deserializer)? {
+            Some(secs) => FixedOffset::east_opt(secs)
+                .map(Some)
+                .ok_or_else(|| D::Error::custom(format!("UTC offset out of range: {}", secs))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A category or keyword a document is filed under.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub term: String,
+    /// The `Vocabulary` the term comes from, if it's not just a free-form
+    /// keyword.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_cid::option")]
+    pub vocabulary: Option<Cid>,
+}
+
+impl Tag {
+    /// A free-form tag, not from any vocabulary.
+    pub fn new(term: &str) -> Tag {
+        Tag {
+            term: term.to_string(),
+            vocabulary: None,
+        }
+    }
+
+    pub fn from_vocabulary(term: &str, vocabulary: &Cid) -> Tag {
+        Tag {
+            term: term.to_string(),
+            vocabulary: Some(vocabulary.clone()),
+        }
+    }
 }
 
 /// Possible character encodings.
 /// Just UTF-8 for now.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Encoding {
+    #[serde(rename = "utf-8")]
     Utf8,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Part {
     Body(Vec<Segment>),
     Section {
         level: u32, 
+        /// The section's title, as in an HTML `<h1>`.  May be empty.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        heading: Elements,
         contents: Vec<Segment>
     },
 }
 
 /// Describe structure of the contained text
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Segment {
     Para(Elements),
     Abstract(Elements),
@@ -48,9 +227,24 @@ pub enum Segment {
     },
     Figure {
         caption: Vec<Elements>,
-        source: Cid, // May get fancier someday
+        #[serde(with = "serde_cid")]
+        source: Cid,
+        /// The MIME type of the source, like `image/png` or `video/webm`.
+        /// Without one, renderers assume it's an image.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        media_type: Option<String>,
+        /// A description for people who can't see the figure.  An empty
+        /// string means it's purely decorative.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        alt: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dimensions: Option<Dimensions>,
+        /// The size of the source, in bytes.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
     },
     List {
+        #[serde(rename = "type")]
         type_: ListType,
         elements: Vec<Segment>,
     },
@@ -59,19 +253,57 @@ pub enum Segment {
         contents: String,
     },
     Quote(Box<Segment>),
+    /// An equation set on its own, written in TeX.  See `math` for
+    /// what can be displayed.
+    Math(String),
+    /// Some or all of another document, included by reference.  See
+    /// `transclude` for how it gets expanded.
+    Transclusion {
+        #[serde(with = "serde_cid")]
+        source: Cid,
+        /// An anchor or section in the source, as for `Element::Xref`.
+        /// Without one the whole document is included.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fragment: Option<String>,
+    },
+    /// Changes from two revisions that a merge couldn't reconcile.
+    /// Someone has to pick one before the document is published.
+    Conflict {
+        ours: Vec<Segment>,
+        theirs: Vec<Segment>,
+    },
+}
+
+/// Size of an image or video, in pixels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ListType {
     Bulleted,
     Numbered,
 }
 
+/// Which way text runs.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Left to right, like English.
+    Ltr,
+    /// Right to left, like Arabic or Hebrew.
+    Rtl,
+}
+
 // #[derive(Clone, PartialEq, Eq)]
 pub type Elements = Vec<Element>;
 
 /// Describe properties of the contained text
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Element {
     Text(String),
     Strong(Elements),
@@ -79,17 +311,65 @@ pub enum Element {
     Footnote(Elements),
     Xref {
         contents: Elements, 
+        #[serde(with = "serde_cid")]
         target: Cid,
+        /// The name of an `Element::Anchor` in the target document, like
+        /// the part of a URL after the `#`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fragment: Option<String>,
+    },
+    /// A link to something outside the document web, like a web page.
+    Link {
+        contents: Elements,
+        url: Url,
     },
     Subscript(Elements),
     Superscript(Elements),
     Insertion(Elements),
     Deletion(Elements),
     Preformatted(Elements),
+    /// Text in a different language or direction from the text around
+    /// it, like a quotation in French.
+    Span {
+        contents: Elements,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lang: Option<LanguageTag>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dir: Option<Direction>,
+    },
+    /// An equation in the middle of some text, written in TeX.
+    Math(String),
     Comment(String),
     Anchor(String),
 }
 
+/// The text of some elements with all the markup stripped off, for
+/// places like alt text that can't hold anything fancier.
+/// Footnotes and comments are left out.
+pub fn plain_text(elements: &[Element]) -> String {
+    fn go(elements: &[Element], out: &mut String) {
+        for e in elements {
+            match *e {
+                Element::Text(ref s) | Element::Math(ref s) => out.push_str(s),
+                Element::Strong(ref e) |
+                Element::Emphasized(ref e) |
+                Element::Xref { contents: ref e, .. } |
+                Element::Link { contents: ref e, .. } |
+                Element::Span { contents: ref e, .. } |
+                Element::Subscript(ref e) |
+                Element::Superscript(ref e) |
+                Element::Insertion(ref e) |
+                Element::Deletion(ref e) |
+                Element::Preformatted(ref e) => go(e, out),
+                Element::Footnote(_) | Element::Comment(_) | Element::Anchor(_) => (),
+            }
+        }
+    }
+    let mut s = String::new();
+    go(elements, &mut s);
+    s
+}
+
 
 
 /*
@@ -100,4 +380,360 @@ struct UpdateRequest {
     new_target: CID,
     signature: Signature,
 }
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+pub mod tests {
+    extern crate serde_json;
+
+    use chrono::prelude::*;
+    use cid::Cid;
+
+    use identity::Identity;
+    use super::*;
+
+    pub const CID_A: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+    pub const CID_B: &str = "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o";
+
+    pub fn cid(s: &str) -> Cid {
+        Cid::from(s).unwrap()
+    }
+
+    pub fn text(s: &str) -> Element {
+        Element::Text(s.to_string())
+    }
+
+    /// A document that uses every variant the tree had when the CID
+    /// vector for it was fixed.  Ones added since, like `Link`, `Span`,
+    /// `Math`, `Transclusion` and `Conflict`, aren't in it, since that
+    /// would change its CID; they have round-trip tests of their own.
+    pub fn kitchen_sink() -> Document {
+        let para = Segment::Para(vec![
+            text("plain "),
+            Element::Strong(vec![text("strong")]),
+            Element::Emphasized(vec![text("emphasized")]),
+            Element::Footnote(vec![text("a footnote")]),
+            Element::Xref {
+                contents: vec![text("elsewhere")],
+                target: cid(CID_A),
+                fragment: None,
+            },
+            Element::Subscript(vec![text("2")]),
+            Element::Superscript(vec![text("3")]),
+            Element::Insertion(vec![text("added")]),
+            Element::Deletion(vec![text("removed")]),
+            Element::Preformatted(vec![text("  spaced  ")]),
+            Element::Comment("not rendered".to_string()),
+            Element::Anchor("here".to_string()),
+        ]);
+        let body = vec![
+            Segment::Abstract(vec![text("In which things happen.")]),
+            para,
+            Segment::Table {
+                header: vec![vec![text("a")], vec![text("b")]],
+                body: vec![vec![Segment::Para(vec![text("1")]), Segment::Para(vec![text("2")])]],
+                footer: vec![vec![text("total")], vec![text("3")]],
+            },
+            Segment::Figure {
+                caption: vec![vec![text("A picture")]],
+                source: cid(CID_B),
+                media_type: None,
+                alt: None,
+                dimensions: None,
+                size: None,
+            },
+            Segment::List {
+                type_: ListType::Bulleted,
+                elements: vec![Segment::Para(vec![text("one")])],
+            },
+            Segment::List {
+                type_: ListType::Numbered,
+                elements: vec![Segment::Para(vec![text("two")])],
+            },
+            Segment::Code {
+                language: Some("rust".to_string()),
+                contents: "fn main() {}\n".to_string(),
+            },
+            Segment::Code {
+                language: None,
+                contents: "???".to_string(),
+            },
+            Segment::Quote(Box::new(Segment::Para(vec![text("Quoth the raven")]))),
+        ];
+        let mut doc = Document::new(vec![
+            Part::Body(body),
+            Part::Section {
+                level: 1,
+                heading: vec![],
+                contents: vec![Segment::Para(vec![text("Section text")])],
+            },
+        ]);
+        doc.title = Some("Everything".to_string());
+        doc.date = Some(Utc.with_ymd_and_hms(2017, 11, 5, 18, 30, 0).unwrap());
+        doc.local_date = Some(FixedOffset::west_opt(5 * 3600).unwrap());
+        doc.author = Some("Simon Heath".to_string());
+        doc.author_id = Some(Identity::new("icefox", "alopex.li"));
+        doc.previous_revisions = Some(vec![cid(CID_A), cid(CID_B)]);
+        doc.subject = Some("Testing".to_string());
+        doc.in_response_to = Some(cid(CID_A));
+        doc.reply_to = Some(Identity::new("someone", "example.com"));
+        doc.language = Some(LanguageTag::parse("en").unwrap());
+        doc
+    }
+
+    #[test]
+    fn test_roundtrip_everything() {
+        let doc = kitchen_sink();
+        let json = serde_json::to_string(&doc).unwrap();
+        let doc2: Document = serde_json::from_str(&json).unwrap();
+        assert_eq!(doc, doc2);
+    }
+
+    #[test]
+    fn test_roundtrip_minimal() {
+        let doc = Document::new(vec![]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert_eq!(json, r#"{"version":1,"document":{"contents":[],"character_encoding":"utf-8"}}"#);
+        let doc2: Document = serde_json::from_str(&json).unwrap();
+        assert_eq!(doc, doc2);
+    }
+
+    #[test]
+    fn test_serialized_form() {
+        let mut doc = Document::new(vec![Part::Section {
+            level: 2,
+            heading: vec![],
+            contents: vec![Segment::List {
+                type_: ListType::Numbered,
+                elements: vec![Segment::Para(vec![text("hi")])],
+            }],
+        }]);
+        doc.date = Some(Utc.with_ymd_and_hms(2017, 11, 5, 18, 30, 0).unwrap());
+        doc.local_date = Some(FixedOffset::east_opt(3600).unwrap());
+        doc.in_response_to = Some(cid(CID_A));
+        let value = serde_json::to_value(&doc).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(r#"{
+            "version": 1,
+            "document": {
+                "contents": [{"section": {"level": 2, "contents": [
+                    {"list": {"type": "numbered", "elements": [{"para": [{"text": "hi"}]}]}}
+                ]}}],
+                "character_encoding": "utf-8",
+                "date": "2017-11-05T18:30:00Z",
+                "local_date": 3600,
+                "in_response_to": "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"
+            }
+        }"#).unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn test_section_heading() {
+        let doc = Document::new(vec![Part::Section {
+            level: 1,
+            heading: vec![text("Introduction")],
+            contents: vec![],
+        }]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(json.contains(r#""heading":[{"text":"Introduction"}]"#));
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_reject_unknown_version() {
+        let json = r#"{"version":99,"document":{"contents":[],"character_encoding":"utf-8"}}"#;
+        assert!(serde_json::from_str::<Document>(json).is_err());
+    }
+
+    #[test]
+    fn test_reject_bad_cid() {
+        let json = r#"{"version":1,"document":{"contents":[],"character_encoding":"utf-8",
+                       "in_response_to":"not a cid"}}"#;
+        assert!(serde_json::from_str::<Document>(json).is_err());
+    }
+
+    #[test]
+    fn test_link() {
+        let doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![Element::Link {
+            contents: vec![text("web")],
+            url: Url::parse("https://example.com").unwrap(),
+        }])])]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(json.contains(r#"{"link":{"contents":[{"text":"web"}],"url":"https://example.com"}}"#));
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+
+        let evil = json.replace("https://example.com", "javascript:alert(1)");
+        assert!(serde_json::from_str::<Document>(&evil).is_err());
+    }
+
+    #[test]
+    fn test_span() {
+        let spans = vec![
+            Element::Span {
+                contents: vec![text("مرحبا")],
+                lang: Some(LanguageTag::parse("ar").unwrap()),
+                dir: Some(Direction::Rtl),
+            },
+            Element::Span { contents: vec![text("plain")], lang: None, dir: None },
+        ];
+        assert_eq!(plain_text(&spans), "مرحباplain");
+        let doc = Document::new(vec![Part::Body(vec![Segment::Para(spans)])]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(json.contains(r#"{"span":{"contents":[{"text":"مرحبا"}],"lang":"ar","dir":"rtl"}},{"span":{"contents":[{"text":"plain"}]}}"#));
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+
+        // Documents from before tags were checked still decode, to the
+        // same bytes, but don't validate.
+        let bad = json.replace(r#""lang":"ar""#, r#""lang":"Arabic please""#);
+        let mut old = serde_json::from_str::<Document>(&bad).unwrap();
+        old.language = Some(LanguageTag::lossy("en_US"));
+        let bytes = old.to_cbor();
+        let decoded = Document::from_cbor(&bytes).unwrap();
+        assert_eq!(decoded.to_cbor(), bytes);
+        assert_eq!(decoded.language, Some(LanguageTag::lossy("en_US")));
+        let problems: Vec<String> = decoded.validate().iter().map(|d| d.to_string()).collect();
+        assert_eq!(problems, vec![
+            "language: invalid language tag \"en_US\"",
+            "part[0]/segment[0]/element[0]/lang: invalid language tag \"Arabic please\"",
+        ]);
+    }
+
+    #[test]
+    fn test_figure_metadata() {
+        let doc = Document::new(vec![Part::Body(vec![Segment::Figure {
+            caption: vec![],
+            source: cid(CID_B),
+            media_type: Some("image/png".to_string()),
+            alt: Some("A cat".to_string()),
+            dimensions: Some(Dimensions { width: 640, height: 480 }),
+            size: Some(5_000_000_000),
+        }])]);
+        let value = serde_json::to_value(&doc).unwrap();
+        assert_eq!(value["document"]["contents"][0]["body"][0]["figure"], serde_json::json!({
+            "caption": [],
+            "source": CID_B,
+            "media_type": "image/png",
+            "alt": "A cat",
+            "dimensions": {"width": 640, "height": 480},
+            "size": 5_000_000_000u64,
+        }));
+        assert_eq!(serde_json::from_value::<Document>(value).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_transclusion() {
+        let doc = Document::new(vec![Part::Body(vec![
+            Segment::Transclusion { source: cid(CID_A), fragment: None },
+            Segment::Transclusion { source: cid(CID_B), fragment: Some("intro".to_string()) },
+        ])]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(json.contains(&format!(r#"{{"transclusion":{{"source":"{}"}}}}"#, CID_A)));
+        assert!(json.contains(&format!(r#"{{"transclusion":{{"source":"{}","fragment":"intro"}}}}"#, CID_B)));
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_conflict() {
+        let doc = Document::new(vec![Part::Body(vec![Segment::Conflict {
+            ours: vec![Segment::Para(vec![text("mine")])],
+            theirs: vec![],
+        }])]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(json.contains(r#"{"conflict":{"ours":[{"para":[{"text":"mine"}]}],"theirs":[]}}"#));
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut doc = Document::new(vec![]);
+        doc.tags = vec![Tag::new("rust"), Tag::from_vocabulary("Physics", &cid(CID_A))];
+        let value = serde_json::to_value(&doc).unwrap();
+        assert_eq!(value["document"]["tags"], serde_json::json!([
+            {"term": "rust"},
+            {"term": "Physics", "vocabulary": CID_A},
+        ]));
+        assert_eq!(serde_json::from_value::<Document>(value).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_math() {
+        let doc = Document::new(vec![Part::Body(vec![
+            Segment::Para(vec![text("so "), Element::Math("x^2".to_string())]),
+            Segment::Math("\\sum_i x_i".to_string()),
+        ])]);
+        let json = serde_json::to_string(&doc).unwrap();
+        assert!(json.contains(r#"{"math":"x^2"}"#));
+        assert!(json.contains(r#"{"math":"\\sum_i x_i"}"#));
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&doc.to_cbor()).unwrap(), doc);
+        assert_eq!(plain_text(&[text("so "), Element::Math("x^2".to_string())]), "so x^2");
+    }
+
+    #[test]
+    fn test_cbor_roundtrip() {
+        let doc = kitchen_sink();
+        let bytes = doc.to_cbor();
+        assert_eq!(Document::from_cbor(&bytes).unwrap(), doc);
+        assert_eq!(Document::from_cbor(&bytes).unwrap().to_cbor(), bytes);
+
+        // Anything that wouldn't come back out the same is rejected.
+        let mut value = cbor::to_value(&doc).unwrap();
+        if let cbor::Value::Map(ref mut m) = value {
+            if let Some(&mut cbor::Value::Map(ref mut d)) = m.get_mut("document") {
+                d.insert("colour".to_string(), cbor::Value::Text("red".to_string()));
+            }
+        }
+        let mut bytes = Vec::new();
+        cbor::encode(&value, &mut bytes);
+        assert!(Document::from_cbor(&bytes).is_err());
+
+        let mut deep = vec![0x81; 1_000_000];
+        deep.push(0x00);
+        assert_eq!(Document::from_cbor(&deep), Err(cbor::Error::TooDeep));
+    }
+
+    /// These vectors must never change; if they do, every stored
+    /// document gets a new address.
+    #[test]
+    fn test_cid_vectors() {
+        let doc = Document::new(vec![]);
+        let hex: String = doc.to_cbor().iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "a26776657273696f6e0168646f63756d656e74a268636f6e74656e747380\
+                         726368617261637465725f656e636f64696e67657574662d38");
+        assert_eq!(doc.cid().to_string(), "zdpuApRKZfC37gSUDbS7mdFCwGVRS1EEfXkQXzSGa2WA8Z1xJ");
+
+        assert_eq!(kitchen_sink().cid().to_string(),
+                   "zdpuAwR66s5jsEZY5VXEuYZZYxVR2hLrB4BhEMhnMdQYCJr77");
+    }
+
+    #[test]
+    fn test_cid_deterministic() {
+        assert_eq!(kitchen_sink().cid(), kitchen_sink().cid());
+        let mut doc = kitchen_sink();
+        doc.title = Some("Something else".to_string());
+        assert!(doc.cid() != kitchen_sink().cid());
+    }
+
+    #[test]
+    fn test_cbor_links() {
+        // CIDs are stored as tag-42 links rather than strings
+        let mut doc = Document::new(vec![]);
+        doc.in_response_to = Some(cid(CID_A));
+        match cbor::decode(&doc.to_cbor()).unwrap() {
+            cbor::Value::Map(m) => match m["document"] {
+                cbor::Value::Map(ref d) => {
+                    assert_eq!(d["in_response_to"], cbor::Value::Link(cid(CID_A)));
+                }
+                _ => panic!("document is not a map"),
+            },
+            _ => panic!("top level is not a map"),
+        }
+    }
+}
@@ -0,0 +1,522 @@
+//! EPUB 3 e-books, for reading documents offline.
+//!
+//! Each document becomes one XHTML chapter, rendered just like the HTML
+//! version.  Figures are fetched from a store and put in the book, so
+//! it doesn't need a network connection, and links between documents in
+//! the same book go straight to the right chapter.  Anything else is
+//! linked through the `Linker` you give, like the HTML renderer.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+
+use cbor::{self, Value};
+use document::{plain_text, Document, Segment};
+use html::{self, escape, Linker};
+use path::Path;
+use store::{self, CidKey, Lookup};
+use threading::Threads;
+use toc::{self, Entry};
+use visit::{self, Visit};
+use zip;
+
+/// Media types every EPUB reader has to support, so they can go in the
+/// book without any fallback.
+const CORE_MEDIA_TYPES: &[(&str, &str)] = &[
+    ("image/gif", "gif"), ("image/jpeg", "jpg"), ("image/png", "png"),
+    ("image/svg+xml", "svg"), ("image/webp", "webp"),
+    ("audio/mpeg", "mp3"), ("audio/mp4", "m4a"),
+];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// There's nothing to put in the book.
+    Empty,
+    /// A figure couldn't be fetched.
+    Store(store::Error),
+    /// A figure is an image, but not in a format EPUB readers can be
+    /// counted on to show, or we couldn't tell what format it's in.
+    MediaType { source: Cid, media_type: Option<String> },
+    /// The book is too big to be a ZIP file.
+    Zip(zip::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Empty => write!(f, "no documents to put in the book"),
+            Error::Store(ref e) => write!(f, "{}", e),
+            Error::MediaType { ref source, media_type: Some(ref t) } => {
+                write!(f, "figure {} has media type {}, which EPUB doesn't support", source, t)
+            }
+            Error::MediaType { ref source, media_type: None } => {
+                write!(f, "figure {} is in an unknown format", source)
+            }
+            Error::Zip(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<store::Error> for Error {
+    fn from(e: store::Error) -> Error {
+        Error::Store(e)
+    }
+}
+
+impl From<zip::Error> for Error {
+    fn from(e: zip::Error) -> Error {
+        Error::Zip(e)
+    }
+}
+
+/// Makes an EPUB of one or more documents, one chapter each, in order.
+/// The book's title and language come from the first.  Its identifier
+/// is the document's CID if there's only one, and otherwise the CID of
+/// a DAG-CBOR list linking to all of them in order, so that no two
+/// books with different contents share one.
+///
+/// Video, and audio in formats EPUB doesn't require readers to play,
+/// are left where they are and played from the network, if the reader
+/// can.  Other figures that aren't images stay links.
+pub fn write<L>(store: &L, documents: &[Document], linker: &dyn Linker) -> Result<Vec<u8>, Error>
+    where L: Lookup + ?Sized
+{
+    let first = documents.first().ok_or(Error::Empty)?;
+
+    // Where everything in the book lives.
+    let mut files: HashMap<CidKey, String> = HashMap::new();
+    for (i, doc) in documents.iter().enumerate() {
+        files.insert(CidKey::new(&doc.cid()), chapter_file(i));
+    }
+
+    let mut images: Vec<(String, &str, Vec<u8>)> = Vec::new();
+    let mut remote = vec![false; documents.len()];
+    for (i, doc) in documents.iter().enumerate() {
+        for (source, media_type) in figures(doc) {
+            if files.contains_key(&CidKey::new(source)) {
+                continue;
+            }
+            let core = media_type.and_then(|t| {
+                let t = t.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+                CORE_MEDIA_TYPES.iter().find(|&&(c, _)| c == t)
+            });
+            let kind = media_type.map_or("image".to_string(), |t| t.split('/').next().unwrap_or("").to_ascii_lowercase());
+            let unsupported = || Error::MediaType { source: source.clone(), media_type: media_type.map(|t| t.to_string()) };
+            let &(media_type, extension) = match (core, &*kind) {
+                (Some(c), _) => c,
+                (None, "image") => {
+                    if media_type.is_some() {
+                        return Err(unsupported());
+                    }
+                    let bytes = store.get(source).ok_or_else(|| store::Error::Missing(source.clone()))?;
+                    sniff(&bytes).ok_or_else(unsupported)?
+                }
+                (None, "video") | (None, "audio") => {
+                    remote[i] = true;
+                    continue;
+                }
+                (None, _) => continue,
+            };
+            let bytes = store.get(source).ok_or_else(|| store::Error::Missing(source.clone()))?;
+            let file = format!("images/{}.{}", source, extension);
+            files.insert(CidKey::new(source), file.clone());
+            images.push((file, media_type, bytes));
+        }
+    }
+
+    let linker = BookLinker { files: &files, outside: linker };
+    // An ill-formed tag from an old document would make the package
+    // invalid, so those count as unknown.
+    let language = first.language.as_ref().filter(|l| l.is_well_formed()).map_or("und".to_string(), |l| l.to_string());
+    let mut chapters = Vec::new();
+    let mut nav = Vec::new();
+    for (i, doc) in documents.iter().enumerate() {
+        // Give the headings anchors for the table of contents to point at.
+        let doc = toc::apply(doc, &toc::Options::default());
+        let entries = toc::contents(&doc);
+        let body = html::render_xhtml_fragment(&doc, &linker);
+        let mut properties = Vec::new();
+        if body.contains("<math") {
+            properties.push("mathml");
+        }
+        if remote[i] {
+            properties.push("remote-resources");
+        }
+        let lang = doc.language.as_ref().filter(|l| l.is_well_formed()).map_or(language.clone(), |l| l.to_string());
+        let label = label(&doc, i);
+        let mut page = xhtml_head(&lang, &label);
+        if let Some(ref title) = doc.title {
+            writeln!(page, "<header><h1 class=\"title\">{}</h1></header>", escape(title)).unwrap();
+        }
+        page.push_str(&body);
+        page.push_str("</body>\n</html>\n");
+        chapters.push((page, properties.join(" ")));
+        nav.push((label, entries));
+    }
+
+    let mut zip = zip::Writer::new();
+    // This has to come first, so the file can be recognized by its
+    // first few bytes.
+    zip.add("mimetype", b"application/epub+zip")?;
+    zip.add("META-INF/container.xml", CONTAINER.as_bytes())?;
+    zip.add("EPUB/content.opf", package(documents, &language, &chapters, &images).as_bytes())?;
+    zip.add("EPUB/nav.xhtml", nav_document(&language, &nav).as_bytes())?;
+    for (i, (page, _)) in chapters.iter().enumerate() {
+        zip.add(&format!("EPUB/{}", chapter_file(i)), page.as_bytes())?;
+    }
+    for (file, _, bytes) in &images {
+        zip.add(&format!("EPUB/{}", file), bytes)?;
+    }
+    Ok(zip.finish()?)
+}
+
+/// Makes an EPUB of a conversation: `root` and all the replies under it,
+/// in the order `Threads::thread()` gives.
+pub fn write_thread<L>(store: &L, threads: &Threads, root: &Cid, linker: &dyn Linker) -> Result<Vec<u8>, Error>
+    where L: Lookup + ?Sized
+{
+    let documents: Vec<Document> = threads.thread(root).into_iter()
+        .filter_map(|(_, cid)| threads.document(cid).cloned())
+        .collect();
+    write(store, &documents, linker)
+}
+
+const CONTAINER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+<rootfiles>\n\
+<rootfile full-path=\"EPUB/content.opf\" media-type=\"application/oebps-package+xml\"/>\n\
+</rootfiles>\n\
+</container>\n";
+
+struct BookLinker<'a> {
+    files: &'a HashMap<CidKey, String>,
+    outside: &'a dyn Linker,
+}
+
+impl<'a> Linker for BookLinker<'a> {
+    fn url(&self, cid: &Cid) -> String {
+        match self.files.get(&CidKey::new(cid)) {
+            Some(file) => file.clone(),
+            None => self.outside.url(cid),
+        }
+    }
+}
+
+fn chapter_file(i: usize) -> String {
+    format!("chapter-{}.xhtml", i + 1)
+}
+
+/// What to call a chapter in the table of contents.
+fn label(doc: &Document, i: usize) -> String {
+    if let Some(title) = doc.title.as_ref().or(doc.subject.as_ref()) {
+        return title.clone();
+    }
+    let author = doc.author.clone().or_else(|| doc.author_id.as_ref().map(|a| a.to_string()));
+    match (author, doc.local_date_time()) {
+        (Some(a), Some(d)) => format!("{}, {}", a, d.format("%Y-%m-%d %H:%M")),
+        (Some(a), None) => a,
+        (None, Some(d)) => d.format("%Y-%m-%d %H:%M").to_string(),
+        (None, None) => format!("Document {}", i + 1),
+    }
+}
+
+/// The sources and media types of all the figures in a document.
+fn figures(doc: &Document) -> Vec<(&Cid, Option<&str>)> {
+    struct Figures<'ast>(Vec<(&'ast Cid, Option<&'ast str>)>);
+
+    impl<'ast> Visit<'ast> for Figures<'ast> {
+        fn visit_segment(&mut self, path: &mut Path, node: &'ast Segment) {
+            if let Segment::Figure { ref source, ref media_type, .. } = *node {
+                self.0.push((source, media_type.as_ref().map(|t| &**t)));
+            }
+            visit::visit_segment(self, path, node);
+        }
+    }
+
+    let mut f = Figures(Vec::new());
+    f.visit_document(&mut Path::new(), doc);
+    f.0
+}
+
+/// Works out what sort of image a figure with no media type is.
+fn sniff(bytes: &[u8]) -> Option<&'static (&'static str, &'static str)> {
+    let media_type = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        "image/gif"
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        "image/webp"
+    } else if bytes.starts_with(b"<svg") || (bytes.starts_with(b"<?xml") && bytes.windows(4).any(|w| w == b"<svg")) {
+        "image/svg+xml"
+    } else {
+        return None;
+    };
+    CORE_MEDIA_TYPES.iter().find(|&&(t, _)| t == media_type)
+}
+
+fn xhtml_head(lang: &str, title: &str) -> String {
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE html>\n\
+             <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" \
+             lang=\"{lang}\" xml:lang=\"{lang}\">\n\
+             <head>\n<meta charset=\"utf-8\" />\n<title>{title}</title>\n</head>\n<body>\n",
+            lang = escape(lang), title = escape(title))
+}
+
+fn w3c_date(date: DateTime<Utc>) -> String {
+    date.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn identifier(documents: &[Document]) -> Cid {
+    if documents.len() == 1 {
+        return documents[0].cid();
+    }
+    let links = Value::Array(documents.iter().map(|d| Value::Link(d.cid())).collect());
+    let mut block = Vec::new();
+    cbor::encode(&links, &mut block);
+    cbor::block_cid(&block)
+}
+
+fn package(documents: &[Document], language: &str, chapters: &[(String, String)],
+           images: &[(String, &str, Vec<u8>)]) -> String {
+    let first = &documents[0];
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                  <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">\n\
+                  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n");
+    writeln!(out, "<dc:identifier id=\"id\">ipfs://{}</dc:identifier>", identifier(documents)).unwrap();
+    let title = first.title.as_ref().or(first.subject.as_ref()).map_or("Untitled", |t| &**t);
+    writeln!(out, "<dc:title>{}</dc:title>", escape(title)).unwrap();
+    writeln!(out, "<dc:language>{}</dc:language>", escape(language)).unwrap();
+    let mut creators: Vec<String> = Vec::new();
+    for doc in documents {
+        let author = doc.author.clone().or_else(|| doc.author_id.as_ref().map(|a| a.to_string()));
+        if let Some(author) = author {
+            if !creators.contains(&author) {
+                creators.push(author);
+            }
+        }
+    }
+    for c in &creators {
+        writeln!(out, "<dc:creator>{}</dc:creator>", escape(c)).unwrap();
+    }
+    if let Some(date) = first.date {
+        writeln!(out, "<dc:date>{}</dc:date>", w3c_date(date)).unwrap();
+    }
+    // Required, and the latest date in the book is the best guess we
+    // have without making the output depend on when it was made.
+    let modified = documents.iter().filter_map(|d| d.date).max()
+        .unwrap_or_else(|| DateTime::from_timestamp(0, 0).expect("the epoch"));
+    writeln!(out, "<meta property=\"dcterms:modified\">{}</meta>", w3c_date(modified)).unwrap();
+    out.push_str("</metadata>\n<manifest>\n\
+                  <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n");
+    for (i, (_, properties)) in chapters.iter().enumerate() {
+        let properties = if properties.is_empty() { String::new() } else { format!(" properties=\"{}\"", properties) };
+        writeln!(out, "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{}/>",
+                 i + 1, chapter_file(i), properties).unwrap();
+    }
+    for (i, &(ref file, media_type, _)) in images.iter().enumerate() {
+        writeln!(out, "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>", i + 1, file, media_type).unwrap();
+    }
+    out.push_str("</manifest>\n<spine>\n");
+    for i in 0..chapters.len() {
+        writeln!(out, "<itemref idref=\"chapter-{}\"/>", i + 1).unwrap();
+    }
+    out.push_str("</spine>\n</package>\n");
+    out
+}
+
+/// The table of contents.  A book of one document lists its sections;
+/// a book of several lists the documents, with their sections under
+/// them.
+fn nav_document(language: &str, chapters: &[(String, Vec<Entry>)]) -> String {
+    fn list(out: &mut String, file: &str, entries: &[Entry]) {
+        out.push_str("<ol>\n");
+        for e in entries {
+            let mut text = plain_text(&e.heading);
+            if text.trim().is_empty() {
+                text = format!("Section {}", e.number_string());
+            }
+            write!(out, "<li><a href=\"{}#{}\">{}</a>", file, escape(&e.anchor), escape(&text)).unwrap();
+            if !e.children.is_empty() {
+                out.push('\n');
+                list(out, file, &e.children);
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str("</ol>\n");
+    }
+
+    let mut out = xhtml_head(language, "Contents");
+    out.push_str("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n");
+    match chapters {
+        [(_, ref sections)] if !sections.is_empty() => list(&mut out, &chapter_file(0), sections),
+        _ => {
+            out.push_str("<ol>\n");
+            for (i, (label, sections)) in chapters.iter().enumerate() {
+                write!(out, "<li><a href=\"{}\">{}</a>", chapter_file(i), escape(label)).unwrap();
+                if !sections.is_empty() {
+                    out.push('\n');
+                    list(&mut out, &chapter_file(i), sections);
+                }
+                out.push_str("</li>\n");
+            }
+            out.push_str("</ol>\n");
+        }
+    }
+    out.push_str("</nav>\n</body>\n</html>\n");
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use document::tests::{cid, text, CID_B};
+    use document::*;
+    use html::Gateway;
+    use language::LanguageTag;
+    use store::MemoryStore;
+    use zip;
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\nnot really a picture";
+
+    fn figure(source: &Cid, media_type: Option<&str>) -> Segment {
+        Segment::Figure {
+            caption: vec![vec![text("A picture")]],
+            source: source.clone(),
+            media_type: media_type.map(|t| t.to_string()),
+            alt: None,
+            dimensions: None,
+            size: None,
+        }
+    }
+
+    fn file<'a>(files: &'a [(String, Vec<u8>)], name: &str) -> &'a str {
+        let data = &files.iter().find(|f| f.0 == name).unwrap_or_else(|| panic!("no {}", name)).1;
+        ::std::str::from_utf8(data).unwrap()
+    }
+
+    fn gateway() -> Gateway {
+        Gateway("https://ipfs.io".to_string())
+    }
+
+    #[test]
+    fn test_book() {
+        let mut store = MemoryStore::new();
+        let picture = store.put(PNG.to_vec());
+        let mut doc = Document::new(vec![
+            Part::Body(vec![Segment::Para(vec![text("Intro "), Element::Math("x^2".to_string())])]),
+            Part::Section { level: 1, heading: vec![text("One & only")], contents: vec![figure(&picture, None)] },
+            Part::Section { level: 2, heading: vec![text("Sub")], contents: vec![
+                Segment::Para(vec![Element::Xref { contents: vec![text("elsewhere")], target: cid(CID_B), fragment: None }]),
+            ] },
+        ]);
+        doc.title = Some("A <Book>".to_string());
+        doc.author = Some("Simon Heath".to_string());
+        doc.language = Some(LanguageTag::parse("en-CA").unwrap());
+        doc.date = Some(Utc.with_ymd_and_hms(2017, 11, 5, 18, 30, 0).unwrap());
+
+        let epub = write(&store, &[doc.clone()], &gateway()).unwrap();
+        let files = zip::read(&epub);
+        let names: Vec<&str> = files.iter().map(|f| &*f.0).collect();
+        let image = format!("EPUB/images/{}.png", picture);
+        assert_eq!(names, vec!["mimetype", "META-INF/container.xml", "EPUB/content.opf", "EPUB/nav.xhtml",
+                               "EPUB/chapter-1.xhtml", &*image]);
+        assert_eq!(file(&files, "mimetype"), "application/epub+zip");
+        assert_eq!(files[5].1, PNG);
+
+        let opf = file(&files, "EPUB/content.opf");
+        assert!(opf.contains(&format!("<dc:identifier id=\"id\">ipfs://{}</dc:identifier>", doc.cid())));
+        assert!(opf.contains("<dc:title>A &lt;Book&gt;</dc:title>"));
+        assert!(opf.contains("<dc:language>en-CA</dc:language>"));
+        assert!(opf.contains("<dc:creator>Simon Heath</dc:creator>"));
+        assert!(opf.contains("<dc:date>2017-11-05T18:30:00Z</dc:date>"));
+        assert!(opf.contains("<meta property=\"dcterms:modified\">2017-11-05T18:30:00Z</meta>"));
+        assert!(opf.contains("<item id=\"chapter-1\" href=\"chapter-1.xhtml\" media-type=\"application/xhtml+xml\" properties=\"mathml\"/>"));
+        assert!(opf.contains(&format!("<item id=\"image-1\" href=\"images/{}.png\" media-type=\"image/png\"/>", picture)));
+
+        let nav = file(&files, "EPUB/nav.xhtml");
+        assert!(nav.contains("<ol>\n<li><a href=\"chapter-1.xhtml#one-only\">One &amp; only</a>\n\
+                              <ol>\n<li><a href=\"chapter-1.xhtml#sub\">Sub</a></li>\n</ol>\n</li>\n</ol>\n"));
+
+        let chapter = file(&files, "EPUB/chapter-1.xhtml");
+        assert!(chapter.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n"));
+        assert!(chapter.contains("lang=\"en-CA\" xml:lang=\"en-CA\""));
+        assert!(chapter.contains("<h1><a id=\"one-only\"></a>One &amp; only</h1>"));
+        assert!(chapter.contains(&format!("<img src=\"images/{}.png\" alt=\"A picture\" />", picture)));
+        assert!(chapter.contains(&format!("<a href=\"https://ipfs.io/ipfs/{}\">elsewhere</a>", CID_B)));
+
+        // The same documents always make the same book.
+        assert_eq!(write(&store, &[doc], &gateway()).unwrap(), epub);
+    }
+
+    #[test]
+    fn test_thread() {
+        let store = MemoryStore::new();
+        let mut root = Document::new(vec![Part::Body(vec![Segment::Para(vec![text("First!")])])]);
+        root.author = Some("Ada".to_string());
+        root.language = Some(LanguageTag::lossy("english please"));
+        let mut reply = Document::new(vec![Part::Body(vec![Segment::Para(vec![
+            Element::Xref { contents: vec![text("You")], target: root.cid(), fragment: None },
+            text(" said that."),
+        ])])]);
+        reply.author = Some("Simon".to_string());
+        reply.in_response_to = Some(root.cid());
+        reply.date = Some(Utc.with_ymd_and_hms(2018, 1, 1, 0, 0, 0).unwrap());
+        let threads = Threads::new(vec![reply.clone(), root.clone()]);
+
+        let files = zip::read(&write_thread(&store, &threads, &root.cid(), &gateway()).unwrap());
+        let opf = file(&files, "EPUB/content.opf");
+        assert!(opf.contains("<dc:title>Untitled</dc:title>\n<dc:language>und</dc:language>\n\
+                              <dc:creator>Ada</dc:creator>\n<dc:creator>Simon</dc:creator>\n"));
+        assert!(opf.contains("<spine>\n<itemref idref=\"chapter-1\"/>\n<itemref idref=\"chapter-2\"/>\n</spine>"));
+
+        // The identifier covers every chapter, not just the first.
+        let id = identifier(&[root.clone(), reply.clone()]);
+        assert!(opf.contains(&format!("<dc:identifier id=\"id\">ipfs://{}</dc:identifier>", id)));
+        assert!(id != root.cid());
+        assert!(id != identifier(&[root.clone()]) && id != identifier(&[reply.clone(), root.clone()]));
+        assert!(id != identifier(&[root.clone(), root.clone()]));
+        let nav = file(&files, "EPUB/nav.xhtml");
+        assert!(nav.contains("<li><a href=\"chapter-1.xhtml\">Ada</a></li>\n\
+                              <li><a href=\"chapter-2.xhtml\">Simon, 2018-01-01 00:00</a></li>\n"));
+        assert!(file(&files, "EPUB/chapter-2.xhtml").contains("<a href=\"chapter-1.xhtml\">You</a>"));
+    }
+
+    #[test]
+    fn test_figures() {
+        let mut store = MemoryStore::new();
+        let picture = store.put(PNG.to_vec());
+        let movie = cid(CID_B);
+        let doc = Document::new(vec![Part::Body(vec![
+            figure(&picture, Some("image/png")),
+            figure(&movie, Some("video/mp4")),
+            figure(&movie, Some("application/pdf")),
+        ])]);
+        let files = zip::read(&write(&store, &[doc], &gateway()).unwrap());
+        assert_eq!(files.len(), 6);
+        assert!(file(&files, "EPUB/content.opf").contains("properties=\"remote-resources\""));
+
+        let gone = Document::new(vec![Part::Body(vec![figure(&movie, Some("image/jpeg"))])]);
+        assert_eq!(write(&store, &[gone], &gateway()), Err(Error::Store(store::Error::Missing(movie.clone()))));
+        let tiff = Document::new(vec![Part::Body(vec![figure(&picture, Some("image/tiff"))])]);
+        assert_eq!(write(&store, &[tiff], &gateway()),
+                   Err(Error::MediaType { source: picture.clone(), media_type: Some("image/tiff".to_string()) }));
+        let text_block = store.put(b"plain text".to_vec());
+        let unknown = Document::new(vec![Part::Body(vec![figure(&text_block, None)])]);
+        assert_eq!(write(&store, &[unknown], &gateway()),
+                   Err(Error::MediaType { source: text_block, media_type: None }));
+        assert_eq!(write(&store, &[], &gateway()), Err(Error::Empty));
+    }
+}
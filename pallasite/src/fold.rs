@@ -0,0 +1,269 @@
+//! Rebuilding a document tree, one node at a time.
+//!
+//! Like `visit`, but each method takes a node by value and returns its
+//! replacement.  The defaults rebuild the node from its folded children.
+
+use cid::Cid;
+
+use document::{Document, Element, Elements, Part, Segment, Tag};
+use path::{Path, Step};
+
+pub trait Fold {
+    fn fold_document(&mut self, path: &mut Path, node: Document) -> Document {
+        fold_document(self, path, node)
+    }
+
+    fn fold_part(&mut self, path: &mut Path, node: Part) -> Part {
+        fold_part(self, path, node)
+    }
+
+    fn fold_segment(&mut self, path: &mut Path, node: Segment) -> Segment {
+        fold_segment(self, path, node)
+    }
+
+    fn fold_element(&mut self, path: &mut Path, node: Element) -> Element {
+        fold_element(self, path, node)
+    }
+
+    fn fold_cid(&mut self, _path: &mut Path, node: Cid) -> Cid {
+        node
+    }
+}
+
+pub fn fold_document<F>(f: &mut F, path: &mut Path, node: Document) -> Document
+    where F: Fold + ?Sized
+{
+    let contents = node.contents.into_iter().enumerate().map(|(i, part)| {
+        path.push(Step::Part(i));
+        let part = f.fold_part(path, part);
+        path.pop();
+        part
+    }).collect();
+    let previous_revisions = node.previous_revisions.map(|revisions| {
+        path.push(Step::Field("previous_revisions"));
+        let revisions = revisions.into_iter().enumerate().map(|(i, cid)| {
+            path.push(Step::Index(i));
+            let cid = f.fold_cid(path, cid);
+            path.pop();
+            cid
+        }).collect();
+        path.pop();
+        revisions
+    });
+    path.push(Step::Field("tags"));
+    let tags = node.tags.into_iter().enumerate().map(|(i, tag)| {
+        let vocabulary = tag.vocabulary.map(|cid| {
+            path.push(Step::Index(i));
+            path.push(Step::Field("vocabulary"));
+            let cid = f.fold_cid(path, cid);
+            path.pop();
+            path.pop();
+            cid
+        });
+        Tag { vocabulary, ..tag }
+    }).collect();
+    path.pop();
+    let in_response_to = node.in_response_to.map(|cid| {
+        path.push(Step::Field("in_response_to"));
+        let cid = f.fold_cid(path, cid);
+        path.pop();
+        cid
+    });
+    Document {
+        contents,
+        previous_revisions,
+        tags,
+        in_response_to,
+        ..node
+    }
+}
+
+pub fn fold_part<F>(f: &mut F, path: &mut Path, node: Part) -> Part
+    where F: Fold + ?Sized
+{
+    match node {
+        Part::Body(segments) => Part::Body(fold_segments(f, path, segments)),
+        Part::Section { level, heading, contents } => {
+            path.push(Step::Heading);
+            let heading = fold_elements(f, path, heading);
+            path.pop();
+            Part::Section {
+                level,
+                heading,
+                contents: fold_segments(f, path, contents),
+            }
+        }
+    }
+}
+
+pub fn fold_segment<F>(f: &mut F, path: &mut Path, node: Segment) -> Segment
+    where F: Fold + ?Sized
+{
+    match node {
+        Segment::Para(e) => Segment::Para(fold_elements(f, path, e)),
+        Segment::Abstract(e) => Segment::Abstract(fold_elements(f, path, e)),
+        Segment::Table { header, body, footer } => {
+            let header = fold_cells(f, path, header, Step::TableHeader);
+            let body = body.into_iter().enumerate().map(|(i, row)| {
+                path.push(Step::TableRow(i));
+                let row = fold_segments(f, path, row);
+                path.pop();
+                row
+            }).collect();
+            let footer = fold_cells(f, path, footer, Step::TableFooter);
+            Segment::Table { header, body, footer }
+        }
+        Segment::Figure { caption, source, media_type, alt, dimensions, size } => {
+            let caption = fold_cells(f, path, caption, Step::Caption);
+            path.push(Step::Field("source"));
+            let source = f.fold_cid(path, source);
+            path.pop();
+            Segment::Figure { caption, source, media_type, alt, dimensions, size }
+        }
+        Segment::List { type_, elements } => Segment::List {
+            type_,
+            elements: fold_segments(f, path, elements),
+        },
+        code @ Segment::Code { .. } => code,
+        math @ Segment::Math(_) => math,
+        Segment::Transclusion { source, fragment } => {
+            path.push(Step::Field("source"));
+            let source = f.fold_cid(path, source);
+            path.pop();
+            Segment::Transclusion { source, fragment }
+        }
+        Segment::Quote(s) => {
+            path.push(Step::Quote);
+            let s = f.fold_segment(path, *s);
+            path.pop();
+            Segment::Quote(Box::new(s))
+        }
+        Segment::Conflict { ours, theirs } => {
+            let ours = ours.into_iter().enumerate().map(|(i, s)| {
+                path.push(Step::Ours(i));
+                let s = f.fold_segment(path, s);
+                path.pop();
+                s
+            }).collect();
+            let theirs = theirs.into_iter().enumerate().map(|(i, s)| {
+                path.push(Step::Theirs(i));
+                let s = f.fold_segment(path, s);
+                path.pop();
+                s
+            }).collect();
+            Segment::Conflict { ours, theirs }
+        }
+    }
+}
+
+pub fn fold_element<F>(f: &mut F, path: &mut Path, node: Element) -> Element
+    where F: Fold + ?Sized
+{
+    match node {
+        Element::Text(_) | Element::Math(_) | Element::Comment(_) | Element::Anchor(_) => node,
+        Element::Xref { contents, target, fragment } => {
+            let contents = fold_elements(f, path, contents);
+            path.push(Step::Field("target"));
+            let target = f.fold_cid(path, target);
+            path.pop();
+            Element::Xref { contents, target, fragment }
+        }
+        Element::Link { contents, url } => Element::Link { contents: fold_elements(f, path, contents), url },
+        Element::Span { contents, lang, dir } => {
+            Element::Span { contents: fold_elements(f, path, contents), lang, dir }
+        }
+        Element::Strong(e) => Element::Strong(fold_elements(f, path, e)),
+        Element::Emphasized(e) => Element::Emphasized(fold_elements(f, path, e)),
+        Element::Footnote(e) => Element::Footnote(fold_elements(f, path, e)),
+        Element::Subscript(e) => Element::Subscript(fold_elements(f, path, e)),
+        Element::Superscript(e) => Element::Superscript(fold_elements(f, path, e)),
+        Element::Insertion(e) => Element::Insertion(fold_elements(f, path, e)),
+        Element::Deletion(e) => Element::Deletion(fold_elements(f, path, e)),
+        Element::Preformatted(e) => Element::Preformatted(fold_elements(f, path, e)),
+    }
+}
+
+fn fold_segments<F>(f: &mut F, path: &mut Path, segments: Vec<Segment>) -> Vec<Segment>
+    where F: Fold + ?Sized
+{
+    segments.into_iter().enumerate().map(|(i, s)| {
+        path.push(Step::Segment(i));
+        let s = f.fold_segment(path, s);
+        path.pop();
+        s
+    }).collect()
+}
+
+fn fold_elements<F>(f: &mut F, path: &mut Path, elements: Elements) -> Elements
+    where F: Fold + ?Sized
+{
+    elements.into_iter().enumerate().map(|(i, e)| {
+        path.push(Step::Element(i));
+        let e = f.fold_element(path, e);
+        path.pop();
+        e
+    }).collect()
+}
+
+/// Table header and footer cells and figure captions: lists of runs of elements.
+fn fold_cells<F>(f: &mut F, path: &mut Path, cells: Vec<Elements>, step: fn(usize) -> Step)
+    -> Vec<Elements>
+    where F: Fold + ?Sized
+{
+    cells.into_iter().enumerate().map(|(i, cell)| {
+        path.push(step(i));
+        let cell = fold_elements(f, path, cell);
+        path.pop();
+        cell
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::tests::kitchen_sink;
+    use document::*;
+    use path::Path;
+    use super::*;
+
+    struct Identity;
+
+    impl Fold for Identity {}
+
+    /// Accepts every suggested change: insertions become plain emphasis
+    /// and deletions become comments.
+    struct Accept;
+
+    impl Fold for Accept {
+        fn fold_element(&mut self, path: &mut Path, node: Element) -> Element {
+            match fold_element(self, path, node) {
+                Element::Insertion(e) => Element::Emphasized(e),
+                Element::Deletion(e) => Element::Comment(plain_text(&e)),
+                other => other,
+            }
+        }
+    }
+
+    #[test]
+    fn test_identity() {
+        let doc = kitchen_sink();
+        assert_eq!(Identity.fold_document(&mut Path::new(), doc.clone()), doc);
+    }
+
+    #[test]
+    fn test_fold_nested() {
+        let doc = Document::new(vec![Part::Body(vec![Segment::Quote(Box::new(Segment::Para(vec![
+            Element::Insertion(vec![
+                Element::Text("new ".to_string()),
+                Element::Deletion(vec![Element::Strong(vec![Element::Text("old".to_string())])]),
+            ]),
+        ])))])]);
+        let folded = Accept.fold_document(&mut Path::new(), doc);
+        assert_eq!(folded, Document::new(vec![Part::Body(vec![Segment::Quote(Box::new(Segment::Para(vec![
+            Element::Emphasized(vec![
+                Element::Text("new ".to_string()),
+                Element::Comment("old".to_string()),
+            ]),
+        ])))])]));
+    }
+}
@@ -0,0 +1,606 @@
+//! Converting documents to and from gemtext, the `text/gemini` format
+//! used on Gemini.
+//!
+//! Gemtext is line based and has no inline markup, so styling is
+//! flattened into plain text on the way out.  Links can't go in the
+//! middle of a line either: each `Xref` or `Link` in a paragraph turns
+//! into a link line after it.  Links to documents are written as
+//! `ipfs://` URLs, so they turn back into `Xref`s on the way in.
+//!
+//! Ordinary gemtext comes back unchanged from `parse()` then
+//! `render()`, but not all of it does.  Quoted lines are always read as
+//! text, so a quoted list item like `> * a` comes back as `>  * a`, and
+//! the same goes for quoted links and preformatted blocks.  Blank quoted
+//! lines are dropped.  And since gemtext has no escaping, a line of
+//! preformatted text that starts with three backticks gets a space in
+//! front, so it doesn't end the block early.
+
+use std::mem;
+
+use document::{Document, Element, Elements, ListType, Part, Segment};
+use markdown::link_reference;
+use url::Url;
+
+/// The `alt` text marking a preformatted block as TeX, so display math
+/// survives the trip.
+pub const MATH_ALT: &str = "math";
+
+/// The result of an import.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Imported {
+    pub document: Document,
+    /// Sorted by line.
+    pub warnings: Vec<Warning>,
+}
+
+/// Something in the source that couldn't be represented exactly.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Warning {
+    /// Line in the source, counting from 1.
+    pub line: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WarningKind {
+    /// A link to something that's neither a CID nor a URL that
+    /// `Url::parse()` accepts, such as a relative path or a `gemini://`
+    /// URL.  Only the link text is kept.
+    ExternalLink(String),
+    /// A preformatted block that's still open at the end of the file.
+    UnclosedPreformatted,
+}
+
+/// What sort of lines a block is made of, which decides whether it
+/// needs a blank line before it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Kind {
+    /// A paragraph, which the links in it follow directly.
+    Para,
+    Links,
+    Quote,
+    /// Anything else.
+    Other,
+}
+
+/// Renders a document as gemtext.  The title, if any, becomes a level 1
+/// heading at the top.  Sections deeper than level 3 get level 3
+/// headings, since that's all gemtext has.
+pub fn render(doc: &Document) -> String {
+    let mut w = Writer { blocks: Vec::new(), footnotes: Vec::new() };
+    if let Some(ref title) = doc.title {
+        w.block(Kind::Other, vec![format!("# {}", title)]);
+    }
+    for part in &doc.contents {
+        match *part {
+            Part::Body(ref segments) => w.segments(segments),
+            Part::Section { level, ref heading, ref contents } => {
+                let marks = "#".repeat(level.clamp(1, 3) as usize);
+                let text = w.inline(heading, &mut Vec::new());
+                w.block(Kind::Other, vec![format!("{} {}", marks, text)]);
+                w.segments(contents);
+            }
+        }
+    }
+    // Footnotes can have footnotes of their own.
+    let mut i = 0;
+    while i < w.footnotes.len() {
+        let note = w.footnotes[i].clone();
+        let mut links = Vec::new();
+        let text = w.inline(&note, &mut links);
+        w.block(Kind::Para, vec![format!("[{}] {}", i + 1, text)]);
+        if !links.is_empty() {
+            w.block(Kind::Links, links);
+        }
+        i += 1;
+    }
+
+    let mut out = String::new();
+    let mut previous = None;
+    for (kind, lines) in w.blocks {
+        let joined = matches!((previous, kind),
+                              (Some(Kind::Para), Kind::Links) | (Some(Kind::Links), Kind::Links) |
+                              (Some(Kind::Quote), Kind::Quote));
+        if previous.is_some() && !joined {
+            out.push('\n');
+        }
+        for line in lines {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        previous = Some(kind);
+    }
+    out
+}
+
+struct Writer {
+    blocks: Vec<(Kind, Vec<String>)>,
+    footnotes: Vec<Elements>,
+}
+
+impl Writer {
+    fn block(&mut self, kind: Kind, lines: Vec<String>) {
+        self.blocks.push((kind, lines));
+    }
+
+    fn segments(&mut self, segments: &[Segment]) {
+        for s in segments {
+            self.segment(s);
+        }
+    }
+
+    fn segment(&mut self, segment: &Segment) {
+        match *segment {
+            Segment::Para(ref e) | Segment::Abstract(ref e) => {
+                let mut links = Vec::new();
+                let text = self.inline(e, &mut links);
+                // A paragraph that's just a link is written as just the
+                // link line.
+                let only_link = e.len() == 1 && links.len() == 1
+                    && matches!(e[0], Element::Xref { .. } | Element::Link { .. });
+                if !only_link && !text.trim().is_empty() {
+                    self.block(Kind::Para, vec![protect(text)]);
+                }
+                if !links.is_empty() {
+                    self.block(Kind::Links, links);
+                }
+            }
+            Segment::Code { ref language, ref contents } => {
+                self.preformatted(language.as_ref().map_or("", |l| &**l), contents);
+            }
+            Segment::Math(ref tex) => self.preformatted(MATH_ALT, tex),
+            Segment::Table { ref header, ref body, ref footer } => {
+                let mut rows = Vec::new();
+                let mut links = Vec::new();
+                if !header.is_empty() {
+                    rows.push(header.iter().map(|c| self.inline(c, &mut links)).collect::<Vec<_>>());
+                }
+                for row in body {
+                    rows.push(row.iter().map(|c| self.cell(c, &mut links)).collect());
+                }
+                if !footer.is_empty() {
+                    rows.push(footer.iter().map(|c| self.inline(c, &mut links)).collect());
+                }
+                let lines: Vec<String> = rows.iter().map(|r| r.join(" | ")).collect();
+                self.preformatted("table", &lines.join("\n"));
+                if !links.is_empty() {
+                    self.block(Kind::Links, links);
+                }
+            }
+            Segment::List { ref type_, ref elements } => {
+                let mut lines = Vec::new();
+                let mut links = Vec::new();
+                self.list(type_, elements, &mut lines, &mut links);
+                self.block(Kind::Other, lines);
+                if !links.is_empty() {
+                    self.block(Kind::Links, links);
+                }
+            }
+            Segment::Quote(ref s) => {
+                // Render the quoted segment on its own, then mark every
+                // line of it as quoted.
+                let outer = mem::take(&mut self.blocks);
+                self.segment(s);
+                let inner = mem::replace(&mut self.blocks, outer);
+                let mut lines = Vec::new();
+                for (_, block) in inner {
+                    for line in block {
+                        if line.is_empty() {
+                            lines.push(">".to_string());
+                        } else {
+                            lines.push(format!("> {}", line));
+                        }
+                    }
+                }
+                self.block(Kind::Quote, lines);
+            }
+            Segment::Figure { ref caption, ref source, ref alt, .. } => {
+                let mut links = Vec::new();
+                let label = match caption.first() {
+                    Some(c) => self.inline(c, &mut links),
+                    None => alt.clone().unwrap_or_default(),
+                };
+                links.insert(0, link_line(&format!("ipfs://{}", source), &label));
+                self.block(Kind::Links, links);
+            }
+            Segment::Transclusion { ref source, ref fragment } => {
+                let url = match *fragment {
+                    Some(ref f) => format!("ipfs://{}#{}", source, f),
+                    None => format!("ipfs://{}", source),
+                };
+                self.block(Kind::Links, vec![link_line(&url, "")]);
+            }
+            Segment::Conflict { ref ours, ref theirs } => {
+                // Like the markers git leaves behind
+                self.block(Kind::Other, vec!["<<<<<<< ours".to_string()]);
+                self.segments(ours);
+                self.block(Kind::Other, vec!["=======".to_string()]);
+                self.segments(theirs);
+                self.block(Kind::Other, vec![">>>>>>> theirs".to_string()]);
+            }
+        }
+    }
+
+    fn preformatted(&mut self, alt: &str, contents: &str) {
+        let mut lines = vec![format!("```{}", alt)];
+        lines.extend(contents.strip_suffix('\n').unwrap_or(contents).split('\n').map(|l| {
+            if l.starts_with("```") {
+                format!(" {}", l)
+            } else {
+                l.to_string()
+            }
+        }));
+        lines.push("```".to_string());
+        self.block(Kind::Other, lines);
+    }
+
+    /// Gemtext lists don't nest, so nested items are just more items.
+    fn list(&mut self, type_: &ListType, items: &[Segment], lines: &mut Vec<String>, links: &mut Vec<String>) {
+        let mut n = 0;
+        for item in items {
+            match *item {
+                Segment::List { type_: ref t, ref elements } => self.list(t, elements, lines, links),
+                ref item => {
+                    n += 1;
+                    let text = self.cell(item, links);
+                    match *type_ {
+                        ListType::Bulleted => lines.push(format!("* {}", text)),
+                        ListType::Numbered => lines.push(format!("* {}. {}", n, text)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// A segment squashed onto one line, for list items and table cells.
+    fn cell(&mut self, segment: &Segment, links: &mut Vec<String>) -> String {
+        match *segment {
+            Segment::Para(ref e) | Segment::Abstract(ref e) => self.inline(e, links),
+            Segment::Code { ref contents, .. } | Segment::Math(ref contents) => contents.replace('\n', " "),
+            Segment::Quote(ref s) => self.cell(s, links),
+            _ => {
+                let outer = mem::take(&mut self.blocks);
+                self.segment(segment);
+                let inner = mem::replace(&mut self.blocks, outer);
+                let lines: Vec<String> = inner.into_iter().flat_map(|(_, l)| l).collect();
+                lines.join(" ")
+            }
+        }
+    }
+
+    /// The text of some elements with the styling stripped off.  Links
+    /// are added to `links` as link lines, and footnotes become `[n]`.
+    fn inline(&mut self, elements: &[Element], links: &mut Vec<String>) -> String {
+        let mut out = String::new();
+        for e in elements {
+            match *e {
+                Element::Text(ref s) | Element::Math(ref s) => out.push_str(s),
+                Element::Strong(ref e) |
+                Element::Emphasized(ref e) |
+                Element::Subscript(ref e) |
+                Element::Superscript(ref e) |
+                Element::Insertion(ref e) |
+                Element::Deletion(ref e) |
+                Element::Preformatted(ref e) |
+                Element::Span { contents: ref e, .. } => out.push_str(&self.inline(e, links)),
+                Element::Xref { ref contents, ref target, ref fragment } => {
+                    let text = self.inline(contents, links);
+                    let url = match *fragment {
+                        Some(ref f) => format!("ipfs://{}#{}", target, f),
+                        None => format!("ipfs://{}", target),
+                    };
+                    links.push(link_line(&url, &text));
+                    out.push_str(&text);
+                }
+                Element::Link { ref contents, ref url } => {
+                    let text = self.inline(contents, links);
+                    links.push(link_line(url.as_str(), &text));
+                    out.push_str(&text);
+                }
+                Element::Footnote(ref e) => {
+                    self.footnotes.push(e.clone());
+                    out.push_str(&format!("[{}]", self.footnotes.len()));
+                }
+                Element::Comment(_) | Element::Anchor(_) => (),
+            }
+        }
+        // Gemtext is one line per paragraph.
+        out.replace('\n', " ")
+    }
+}
+
+/// Gemtext has no escaping, so a paragraph that happens to start like a
+/// heading, link or the like gets a space in front to keep it a
+/// paragraph.
+fn protect(text: String) -> String {
+    if ["#", "* ", "=>", ">", "```"].iter().any(|m| text.starts_with(m)) {
+        format!(" {}", text)
+    } else {
+        text
+    }
+}
+
+/// A link line.  Link text that's the same as the URL is left off, since
+/// clients show the URL when there isn't any.
+fn link_line(url: &str, text: &str) -> String {
+    if text.is_empty() || text == url {
+        format!("=> {}", url)
+    } else {
+        format!("=> {} {}", url, text)
+    }
+}
+
+/// Parses gemtext.  This never fails; anything that can't be
+/// represented is reported in the warnings instead.
+///
+/// Each text line becomes a paragraph, and each run of `*` lines a
+/// bulleted list.  Headings start sections, with anything before the
+/// first one in a body part.  Link lines become paragraphs holding just
+/// the link.
+pub fn parse(text: &str) -> Imported {
+    let mut parts: Vec<Part> = Vec::new();
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut heading: Option<(u32, Elements)> = None;
+    let mut warnings = Vec::new();
+    let mut list: Vec<Segment> = Vec::new();
+    // Alt text, contents and starting line of an open preformatted block
+    let mut pre: Option<(String, String, usize)> = None;
+
+    fn finish_list(list: &mut Vec<Segment>, segments: &mut Vec<Segment>) {
+        if !list.is_empty() {
+            segments.push(Segment::List { type_: ListType::Bulleted, elements: mem::take(list) });
+        }
+    }
+
+    fn finish_part(heading: Option<(u32, Elements)>, segments: Vec<Segment>, parts: &mut Vec<Part>) {
+        match heading {
+            Some((level, heading)) => parts.push(Part::Section { level, heading, contents: segments }),
+            None if !segments.is_empty() => parts.push(Part::Body(segments)),
+            None => (),
+        }
+    }
+
+    for (i, line) in text.lines().enumerate() {
+        let n = i + 1;
+        if let Some((alt, mut contents, start)) = pre.take() {
+            if line.starts_with("```") {
+                segments.push(preformatted(alt, contents));
+            } else {
+                contents.push_str(line);
+                contents.push('\n');
+                pre = Some((alt, contents, start));
+            }
+            continue;
+        }
+        if !line.starts_with("* ") {
+            finish_list(&mut list, &mut segments);
+        }
+
+        if let Some(alt) = line.strip_prefix("```") {
+            pre = Some((alt.trim().to_string(), String::new(), n));
+        } else if let Some(rest) = line.strip_prefix("=>") {
+            segments.push(Segment::Para(parse_link(rest, n, &mut warnings)));
+        } else if line.starts_with('#') {
+            let level = line.chars().take_while(|&c| c == '#').count();
+            let title = line[level..].trim();
+            finish_part(heading.take(), mem::take(&mut segments), &mut parts);
+            heading = Some((level.min(3) as u32, vec![Element::Text(title.to_string())]));
+        } else if let Some(item) = line.strip_prefix("* ") {
+            list.push(Segment::Para(vec![Element::Text(item.trim().to_string())]));
+        } else if let Some(quoted) = line.strip_prefix('>') {
+            let quoted = quoted.strip_prefix(' ').unwrap_or(quoted);
+            if quoted.trim().is_empty() {
+                continue;
+            }
+            segments.push(Segment::Quote(Box::new(Segment::Para(vec![Element::Text(quoted.to_string())]))));
+        } else if !line.trim().is_empty() {
+            segments.push(Segment::Para(vec![Element::Text(line.to_string())]));
+        }
+    }
+    if let Some((alt, contents, start)) = pre {
+        warnings.push(Warning { line: start, kind: WarningKind::UnclosedPreformatted });
+        segments.push(preformatted(alt, contents));
+    }
+    finish_list(&mut list, &mut segments);
+    finish_part(heading, segments, &mut parts);
+
+    warnings.sort_by_key(|w| w.line);
+    Imported { document: Document::new(parts), warnings }
+}
+
+fn preformatted(alt: String, contents: String) -> Segment {
+    if alt == MATH_ALT {
+        Segment::Math(contents.strip_suffix('\n').unwrap_or(&contents).to_string())
+    } else {
+        Segment::Code { language: if alt.is_empty() { None } else { Some(alt) }, contents }
+    }
+}
+
+/// The part of a link line after the `=>`.
+fn parse_link(rest: &str, line: usize, warnings: &mut Vec<Warning>) -> Elements {
+    let rest = rest.trim_start();
+    let (url, label) = match rest.find(char::is_whitespace) {
+        Some(i) => (&rest[..i], rest[i..].trim()),
+        None => (rest, ""),
+    };
+    let contents = vec![Element::Text(if label.is_empty() { url } else { label }.to_string())];
+    if let Some((target, fragment)) = link_reference(url) {
+        return vec![Element::Xref { contents, target, fragment }];
+    }
+    match Url::parse(url) {
+        Ok(url) => vec![Element::Link { contents, url }],
+        Err(_) => {
+            warnings.push(Warning { line, kind: WarningKind::ExternalLink(url.to_string()) });
+            contents
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::tests::{cid, text, CID_A, CID_B};
+    use document::*;
+    use super::*;
+
+    /// Gemtext that should come out of `render(&parse(..))` unchanged.
+    const CORPUS: &[(&str, &str)] = &[
+        ("basic", include_str!("../testdata/gemtext/basic.gmi")),
+        ("links", include_str!("../testdata/gemtext/links.gmi")),
+        ("lists", include_str!("../testdata/gemtext/lists.gmi")),
+        ("preformatted", include_str!("../testdata/gemtext/preformatted.gmi")),
+        ("quotes", include_str!("../testdata/gemtext/quotes.gmi")),
+    ];
+
+    /// Gemtext that changes on the way through, and what it turns into.
+    const NORMALIZED: &[(&str, &str)] = &[
+        ("> * a\n> * b\n", ">  * a\n>  * b\n"),
+        ("> => https://example.com/ Example\n", ">  => https://example.com/ Example\n"),
+        ("> ```\n> code\n> ```\n", ">  ```\n> code\n>  ```\n"),
+        ("> a\n>\n> b\n", "> a\n> b\n"),
+    ];
+
+    #[test]
+    fn test_roundtrip_corpus() {
+        for &(name, source) in CORPUS {
+            let imported = parse(source);
+            assert_eq!(imported.warnings, vec![], "{}", name);
+            assert_eq!(render(&imported.document), source, "{}", name);
+        }
+        for &(source, normalized) in NORMALIZED {
+            assert_eq!(render(&parse(source).document), normalized, "{:?}", source);
+            assert_eq!(render(&parse(normalized).document), normalized, "{:?}", normalized);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let source = format!("intro\n\n# One\n=> ipfs://{}#here There\n=> https://example.com\n\
+                              ## Two\n* a\n*  b\n\n> said\n```rust\nfn main() {{}}\n```\n", CID_A);
+        let doc = parse(&source).document;
+        assert_eq!(doc.contents, vec![
+            Part::Body(vec![Segment::Para(vec![text("intro")])]),
+            Part::Section {
+                level: 1,
+                heading: vec![text("One")],
+                contents: vec![
+                    Segment::Para(vec![Element::Xref {
+                        contents: vec![text("There")],
+                        target: cid(CID_A),
+                        fragment: Some("here".to_string()),
+                    }]),
+                    Segment::Para(vec![Element::Link {
+                        contents: vec![text("https://example.com")],
+                        url: Url::parse("https://example.com").unwrap(),
+                    }]),
+                ],
+            },
+            Part::Section {
+                level: 2,
+                heading: vec![text("Two")],
+                contents: vec![
+                    Segment::List {
+                        type_: ListType::Bulleted,
+                        elements: vec![Segment::Para(vec![text("a")]), Segment::Para(vec![text("b")])],
+                    },
+                    Segment::Quote(Box::new(Segment::Para(vec![text("said")]))),
+                    Segment::Code { language: Some("rust".to_string()), contents: "fn main() {}\n".to_string() },
+                ],
+            },
+        ]);
+        assert!(doc.is_valid());
+    }
+
+    #[test]
+    fn test_parse_warnings() {
+        let imported = parse("=> gemini://example.com/ Capsule\n=> ../up\n```\nnever closed\n");
+        assert_eq!(imported.warnings, vec![
+            Warning { line: 1, kind: WarningKind::ExternalLink("gemini://example.com/".to_string()) },
+            Warning { line: 2, kind: WarningKind::ExternalLink("../up".to_string()) },
+            Warning { line: 3, kind: WarningKind::UnclosedPreformatted },
+        ]);
+        assert_eq!(imported.document.contents, vec![Part::Body(vec![
+            Segment::Para(vec![text("Capsule")]),
+            Segment::Para(vec![text("../up")]),
+            Segment::Code { language: None, contents: "never closed\n".to_string() },
+        ])]);
+    }
+
+    #[test]
+    fn test_render_protects_markers() {
+        let doc = Document::new(vec![Part::Body(vec![
+            Segment::Para(vec![text("# not a heading")]),
+            Segment::Para(vec![text("=> not a link")]),
+        ])]);
+        let gemtext = render(&doc);
+        assert_eq!(gemtext, " # not a heading\n\n => not a link\n");
+        assert_eq!(render(&parse(&gemtext).document), gemtext);
+    }
+
+    #[test]
+    fn test_render_protects_fences() {
+        let code = Segment::Code { language: None, contents: "```\nnot the end\n```rust\n".to_string() };
+        let gemtext = render(&Document::new(vec![Part::Body(vec![code])]));
+        assert_eq!(gemtext, "```\n ```\nnot the end\n ```rust\n```\n");
+        assert_eq!(parse(&gemtext).document.contents, vec![Part::Body(vec![Segment::Code {
+            language: None,
+            contents: " ```\nnot the end\n ```rust\n".to_string(),
+        }])]);
+    }
+
+    #[test]
+    fn test_render_flattens() {
+        let mut doc = Document::new(vec![
+            Part::Body(vec![
+                Segment::Para(vec![
+                    text("Some "),
+                    Element::Strong(vec![text("bold")]),
+                    text(" and a "),
+                    Element::Xref { contents: vec![text("link")], target: cid(CID_B), fragment: None },
+                    text("."),
+                    Element::Footnote(vec![text("Aside.")]),
+                    Element::Comment("hidden".to_string()),
+                ]),
+                Segment::List {
+                    type_: ListType::Numbered,
+                    elements: vec![
+                        Segment::Para(vec![text("first")]),
+                        Segment::List { type_: ListType::Bulleted, elements: vec![Segment::Para(vec![text("inner")])] },
+                        Segment::Para(vec![text("second")]),
+                    ],
+                },
+                Segment::Math("x^2".to_string()),
+                Segment::Figure {
+                    caption: vec![vec![text("A cat")]],
+                    source: cid(CID_A),
+                    media_type: None,
+                    alt: None,
+                    dimensions: None,
+                    size: None,
+                },
+            ]),
+            Part::Section { level: 5, heading: vec![Element::Emphasized(vec![text("Deep")])], contents: vec![] },
+        ]);
+        doc.title = Some("Title".to_string());
+        assert_eq!(render(&doc), format!("\
+# Title
+
+Some bold and a link.[1]
+=> ipfs://{b} link
+
+* 1. first
+* inner
+* 2. second
+
+```math
+x^2
+```
+
+=> ipfs://{a} A cat
+
+### Deep
+
+[1] Aside.
+", a = CID_A, b = CID_B));
+    }
+}
@@ -0,0 +1,426 @@
+//! The revision history of a document: the graph you get by following
+//! `previous_revisions` back from one or more revisions.
+//!
+//! Revisions are listed oldest first, so every revision comes after all
+//! of its previous revisions.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error;
+use std::fmt;
+use std::slice;
+
+use cid::Cid;
+
+use document::Document;
+use store::{self, CidKey, Lookup};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    Store(store::Error),
+    /// A revision turned out to be its own ancestor.  This can't happen
+    /// with honest content addressing, so the store is lying to us.
+    Cycle(Cid),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Store(ref e) => write!(f, "{}", e),
+            Error::Cycle(ref cid) => write!(f, "revision {} is its own ancestor", cid),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<store::Error> for Error {
+    fn from(e: store::Error) -> Error {
+        Error::Store(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    cid: Cid,
+    document: Document,
+    /// Indices of the previous revisions we found.
+    parents: Vec<usize>,
+    children: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct History {
+    nodes: Vec<Node>,
+    index: HashMap<CidKey, usize>,
+    /// Node indices, oldest first.
+    order: Vec<usize>,
+    missing: Vec<Cid>,
+}
+
+impl History {
+    /// Fetches `start` and everything it's a revision of.
+    pub fn load<L>(store: &L, start: &Cid) -> Result<History, Error>
+        where L: Lookup + ?Sized
+    {
+        History::load_all(store, slice::from_ref(start))
+    }
+
+    /// Fetches several revisions and all of their history, so we can
+    /// see how they're related.
+    ///
+    /// Revisions the store doesn't have don't stop us; they're listed
+    /// by `missing()` instead.  Blocks that aren't documents do.
+    pub fn load_all<L>(store: &L, heads: &[Cid]) -> Result<History, Error>
+        where L: Lookup + ?Sized
+    {
+        let mut history = History {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            order: Vec::new(),
+            missing: Vec::new(),
+        };
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<Cid> = VecDeque::new();
+        for head in heads {
+            if seen.insert(CidKey::new(head)) {
+                queue.push_back(head.clone());
+            }
+        }
+        while let Some(cid) = queue.pop_front() {
+            let document = match store.get(&cid) {
+                Some(bytes) => Document::from_cbor(&bytes)
+                    .map_err(|e| store::Error::Decode(cid.clone(), e))?,
+                None => {
+                    history.missing.push(cid);
+                    continue;
+                }
+            };
+            for parent in document.previous_revisions.iter().flatten() {
+                if seen.insert(CidKey::new(parent)) {
+                    queue.push_back(parent.clone());
+                }
+            }
+            history.index.insert(CidKey::new(&cid), history.nodes.len());
+            history.nodes.push(Node {
+                cid,
+                document,
+                parents: Vec::new(),
+                children: Vec::new(),
+            });
+        }
+
+        for i in 0..history.nodes.len() {
+            let mut parents: Vec<usize> = Vec::new();
+            for parent in history.nodes[i].document.previous_revisions.iter().flatten() {
+                if let Some(&p) = history.index.get(&CidKey::new(parent)) {
+                    if !parents.contains(&p) {
+                        parents.push(p);
+                    }
+                }
+            }
+            for &p in &parents {
+                history.nodes[p].children.push(i);
+            }
+            history.nodes[i].parents = parents;
+        }
+        history.sort()?;
+        Ok(history)
+    }
+
+    /// Puts the revisions in order, oldest first, and makes sure there
+    /// aren't any cycles.
+    fn sort(&mut self) -> Result<(), Error> {
+        let mut waiting: Vec<usize> = self.nodes.iter().map(|n| n.parents.len()).collect();
+        // The history was found newest first, so go backwards to keep
+        // unrelated revisions in a sensible order.
+        let mut ready: VecDeque<usize> = (0..self.nodes.len()).rev().filter(|&i| waiting[i] == 0).collect();
+        while let Some(i) = ready.pop_front() {
+            self.order.push(i);
+            for &child in &self.nodes[i].children {
+                waiting[child] -= 1;
+                if waiting[child] == 0 {
+                    ready.push_back(child);
+                }
+            }
+        }
+        match waiting.iter().position(|&w| w > 0) {
+            Some(i) => Err(Error::Cycle(self.nodes[i].cid.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn find(&self, cid: &Cid) -> Option<usize> {
+        self.index.get(&CidKey::new(cid)).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.find(cid).is_some()
+    }
+
+    pub fn document(&self, cid: &Cid) -> Option<&Document> {
+        self.find(cid).map(|i| &self.nodes[i].document)
+    }
+
+    /// Revisions that something refers to but the store didn't have.
+    pub fn missing(&self) -> &[Cid] {
+        &self.missing
+    }
+
+    /// Every revision, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = (&Cid, &Document)> {
+        self.order.iter().map(move |&i| (&self.nodes[i].cid, &self.nodes[i].document))
+    }
+
+    /// Revisions nothing else is a revision of.
+    pub fn heads(&self) -> Vec<&Cid> {
+        self.order.iter()
+            .filter(|&&i| self.nodes[i].children.is_empty())
+            .map(|&i| &self.nodes[i].cid)
+            .collect()
+    }
+
+    /// The previous revisions of `cid` that we have.
+    pub fn parents(&self, cid: &Cid) -> Vec<&Cid> {
+        self.find(cid)
+            .map(|i| self.nodes[i].parents.iter().map(|&p| &self.nodes[p].cid).collect())
+            .unwrap_or_default()
+    }
+
+    /// The revisions that list `cid` as a previous revision.
+    pub fn children(&self, cid: &Cid) -> Vec<&Cid> {
+        self.find(cid)
+            .map(|i| self.nodes[i].children.iter().map(|&c| &self.nodes[c].cid).collect())
+            .unwrap_or_default()
+    }
+
+    /// Indices of everything reachable from `start` along `next`,
+    /// including `start`.
+    fn reachable<F>(&self, start: usize, next: F) -> Vec<bool>
+        where F: Fn(&Node) -> &[usize]
+    {
+        let mut found = vec![false; self.nodes.len()];
+        let mut stack = vec![start];
+        found[start] = true;
+        while let Some(i) = stack.pop() {
+            for &j in next(&self.nodes[i]) {
+                if !found[j] {
+                    found[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        found
+    }
+
+    /// Every earlier version of `cid`, newest first.
+    pub fn ancestors(&self, cid: &Cid) -> Vec<&Cid> {
+        let i = match self.find(cid) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+        let found = self.reachable(i, |n| &n.parents);
+        self.order.iter().rev()
+            .filter(|&&j| j != i && found[j])
+            .map(|&j| &self.nodes[j].cid)
+            .collect()
+    }
+
+    /// Every later version of `cid` that we know about, oldest first.
+    pub fn descendants(&self, cid: &Cid) -> Vec<&Cid> {
+        let i = match self.find(cid) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+        let found = self.reachable(i, |n| &n.children);
+        self.order.iter()
+            .filter(|&&j| j != i && found[j])
+            .map(|&j| &self.nodes[j].cid)
+            .collect()
+    }
+
+    /// Whether `a` is an earlier version of `b`.
+    pub fn is_ancestor(&self, a: &Cid, b: &Cid) -> bool {
+        match (self.find(a), self.find(b)) {
+            (Some(a), Some(b)) => a != b && self.reachable(b, |n| &n.parents)[a],
+            _ => false,
+        }
+    }
+
+    /// The latest revisions that both `a` and `b` come from, counting a
+    /// revision as coming from itself.  Usually there's just one, but
+    /// criss-crossing merges can leave several equally good ones.
+    pub fn merge_bases(&self, a: &Cid, b: &Cid) -> Vec<&Cid> {
+        let (a, b) = match (self.find(a), self.find(b)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Vec::new(),
+        };
+        let from_a = self.reachable(a, |n| &n.parents);
+        let from_b = self.reachable(b, |n| &n.parents);
+        let common: Vec<usize> = (0..self.nodes.len()).filter(|&i| from_a[i] && from_b[i]).collect();
+        // Drop any common ancestor that's an ancestor of another one.
+        let mut older = vec![false; self.nodes.len()];
+        for &c in &common {
+            for &p in &self.nodes[c].parents {
+                for (j, &r) in self.reachable(p, |n| &n.parents).iter().enumerate() {
+                    older[j] |= r;
+                }
+            }
+        }
+        self.order.iter().rev()
+            .filter(|&&i| from_a[i] && from_b[i] && !older[i])
+            .map(|&i| &self.nodes[i].cid)
+            .collect()
+    }
+
+    /// The latest common ancestor of `a` and `b`, if they have one.
+    /// If there's more than one, picks the newest-looking.
+    pub fn merge_base(&self, a: &Cid, b: &Cid) -> Option<&Cid> {
+        self.merge_bases(a, b).into_iter().next()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::*;
+    use store::MemoryStore;
+    use super::*;
+
+    fn revision(store: &mut MemoryStore, text: &str, parents: &[&Cid]) -> Cid {
+        let mut doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![
+            Element::Text(text.to_string()),
+        ])])]);
+        if !parents.is_empty() {
+            doc.previous_revisions = Some(parents.iter().map(|&c| c.clone()).collect());
+        }
+        store.put_document(&doc)
+    }
+
+    /// ```text
+    /// a - b - c ----- f
+    ///      \         /
+    ///       d ----- e
+    /// ```
+    fn diamond() -> (MemoryStore, Vec<Cid>) {
+        let mut s = MemoryStore::new();
+        let a = revision(&mut s, "a", &[]);
+        let b = revision(&mut s, "b", &[&a]);
+        let c = revision(&mut s, "c", &[&b]);
+        let d = revision(&mut s, "d", &[&b]);
+        let e = revision(&mut s, "e", &[&d]);
+        let f = revision(&mut s, "f", &[&c, &e]);
+        (s, vec![a, b, c, d, e, f])
+    }
+
+    fn position(order: &[&Cid], cid: &Cid) -> usize {
+        order.iter().position(|&c| c == cid).unwrap()
+    }
+
+    #[test]
+    fn test_topological() {
+        let (store, r) = diamond();
+        let h = History::load(&store, &r[5]).unwrap();
+        assert_eq!(h.len(), 6);
+        assert!(h.missing().is_empty());
+        let order: Vec<&Cid> = h.iter().map(|(c, _)| c).collect();
+        assert_eq!(order[0], &r[0]);
+        assert_eq!(order[5], &r[5]);
+        for (child, parent) in &[(1, 0), (2, 1), (3, 1), (4, 3), (5, 2), (5, 4)] {
+            assert!(position(&order, &r[*parent]) < position(&order, &r[*child]));
+        }
+        assert_eq!(h.heads(), vec![&r[5]]);
+        assert_eq!(h.parents(&r[5]), vec![&r[2], &r[4]]);
+        assert_eq!(h.children(&r[1]).len(), 2);
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let (store, r) = diamond();
+        let h = History::load(&store, &r[5]).unwrap();
+        let ancestors = h.ancestors(&r[4]);
+        assert_eq!(ancestors, vec![&r[3], &r[1], &r[0]]);
+        assert_eq!(h.ancestors(&r[0]), Vec::<&Cid>::new());
+        let descendants = h.descendants(&r[1]);
+        assert_eq!(descendants.len(), 4);
+        assert_eq!(descendants[3], &r[5]);
+        assert!(h.is_ancestor(&r[0], &r[5]));
+        assert!(!h.is_ancestor(&r[2], &r[4]));
+        assert!(!h.is_ancestor(&r[5], &r[5]));
+    }
+
+    #[test]
+    fn test_merge_base() {
+        let (store, r) = diamond();
+        let h = History::load(&store, &r[5]).unwrap();
+        assert_eq!(h.merge_base(&r[2], &r[4]), Some(&r[1]));
+        assert_eq!(h.merge_base(&r[2], &r[5]), Some(&r[2]));
+        assert_eq!(h.merge_base(&r[0], &r[0]), Some(&r[0]));
+    }
+
+    #[test]
+    fn test_criss_cross() {
+        let mut s = MemoryStore::new();
+        let a = revision(&mut s, "a", &[]);
+        let b = revision(&mut s, "b", &[&a]);
+        let c = revision(&mut s, "c", &[&a]);
+        let d = revision(&mut s, "d", &[&b, &c]);
+        let e = revision(&mut s, "e", &[&c, &b]);
+        let h = History::load_all(&s, &[d.clone(), e.clone()]).unwrap();
+        let mut bases = h.merge_bases(&d, &e);
+        bases.sort_by_key(|c| c.to_string());
+        let mut expected = vec![&b, &c];
+        expected.sort_by_key(|c| c.to_string());
+        assert_eq!(bases, expected);
+        assert_eq!(h.heads().len(), 2);
+    }
+
+    #[test]
+    fn test_missing() {
+        let (mut store, r) = diamond();
+        store.remove(&r[1]);
+        let h = History::load(&store, &r[5]).unwrap();
+        assert_eq!(h.missing(), &[r[1].clone()][..]);
+        assert!(!h.contains(&r[0]));
+        assert_eq!(h.merge_base(&r[2], &r[4]), None);
+        assert_eq!(h.ancestors(&r[5]).len(), 3);
+    }
+
+    #[test]
+    fn test_cycle() {
+        use cbor;
+        use std::collections::HashMap;
+        use store::tests::Liar;
+
+        let a = cbor::block_cid(b"a");
+        let b = cbor::block_cid(b"b");
+        let mut doc_a = Document::new(vec![]);
+        doc_a.previous_revisions = Some(vec![b.clone()]);
+        let mut doc_b = Document::new(vec![]);
+        doc_b.previous_revisions = Some(vec![a.clone()]);
+        let mut blocks = HashMap::new();
+        blocks.insert(a.to_string(), doc_a.to_cbor());
+        blocks.insert(b.to_string(), doc_b.to_cbor());
+        match History::load(&Liar(blocks), &a) {
+            Err(Error::Cycle(_)) => (),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_bad_block() {
+        let mut store = MemoryStore::new();
+        let junk = store.put(vec![0xf6]);
+        match History::load(&store, &junk) {
+            Err(Error::Store(store::Error::Decode(..))) => (),
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,612 @@
+//! Rendering documents as HTML5.
+//!
+//! All text is escaped, and nothing from the document ever ends up as
+//! markup, so it's safe to show documents from people you don't trust.
+
+use std::fmt::Write;
+
+use cid::Cid;
+
+use document::{plain_text, Direction, Document, Element, ListType, Part, Segment};
+use math;
+
+/// Turns a `Cid` into a URL a browser can fetch, for links and images.
+pub trait Linker {
+    fn url(&self, cid: &Cid) -> String;
+}
+
+/// Links to content through an IPFS HTTP gateway, such as
+/// `https://ipfs.io` or a local daemon's `http://localhost:8080`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Gateway(pub String);
+
+impl Linker for Gateway {
+    fn url(&self, cid: &Cid) -> String {
+        format!("{}/ipfs/{}", self.0.trim_end_matches('/'), cid)
+    }
+}
+
+/// Escapes text so it's safe both between tags and inside quoted attributes.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders a complete HTML page, with the document's metadata in the `<head>`.
+pub fn render(doc: &Document, linker: &dyn Linker) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n");
+    match doc.language {
+        Some(ref lang) => writeln!(out, "<html lang=\"{}\">", escape(lang.as_str())).unwrap(),
+        None => out.push_str("<html>\n"),
+    }
+    out.push_str("<head>\n<meta charset=\"utf-8\">\n");
+    if let Some(title) = doc.title.as_ref().or(doc.subject.as_ref()) {
+        writeln!(out, "<title>{}</title>", escape(title)).unwrap();
+    }
+    if let Some(ref author) = doc.author {
+        writeln!(out, "<meta name=\"author\" content=\"{}\">", escape(author)).unwrap();
+    }
+    if let Some(date) = doc.local_date_time() {
+        writeln!(out, "<meta name=\"date\" content=\"{}\">", date.to_rfc3339()).unwrap();
+    }
+    if !doc.tags.is_empty() {
+        let terms: Vec<_> = doc.tags.iter().map(|t| &*t.term).collect();
+        writeln!(out, "<meta name=\"keywords\" content=\"{}\">", escape(&terms.join(", "))).unwrap();
+    }
+    out.push_str("</head>\n<body>\n");
+    if let Some(ref title) = doc.title {
+        writeln!(out, "<header><h1 class=\"title\">{}</h1></header>", escape(title)).unwrap();
+    }
+    out.push_str(&render_fragment(doc, linker));
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders just the contents of a document, for embedding in
+/// some other page.  Footnotes are collected at the end.
+pub fn render_fragment(doc: &Document, linker: &dyn Linker) -> String {
+    fragment(doc, linker, false)
+}
+
+/// Like `render_fragment()`, but well-formed XML, for XHTML pages like
+/// the ones in an EPUB.
+pub fn render_xhtml_fragment(doc: &Document, linker: &dyn Linker) -> String {
+    fragment(doc, linker, true)
+}
+
+fn fragment(doc: &Document, linker: &dyn Linker, xhtml: bool) -> String {
+    let mut r = Renderer {
+        linker,
+        xhtml,
+        out: String::new(),
+        footnotes: Vec::new(),
+    };
+    for part in &doc.contents {
+        r.part(part);
+    }
+    r.footnotes();
+    r.out
+}
+
+struct Renderer<'a> {
+    linker: &'a dyn Linker,
+    /// Write well-formed XML, like `<br />` rather than `<br>`.
+    xhtml: bool,
+    out: String,
+    footnotes: Vec<&'a [Element]>,
+}
+
+impl<'a> Renderer<'a> {
+    fn part(&mut self, part: &'a Part) {
+        match *part {
+            Part::Body(ref segments) => self.segments(segments),
+            Part::Section { level, ref heading, ref contents } => {
+                let h = level.clamp(1, 6);
+                self.out.push_str("<section>\n");
+                if !heading.is_empty() {
+                    write!(self.out, "<h{}>", h).unwrap();
+                    self.elements(heading);
+                    writeln!(self.out, "</h{}>", h).unwrap();
+                }
+                self.segments(contents);
+                self.out.push_str("</section>\n");
+            }
+        }
+    }
+
+    fn segments(&mut self, segments: &'a [Segment]) {
+        let mut i = 0;
+        while i < segments.len() {
+            // Runs of quotes are one quotation with several paragraphs.
+            if let Segment::Quote(_) = segments[i] {
+                self.out.push_str("<blockquote>\n");
+                while let Some(Segment::Quote(s)) = segments.get(i) {
+                    self.segment(s);
+                    i += 1;
+                }
+                self.out.push_str("</blockquote>\n");
+            } else {
+                self.segment(&segments[i]);
+                i += 1;
+            }
+        }
+    }
+
+    fn segment(&mut self, segment: &'a Segment) {
+        match *segment {
+            Segment::Para(ref e) => {
+                self.out.push_str("<p>");
+                self.elements(e);
+                self.out.push_str("</p>\n");
+            }
+            Segment::Abstract(ref e) => {
+                self.out.push_str("<p class=\"abstract\">");
+                self.elements(e);
+                self.out.push_str("</p>\n");
+            }
+            Segment::Table { ref header, ref body, ref footer } => {
+                self.out.push_str("<table>\n");
+                if !header.is_empty() {
+                    self.out.push_str("<thead>\n");
+                    self.row("th", header);
+                    self.out.push_str("</thead>\n");
+                }
+                self.out.push_str("<tbody>\n");
+                for row in body {
+                    self.out.push_str("<tr>");
+                    for cell in row {
+                        self.out.push_str("<td>");
+                        self.cell(cell);
+                        self.out.push_str("</td>");
+                    }
+                    self.out.push_str("</tr>\n");
+                }
+                self.out.push_str("</tbody>\n");
+                if !footer.is_empty() {
+                    self.out.push_str("<tfoot>\n");
+                    self.row("td", footer);
+                    self.out.push_str("</tfoot>\n");
+                }
+                self.out.push_str("</table>\n");
+            }
+            Segment::Figure { ref caption, ref source, ref media_type, ref alt, dimensions, .. } => {
+                let url = escape(&self.linker.url(source));
+                // Without alt text, the caption is the best description we have.
+                let alt = match *alt {
+                    Some(ref a) => a.clone(),
+                    None => caption.iter().map(|c| plain_text(c)).collect::<Vec<_>>().join(" "),
+                };
+                let size = match dimensions {
+                    Some(d) => format!(" width=\"{}\" height=\"{}\"", d.width, d.height),
+                    None => String::new(),
+                };
+                let kind = match *media_type {
+                    Some(ref t) => t.split('/').next().unwrap_or("").to_ascii_lowercase(),
+                    None => "image".to_string(),
+                };
+                let (close, controls) = if self.xhtml { (" /", "controls=\"controls\"") } else { ("", "controls") };
+                self.out.push_str("<figure>\n");
+                match &*kind {
+                    "image" => writeln!(self.out, "<img src=\"{}\" alt=\"{}\"{}{}>", url, escape(&alt), size, close),
+                    "video" | "audio" => writeln!(self.out, "<{} src=\"{}\" {} aria-label=\"{}\"{}></{}>",
+                                                  kind, url, controls, escape(&alt), size, kind),
+                    // Something a browser can't show inline, like a PDF
+                    _ => {
+                        let text = if alt.is_empty() { source.to_string() } else { alt };
+                        writeln!(self.out, "<a href=\"{}\" type=\"{}\">{}</a>", url,
+                                 escape(media_type.as_ref().map_or("", |t| &**t)), escape(&text))
+                    }
+                }.unwrap();
+                if !caption.is_empty() {
+                    self.out.push_str("<figcaption>");
+                    for (i, c) in caption.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str(if self.xhtml { "<br />" } else { "<br>" });
+                        }
+                        self.elements(c);
+                    }
+                    self.out.push_str("</figcaption>\n");
+                }
+                self.out.push_str("</figure>\n");
+            }
+            Segment::List { ref type_, ref elements } => {
+                let tag = match *type_ {
+                    ListType::Bulleted => "ul",
+                    ListType::Numbered => "ol",
+                };
+                writeln!(self.out, "<{}>", tag).unwrap();
+                let mut open = false;
+                for e in elements {
+                    match *e {
+                        // A list following an item is a sublist of that item.
+                        Segment::List { .. } if open => self.segment(e),
+                        _ => {
+                            if open {
+                                self.out.push_str("</li>\n");
+                            }
+                            self.out.push_str("<li>");
+                            self.cell(e);
+                            open = true;
+                        }
+                    }
+                }
+                if open {
+                    self.out.push_str("</li>\n");
+                }
+                writeln!(self.out, "</{}>", tag).unwrap();
+            }
+            Segment::Code { ref language, ref contents } => {
+                match *language {
+                    Some(ref l) => write!(self.out, "<pre><code class=\"language-{}\">", escape(l)),
+                    None => write!(self.out, "<pre><code>"),
+                }.unwrap();
+                self.out.push_str(&escape(contents));
+                self.out.push_str("</code></pre>\n");
+            }
+            Segment::Math(ref tex) => match math::to_mathml(tex, true) {
+                Ok(m) => writeln!(self.out, "{}", m).unwrap(),
+                // Better to show the source than nothing at all.
+                Err(_) => writeln!(self.out, "<pre class=\"math\">{}</pre>", escape(tex)).unwrap(),
+            },
+            // One that hasn't been expanded, or couldn't be.
+            Segment::Transclusion { ref source, ref fragment } => {
+                let mut url = self.linker.url(source);
+                if let Some(ref f) = *fragment {
+                    url.push('#');
+                    url.push_str(f);
+                }
+                writeln!(self.out, "<p class=\"transclusion\"><a href=\"{}\">{}</a></p>",
+                         escape(&url), escape(&url)).unwrap();
+            }
+            Segment::Quote(ref s) => {
+                self.out.push_str("<blockquote>\n");
+                self.segment(s);
+                self.out.push_str("</blockquote>\n");
+            }
+            Segment::Conflict { ref ours, ref theirs } => {
+                self.out.push_str("<div class=\"conflict\">\n<div class=\"ours\">\n");
+                self.segments(ours);
+                self.out.push_str("</div>\n<div class=\"theirs\">\n");
+                self.segments(theirs);
+                self.out.push_str("</div>\n</div>\n");
+            }
+        }
+    }
+
+    fn row(&mut self, tag: &str, cells: &'a [Vec<Element>]) {
+        self.out.push_str("<tr>");
+        for cell in cells {
+            write!(self.out, "<{}>", tag).unwrap();
+            self.elements(cell);
+            write!(self.out, "</{}>", tag).unwrap();
+        }
+        self.out.push_str("</tr>\n");
+    }
+
+    /// Segments inside table cells and list items.  A lone paragraph
+    /// doesn't get wrapped in `<p>`, so simple lists and tables stay simple.
+    fn cell(&mut self, segment: &'a Segment) {
+        match *segment {
+            Segment::Para(ref e) => self.elements(e),
+            _ => {
+                self.out.push('\n');
+                self.segment(segment);
+            }
+        }
+    }
+
+    fn wrap(&mut self, tag: &str, elements: &'a [Element]) {
+        write!(self.out, "<{}>", tag).unwrap();
+        self.elements(elements);
+        write!(self.out, "</{}>", tag).unwrap();
+    }
+
+    fn elements(&mut self, elements: &'a [Element]) {
+        for e in elements {
+            self.element(e);
+        }
+    }
+
+    fn element(&mut self, element: &'a Element) {
+        match *element {
+            Element::Text(ref s) => self.out.push_str(&escape(s)),
+            Element::Strong(ref e) => self.wrap("strong", e),
+            Element::Emphasized(ref e) => self.wrap("em", e),
+            Element::Footnote(ref e) => {
+                self.footnotes.push(e);
+                let n = self.footnotes.len();
+                write!(self.out, "<sup class=\"footnote-ref\"><a href=\"#fn-{}\" id=\"fnref-{}\">{}</a></sup>",
+                       n, n, n).unwrap();
+            }
+            Element::Xref { ref contents, ref target, ref fragment } => {
+                let mut url = self.linker.url(target);
+                if let Some(ref f) = *fragment {
+                    url.push('#');
+                    url.push_str(f);
+                }
+                write!(self.out, "<a href=\"{}\">", escape(&url)).unwrap();
+                self.elements(contents);
+                self.out.push_str("</a>");
+            }
+            Element::Link { ref contents, ref url } => {
+                write!(self.out, "<a href=\"{}\">", escape(url.as_str())).unwrap();
+                self.elements(contents);
+                self.out.push_str("</a>");
+            }
+            Element::Span { ref contents, ref lang, dir } => {
+                self.out.push_str("<span");
+                if let Some(ref lang) = *lang {
+                    write!(self.out, " lang=\"{}\"", escape(lang.as_str())).unwrap();
+                }
+                match dir {
+                    Some(Direction::Ltr) => self.out.push_str(" dir=\"ltr\""),
+                    Some(Direction::Rtl) => self.out.push_str(" dir=\"rtl\""),
+                    None => (),
+                }
+                self.out.push('>');
+                self.elements(contents);
+                self.out.push_str("</span>");
+            }
+            Element::Subscript(ref e) => self.wrap("sub", e),
+            Element::Superscript(ref e) => self.wrap("sup", e),
+            Element::Insertion(ref e) => self.wrap("ins", e),
+            Element::Deletion(ref e) => self.wrap("del", e),
+            Element::Preformatted(ref e) => self.wrap("code", e),
+            Element::Math(ref tex) => match math::to_mathml(tex, false) {
+                Ok(m) => self.out.push_str(&m),
+                Err(_) => write!(self.out, "<code class=\"math\">{}</code>", escape(tex)).unwrap(),
+            },
+            // Comments are notes for authors, not for readers.
+            Element::Comment(_) => (),
+            Element::Anchor(ref name) => write!(self.out, "<a id=\"{}\"></a>", escape(name)).unwrap(),
+        }
+    }
+
+    /// Writes out the collected footnotes, including any that turn up
+    /// inside other footnotes.
+    fn footnotes(&mut self) {
+        if self.footnotes.is_empty() {
+            return;
+        }
+        self.out.push_str("<section class=\"footnotes\">\n<ol>\n");
+        let mut i = 0;
+        while i < self.footnotes.len() {
+            let n = i + 1;
+            write!(self.out, "<li id=\"fn-{}\">", n).unwrap();
+            let e = self.footnotes[i];
+            self.elements(e);
+            writeln!(self.out, " <a href=\"#fnref-{}\">\u{21A9}</a></li>", n).unwrap();
+            i += 1;
+        }
+        self.out.push_str("</ol>\n</section>\n");
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use document::tests::{cid, kitchen_sink, text, CID_A};
+    use document::*;
+    use language::LanguageTag;
+    use url::Url;
+    use super::*;
+
+    const GATEWAY: &str = "https://ipfs.io/";
+
+    fn fragment(segments: Vec<Segment>) -> String {
+        render_fragment(&Document::new(vec![Part::Body(segments)]), &Gateway(GATEWAY.to_string()))
+    }
+
+    #[test]
+    fn test_escaping() {
+        let html = fragment(vec![Segment::Para(vec![text("<script>alert('hi & \"bye\"')</script>")])]);
+        assert_eq!(html, "<p>&lt;script&gt;alert(&#39;hi &amp; &quot;bye&quot;&#39;)&lt;/script&gt;</p>\n");
+        let html = fragment(vec![Segment::Code {
+            language: Some("\"><script>".to_string()),
+            contents: "a < b".to_string(),
+        }]);
+        assert_eq!(html, "<pre><code class=\"language-&quot;&gt;&lt;script&gt;\">a &lt; b</code></pre>\n");
+    }
+
+    #[test]
+    fn test_sections() {
+        let doc = Document::new(vec![
+            Part::Section { level: 2, heading: vec![text("Two")], contents: vec![] },
+            Part::Section { level: 9, heading: vec![text("Nine")], contents: vec![] },
+        ]);
+        let html = render_fragment(&doc, &Gateway(GATEWAY.to_string()));
+        assert_eq!(html, "<section>\n<h2>Two</h2>\n</section>\n<section>\n<h6>Nine</h6>\n</section>\n");
+    }
+
+    #[test]
+    fn test_inline() {
+        let html = fragment(vec![Segment::Para(vec![
+            Element::Insertion(vec![text("new")]),
+            Element::Deletion(vec![text("old")]),
+            Element::Comment("secret".to_string()),
+            Element::Xref { contents: vec![text("link")], target: cid(CID_A), fragment: None },
+        ])]);
+        assert_eq!(html, format!("<p><ins>new</ins><del>old</del><a href=\"https://ipfs.io/ipfs/{}\">link</a></p>\n",
+                                 CID_A));
+    }
+
+    #[test]
+    fn test_math() {
+        let html = fragment(vec![
+            Segment::Para(vec![text("so "), Element::Math("x^2".to_string())]),
+            Segment::Math("\\frac{a}{b}".to_string()),
+            Segment::Math("\\begin{x}<".to_string()),
+        ]);
+        assert_eq!(html, "<p>so <math xmlns=\"http://www.w3.org/1998/Math/MathML\"><msup><mi>x</mi><mn>2</mn></msup></math></p>\n\
+                          <math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\">\
+                          <mfrac><mi>a</mi><mi>b</mi></mfrac></math>\n\
+                          <pre class=\"math\">\\begin{x}&lt;</pre>\n");
+    }
+
+    #[test]
+    fn test_span() {
+        let html = fragment(vec![Segment::Para(vec![
+            text("He said "),
+            Element::Span {
+                contents: vec![text("שלום")],
+                lang: Some(LanguageTag::parse("he").unwrap()),
+                dir: Some(Direction::Rtl),
+            },
+            Element::Span { contents: vec![text("!")], lang: None, dir: Some(Direction::Ltr) },
+        ])]);
+        assert_eq!(html, "<p>He said <span lang=\"he\" dir=\"rtl\">שלום</span><span dir=\"ltr\">!</span></p>\n");
+    }
+
+    #[test]
+    fn test_external_link() {
+        let html = fragment(vec![Segment::Para(vec![Element::Link {
+            contents: vec![text("search")],
+            url: Url::parse("https://example.com/?q=a&b=\"c\"").unwrap(),
+        }])]);
+        assert_eq!(html, "<p><a href=\"https://example.com/?q=a&amp;b=&quot;c&quot;\">search</a></p>\n");
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let html = fragment(vec![Segment::Para(vec![
+            text("a"),
+            Element::Footnote(vec![text("one"), Element::Footnote(vec![text("inner")])]),
+            text("b"),
+            Element::Footnote(vec![text("two")]),
+        ])]);
+        assert_eq!(html, "<p>a<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1\">1</a></sup>\
+                          b<sup class=\"footnote-ref\"><a href=\"#fn-2\" id=\"fnref-2\">2</a></sup></p>\n\
+                          <section class=\"footnotes\">\n<ol>\n\
+                          <li id=\"fn-1\">one<sup class=\"footnote-ref\"><a href=\"#fn-3\" id=\"fnref-3\">3</a></sup> \
+                          <a href=\"#fnref-1\">\u{21A9}</a></li>\n\
+                          <li id=\"fn-2\">two <a href=\"#fnref-2\">\u{21A9}</a></li>\n\
+                          <li id=\"fn-3\">inner <a href=\"#fnref-3\">\u{21A9}</a></li>\n\
+                          </ol>\n</section>\n");
+    }
+
+    #[test]
+    fn test_lists_and_quotes() {
+        let html = fragment(vec![
+            Segment::List {
+                type_: ListType::Numbered,
+                elements: vec![
+                    Segment::Para(vec![text("a")]),
+                    Segment::List { type_: ListType::Bulleted, elements: vec![Segment::Para(vec![text("b")])] },
+                ],
+            },
+            Segment::Quote(Box::new(Segment::Para(vec![text("q1")]))),
+            Segment::Quote(Box::new(Segment::Para(vec![text("q2")]))),
+        ]);
+        assert_eq!(html, "<ol>\n<li>a<ul>\n<li>b</li>\n</ul>\n</li>\n</ol>\n\
+                          <blockquote>\n<p>q1</p>\n<p>q2</p>\n</blockquote>\n");
+    }
+
+    #[test]
+    fn test_figure() {
+        let html = fragment(vec![Segment::Figure {
+            caption: vec![vec![Element::Emphasized(vec![text("A cat")])]],
+            source: cid(CID_A),
+            media_type: None,
+            alt: None,
+            dimensions: None,
+            size: None,
+        }]);
+        assert_eq!(html, format!("<figure>\n<img src=\"https://ipfs.io/ipfs/{}\" alt=\"A cat\">\n\
+                                  <figcaption><em>A cat</em></figcaption>\n</figure>\n", CID_A));
+    }
+
+    #[test]
+    fn test_figure_metadata() {
+        let figure = |media_type: &str, alt: &str| Segment::Figure {
+            caption: vec![],
+            source: cid(CID_A),
+            media_type: Some(media_type.to_string()),
+            alt: Some(alt.to_string()),
+            dimensions: Some(Dimensions { width: 640, height: 480 }),
+            size: Some(1024),
+        };
+        let url = format!("https://ipfs.io/ipfs/{}", CID_A);
+        assert_eq!(fragment(vec![figure("image/png", "A \"cat\"")]),
+                   format!("<figure>\n<img src=\"{}\" alt=\"A &quot;cat&quot;\" width=\"640\" height=\"480\">\n\
+                            </figure>\n", url));
+        assert_eq!(fragment(vec![figure("video/webm", "A cat")]),
+                   format!("<figure>\n<video src=\"{}\" controls aria-label=\"A cat\" width=\"640\" height=\"480\">\
+                            </video>\n</figure>\n", url));
+        assert_eq!(fragment(vec![figure("application/pdf", "")]),
+                   format!("<figure>\n<a href=\"{}\" type=\"application/pdf\">{}</a>\n</figure>\n", url, CID_A));
+    }
+
+    #[test]
+    fn test_xhtml() {
+        let figure = |media_type: &str| Segment::Figure {
+            caption: vec![vec![text("A")], vec![text("cat")]],
+            source: cid(CID_A),
+            media_type: Some(media_type.to_string()),
+            alt: None,
+            dimensions: None,
+            size: None,
+        };
+        let doc = Document::new(vec![Part::Body(vec![figure("image/png"), figure("audio/ogg")])]);
+        let url = format!("https://ipfs.io/ipfs/{}", CID_A);
+        assert_eq!(render_xhtml_fragment(&doc, &Gateway(GATEWAY.to_string())),
+                   format!("<figure>\n<img src=\"{0}\" alt=\"A cat\" />\n<figcaption>A<br />cat</figcaption>\n</figure>\n\
+                            <figure>\n<audio src=\"{0}\" controls=\"controls\" aria-label=\"A cat\"></audio>\n\
+                            <figcaption>A<br />cat</figcaption>\n</figure>\n", url));
+    }
+
+    #[test]
+    fn test_transclusion() {
+        let html = fragment(vec![Segment::Transclusion { source: cid(CID_A), fragment: Some("intro".to_string()) }]);
+        assert_eq!(html, format!("<p class=\"transclusion\"><a href=\"https://ipfs.io/ipfs/{0}#intro\">\
+                                  https://ipfs.io/ipfs/{0}#intro</a></p>\n", CID_A));
+    }
+
+    #[test]
+    fn test_conflict() {
+        let html = fragment(vec![Segment::Conflict {
+            ours: vec![Segment::Para(vec![text("mine")])],
+            theirs: vec![],
+        }]);
+        assert_eq!(html, "<div class=\"conflict\">\n<div class=\"ours\">\n<p>mine</p>\n</div>\n\
+                          <div class=\"theirs\">\n</div>\n</div>\n");
+    }
+
+    #[test]
+    fn test_head() {
+        let mut doc = Document::new(vec![]);
+        doc.title = Some("Tom & Jerry".to_string());
+        doc.author = Some("Simon Heath".to_string());
+        doc.language = Some(LanguageTag::parse("en").unwrap());
+        doc.date = Some(Utc.with_ymd_and_hms(2017, 11, 5, 18, 30, 0).unwrap());
+        doc.local_date = Some(FixedOffset::west_opt(5 * 3600).unwrap());
+        let html = render(&doc, &Gateway(GATEWAY.to_string()));
+        assert_eq!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+                          <title>Tom &amp; Jerry</title>\n\
+                          <meta name=\"author\" content=\"Simon Heath\">\n\
+                          <meta name=\"date\" content=\"2017-11-05T13:30:00-05:00\">\n\
+                          </head>\n<body>\n<header><h1 class=\"title\">Tom &amp; Jerry</h1></header>\n\
+                          </body>\n</html>\n");
+    }
+
+    #[test]
+    fn test_everything() {
+        // Just make sure nothing falls over.
+        let html = render(&kitchen_sink(), &Gateway(GATEWAY.to_string()));
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<section class=\"footnotes\">"));
+        assert!(!html.contains("not rendered"));
+    }
+}
@@ -1,7 +1,10 @@
+use std::fmt;
 use std::time::Duration;
 use chrono::prelude::*;
 
-#[derive(Clone, PartialEq, Eq)]
+use base64;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Pubkey {
     username: Identity,
     algorithm: Algorithm,
@@ -15,34 +18,117 @@ pub struct Pubkey {
     signature: Option<Signature>,
 }
 
+impl Pubkey {
+    pub fn new(username: Identity, algorithm: Algorithm, public_key: Key, created: DateTime<Utc>) -> Pubkey {
+        Pubkey {
+            username,
+            algorithm,
+            public_key,
+            created,
+            expires: None,
+            ttl: None,
+            signature: None,
+        }
+    }
+
+    /// The same key, but only good until `expires`.
+    pub fn expiring(self, expires: DateTime<Utc>) -> Pubkey {
+        Pubkey { expires: Some(expires), ..self }
+    }
+
+    pub fn username(&self) -> &Identity {
+        &self.username
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn public_key(&self) -> &Key {
+        &self.public_key
+    }
+
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        self.expires
+    }
+
+    /// Whether the key could be used to sign something written at
+    /// `date`: from when it was created up to, but not including, when
+    /// it expires.
+    pub fn is_valid_at(&self, date: DateTime<Utc>) -> bool {
+        self.created <= date && self.expires.is_none_or(|e| date < e)
+    }
+}
+
 /// A user identity, such as icefox@alopex.li
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Identity {
     username: String,
     authority: String,
 }
 
+impl Identity {
+    pub fn new(username: &str, authority: &str) -> Identity {
+        Identity {
+            username: username.to_string(),
+            authority: authority.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.username, self.authority)
+    }
+}
+
 /// A base64 encoded string of a key
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Key(String);
 
+impl Key {
+    pub fn from_bytes(bytes: &[u8]) -> Key {
+        Key(base64::encode(bytes))
+    }
+
+    /// The raw key, or `None` if it isn't valid base64.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        base64::decode(&self.0)
+    }
+}
+
 /// A base64 encoded signature for the message
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Signature(String);
 
-#[derive(Clone, PartialEq, Eq)]
+impl Signature {
+    pub fn from_bytes(bytes: &[u8]) -> Signature {
+        Signature(base64::encode(bytes))
+    }
+
+    /// The raw signature, or `None` if it isn't valid base64.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        base64::decode(&self.0)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Algorithm {
     Ed25519,
     // Maybe others later
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct PubkeyRequest {
     username: String,
     query: Option<Query>
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Query {
     Before(DateTime<Utc>),
     After(DateTime<Utc>),
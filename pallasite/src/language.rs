@@ -0,0 +1,168 @@
+//! BCP 47 language tags, like `en`, `en-US` or `zh-Hant-TW`.
+
+use std::error;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The first subtag isn't a language, like the `english` in
+    /// `english-US`.
+    Primary(String),
+    /// A later subtag is empty, too long, or has something other than
+    /// letters and digits in it, or is a singleton like the `a` in
+    /// `en-a` with nothing after it.
+    Subtag(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Primary(ref s) => write!(f, "{:?} is not a primary language subtag", s),
+            Error::Subtag(ref s) => write!(f, "invalid subtag {:?}", s),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A language tag.  Only the syntax is checked, not that the subtags
+/// are actually registered.  It's kept exactly as written; tags are
+/// case insensitive, so compare them with `matches()`.
+///
+/// Tags made with `parse()` are well-formed.  Ones read from documents
+/// might not be, since documents written before tags were checked can
+/// have anything there, and they still have to decode to the same
+/// bytes.  `Document::validate()` reports those.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LanguageTag(String);
+
+impl LanguageTag {
+    pub fn parse(s: &str) -> Result<LanguageTag, Error> {
+        let mut subtags = s.split('-');
+        let primary = subtags.next().unwrap_or("");
+        let primary_ok = match primary.len() {
+            // Private use and grandfathered tags, which need something
+            // after them: `x-klingon`, not just `x`.
+            1 => (primary.eq_ignore_ascii_case("x") || primary.eq_ignore_ascii_case("i"))
+                && s.len() > 1,
+            2..=8 => primary.chars().all(|c| c.is_ascii_alphabetic()),
+            _ => false,
+        };
+        if !primary_ok {
+            return Err(Error::Primary(primary.to_string()));
+        }
+        // Singletons start an extension or private use section, which
+        // can't be empty.  Private use subtags can be single characters
+        // themselves, so they're left alone.
+        let mut private = primary.eq_ignore_ascii_case("x");
+        let mut subtags = subtags.peekable();
+        while let Some(subtag) = subtags.next() {
+            if subtag.is_empty() || subtag.len() > 8 || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+                || (subtag.len() == 1 && !private && subtags.peek().is_none()) {
+                return Err(Error::Subtag(subtag.to_string()));
+            }
+            private = private || subtag.eq_ignore_ascii_case("x");
+        }
+        Ok(LanguageTag(s.to_string()))
+    }
+
+    /// Keeps the tag as written whether it's well-formed or not.
+    pub fn lossy(s: &str) -> LanguageTag {
+        LanguageTag(s.to_string())
+    }
+
+    pub fn is_well_formed(&self) -> bool {
+        LanguageTag::parse(&self.0).is_ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The language without any script, region or the like, in lower
+    /// case: `en` for `en-US`.
+    pub fn primary(&self) -> String {
+        self.0.split('-').next().unwrap_or("").to_ascii_lowercase()
+    }
+
+    /// Whether two tags are the same, ignoring case.
+    pub fn matches(&self, other: &LanguageTag) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for LanguageTag {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for LanguageTag {
+    fn deserialize<D>(deserializer: D) -> Result<LanguageTag, D::Error>
+        where D: Deserializer<'de>
+    {
+        String::deserialize(deserializer).map(LanguageTag)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for good in &["en", "en-US", "zh-Hant-TW", "sgn-BE-FR", "x-klingon", "X-Klingon", "de-CH-1901",
+                      "en-a-bbb", "en-x-a", "x-a-b", "de-x-phonebk-1"] {
+            assert_eq!(LanguageTag::parse(good).unwrap().as_str(), *good);
+        }
+        assert_eq!(LanguageTag::parse(""), Err(Error::Primary("".to_string())));
+        assert_eq!(LanguageTag::parse("e"), Err(Error::Primary("e".to_string())));
+        assert_eq!(LanguageTag::parse("x"), Err(Error::Primary("x".to_string())));
+        assert_eq!(LanguageTag::parse("I"), Err(Error::Primary("I".to_string())));
+        assert_eq!(LanguageTag::parse("toolongtag"), Err(Error::Primary("toolongtag".to_string())));
+        assert_eq!(LanguageTag::parse("en_US"), Err(Error::Primary("en_US".to_string())));
+        assert_eq!(LanguageTag::parse("english please"), Err(Error::Primary("english please".to_string())));
+        assert_eq!(LanguageTag::parse("english-"), Err(Error::Subtag("".to_string())));
+        assert_eq!(LanguageTag::parse("en--US"), Err(Error::Subtag("".to_string())));
+        assert_eq!(LanguageTag::parse("en-abcdefghi"), Err(Error::Subtag("abcdefghi".to_string())));
+        assert_eq!(LanguageTag::parse("en-a"), Err(Error::Subtag("a".to_string())));
+        assert_eq!(LanguageTag::parse("en-US-x"), Err(Error::Subtag("x".to_string())));
+        assert_eq!(LanguageTag::parse("i-1"), Err(Error::Subtag("1".to_string())));
+    }
+
+    #[test]
+    fn test_case() {
+        let a = LanguageTag::parse("EN-us").unwrap();
+        let b = LanguageTag::parse("en-US").unwrap();
+        assert_ne!(a, b);
+        assert!(a.matches(&b));
+        assert_eq!(a.primary(), "en");
+    }
+
+    #[test]
+    fn test_serde() {
+        let tag = LanguageTag::parse("pt-BR").unwrap();
+        assert_eq!(serde_json::to_string(&tag).unwrap(), r#""pt-BR""#);
+        assert_eq!(serde_json::from_str::<LanguageTag>(r#""pt-BR""#).unwrap(), tag);
+        assert!(tag.is_well_formed());
+
+        // Kept as written, so documents with them still decode.
+        let bad = serde_json::from_str::<LanguageTag>(r#""not a language""#).unwrap();
+        assert_eq!(bad, LanguageTag::lossy("not a language"));
+        assert!(!bad.is_well_formed());
+        assert_eq!(serde_json::to_string(&bad).unwrap(), r#""not a language""#);
+    }
+}
@@ -0,0 +1,655 @@
+//! Rendering documents as LaTeX, for printing.
+//!
+//! The output is a complete `article` that builds with `pdflatex`,
+//! needing only packages from a standard TeX Live install.  Like the
+//! HTML renderer, it never lets the document's text be read as markup:
+//! everything is escaped, and equations are only passed through if
+//! they stick to the TeX subset `math` understands.
+//!
+//! Figures are included from files named by `figure_file()`, which
+//! whoever runs LaTeX has to put next to the `.tex` file.
+
+use std::fmt::Write;
+
+use cid::Cid;
+
+use document::{plain_text, Document, Element, ListType, Part, Segment};
+use html::Linker;
+use math;
+
+/// Languages the `listings` package knows, by the name people usually
+/// give them and the name `listings` wants.  Asking `listings` for any
+/// other language is an error, so those get no highlighting.
+const LISTINGS_LANGUAGES: &[(&str, &str)] = &[
+    ("ada", "Ada"), ("bash", "bash"), ("c", "C"), ("c++", "C++"), ("cpp", "C++"),
+    ("erlang", "erlang"), ("fortran", "Fortran"), ("haskell", "Haskell"), ("html", "HTML"),
+    ("java", "Java"), ("lisp", "Lisp"), ("lua", "Lua"), ("pascal", "Pascal"), ("perl", "Perl"),
+    ("php", "PHP"), ("python", "Python"), ("r", "R"), ("ruby", "Ruby"), ("sh", "sh"),
+    ("sql", "SQL"), ("tex", "TeX"), ("xml", "XML"),
+];
+
+/// Escapes text so LaTeX prints it as it is.
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            // Brackets can be taken as optional arguments, after `\\` or `\item`.
+            '[' => out.push_str("{[}"),
+            ']' => out.push_str("{]}"),
+            // Newlines don't mean anything in a paragraph, but two in a
+            // row would end it.
+            '\n' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The name a figure's file should have, with an extension LaTeX will
+/// recognize, or `None` if LaTeX can't include that sort of file.
+/// Figures with no media type are taken to be PNG images.
+pub fn figure_file(source: &Cid, media_type: Option<&str>) -> Option<String> {
+    let extension = match media_type.map(|t| t.split(';').next().unwrap_or("").trim().to_ascii_lowercase()) {
+        None => "png",
+        Some(ref t) if t == "image/png" => "png",
+        Some(ref t) if t == "image/jpeg" => "jpg",
+        Some(ref t) if t == "application/pdf" => "pdf",
+        Some(_) => return None,
+    };
+    Some(format!("{}.{}", source, extension))
+}
+
+/// Renders a complete LaTeX file, with the document's metadata on the
+/// title page.
+pub fn render(doc: &Document, linker: &dyn Linker) -> String {
+    let mut out = String::new();
+    out.push_str("\\documentclass{article}\n\
+                  \\usepackage[utf8]{inputenc}\n\
+                  \\usepackage[T1]{fontenc}\n\
+                  \\usepackage{amsmath}\n\
+                  \\usepackage{graphicx}\n\
+                  \\usepackage{listings}\n\
+                  \\usepackage[normalem]{ulem}\n\
+                  \\usepackage{hyperref}\n\
+                  \\lstset{basicstyle=\\ttfamily\\small, breaklines=true}\n");
+    if let Some(title) = doc.title.as_ref().or(doc.subject.as_ref()) {
+        writeln!(out, "\\title{{{}}}", escape(title)).unwrap();
+    }
+    let author = doc.author.clone().or_else(|| doc.author_id.as_ref().map(|a| a.to_string()));
+    if let Some(author) = author {
+        writeln!(out, "\\author{{{}}}", escape(&author)).unwrap();
+    }
+    // Without this LaTeX uses the day it was run.
+    match doc.local_date_time() {
+        Some(date) => writeln!(out, "\\date{{{}}}", date.format("%Y-%m-%d")).unwrap(),
+        None => out.push_str("\\date{}\n"),
+    }
+    out.push_str("\\begin{document}\n");
+    if doc.title.is_some() || doc.subject.is_some() {
+        out.push_str("\\maketitle\n");
+    }
+    out.push_str(&render_fragment(doc, linker));
+    out.push_str("\\end{document}\n");
+    out
+}
+
+/// Renders just the body of a document, for including in some other
+/// LaTeX file with the same packages loaded.
+pub fn render_fragment(doc: &Document, linker: &dyn Linker) -> String {
+    let mut r = Renderer {
+        linker,
+        out: String::new(),
+    };
+    for part in &doc.contents {
+        r.part(part);
+    }
+    r.out
+}
+
+struct Renderer<'a> {
+    linker: &'a dyn Linker,
+    out: String,
+}
+
+impl<'a> Renderer<'a> {
+    fn part(&mut self, part: &Part) {
+        match *part {
+            Part::Body(ref segments) => self.segments(segments),
+            Part::Section { level, ref heading, ref contents } => {
+                let command = match level {
+                    0 | 1 => "section",
+                    2 => "subsection",
+                    3 => "subsubsection",
+                    4 => "paragraph",
+                    _ => "subparagraph",
+                };
+                write!(self.out, "\\{}{{", command).unwrap();
+                self.elements(heading);
+                self.out.push_str("}\n\n");
+                self.segments(contents);
+            }
+        }
+    }
+
+    fn segments(&mut self, segments: &[Segment]) {
+        let mut i = 0;
+        while i < segments.len() {
+            // Runs of quotes are one quotation with several paragraphs,
+            // and runs of abstracts one abstract.
+            match segments[i] {
+                Segment::Quote(_) => {
+                    self.out.push_str("\\begin{quote}\n");
+                    while let Some(Segment::Quote(s)) = segments.get(i) {
+                        self.segment(s);
+                        i += 1;
+                    }
+                    self.out.push_str("\\end{quote}\n\n");
+                }
+                Segment::Abstract(_) => {
+                    self.out.push_str("\\begin{abstract}\n");
+                    while let Some(Segment::Abstract(e)) = segments.get(i) {
+                        self.elements(e);
+                        self.out.push_str("\n\n");
+                        i += 1;
+                    }
+                    self.out.push_str("\\end{abstract}\n\n");
+                }
+                _ => {
+                    self.segment(&segments[i]);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    fn segment(&mut self, segment: &Segment) {
+        match *segment {
+            Segment::Para(ref e) => {
+                self.elements(e);
+                self.out.push_str("\n\n");
+            }
+            Segment::Abstract(_) | Segment::Quote(_) => self.segments(std::slice::from_ref(segment)),
+            Segment::Table { ref header, ref body, ref footer } => {
+                let columns = body.iter().map(|r| r.len())
+                    .chain([header.len(), footer.len()])
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                writeln!(self.out, "\\begin{{center}}\n\\begin{{tabular}}{{|{}}}\n\\hline", "l|".repeat(columns))
+                    .unwrap();
+                if !header.is_empty() {
+                    self.row(header.iter().map(|c| Cell::Elements(c)), true);
+                    self.out.push_str("\\hline\n");
+                }
+                for row in body {
+                    self.row(row.iter().map(Cell::Segment), false);
+                }
+                if !footer.is_empty() {
+                    self.out.push_str("\\hline\n");
+                    self.row(footer.iter().map(|c| Cell::Elements(c)), false);
+                }
+                self.out.push_str("\\hline\n\\end{tabular}\n\\end{center}\n\n");
+            }
+            Segment::Figure { ref caption, ref source, ref media_type, .. } => {
+                self.out.push_str("\\begin{figure}[htbp]\n\\centering\n");
+                match figure_file(source, media_type.as_ref().map(|t| &**t)) {
+                    Some(file) => writeln!(self.out, "\\includegraphics[width=\\linewidth,keepaspectratio]{{{}}}", file),
+                    // Something LaTeX can't include, like a video
+                    None => writeln!(self.out, "\\url{{{}}}", url_escape(&self.linker.url(source))),
+                }.unwrap();
+                if !caption.is_empty() {
+                    // The short form goes in the list of figures, which
+                    // can't have footnotes in it.
+                    let short: Vec<String> = caption.iter().map(|c| escape(&plain_text(c))).collect();
+                    write!(self.out, "\\caption[{}]{{", short.join(" ")).unwrap();
+                    for (i, c) in caption.iter().enumerate() {
+                        if i > 0 {
+                            self.out.push_str("\\\\ ");
+                        }
+                        self.elements(c);
+                    }
+                    self.out.push_str("}\n");
+                }
+                self.out.push_str("\\end{figure}\n\n");
+            }
+            Segment::List { ref type_, ref elements } => {
+                let env = match *type_ {
+                    ListType::Bulleted => "itemize",
+                    ListType::Numbered => "enumerate",
+                };
+                writeln!(self.out, "\\begin{{{}}}", env).unwrap();
+                let mut open = false;
+                for e in elements {
+                    match *e {
+                        // A list following an item is a sublist of that item.
+                        Segment::List { .. } if open => self.segment(e),
+                        // LaTeX won't have a list with no items before it.
+                        Segment::List { .. } => {
+                            self.out.push_str("\\item[]\n");
+                            self.segment(e);
+                        }
+                        _ => {
+                            self.out.push_str("\\item ");
+                            self.cell(e);
+                            self.out.push('\n');
+                            open = true;
+                        }
+                    }
+                }
+                writeln!(self.out, "\\end{{{}}}\n", env).unwrap();
+            }
+            Segment::Code { ref language, ref contents } => {
+                let language = language.as_ref().and_then(|l| {
+                    let l = l.to_ascii_lowercase();
+                    LISTINGS_LANGUAGES.iter().find(|&&(name, _)| name == l).map(|&(_, name)| name)
+                });
+                let contents = contents.strip_suffix('\n').unwrap_or(contents);
+                if contents.contains("\\end{lstlisting}") {
+                    // The listing would end early, so this one has to be escaped instead.
+                    self.out.push_str("\\begin{flushleft}\\ttfamily\\small\n");
+                    let lines: Vec<String> = contents.split('\n')
+                        .map(|l| escape(l).replace(' ', "~"))
+                        .collect();
+                    self.out.push_str(&lines.join("\\\\\n"));
+                    self.out.push_str("\n\\end{flushleft}\n\n");
+                } else {
+                    match language {
+                        Some(l) => writeln!(self.out, "\\begin{{lstlisting}}[language={}]", l),
+                        None => writeln!(self.out, "\\begin{{lstlisting}}"),
+                    }.unwrap();
+                    writeln!(self.out, "{}\n\\end{{lstlisting}}\n", contents).unwrap();
+                }
+            }
+            Segment::Math(ref tex) => match safe_math(tex) {
+                Some(tex) => writeln!(self.out, "\\[ {} \\]\n", tex).unwrap(),
+                // Better to show the source than nothing at all.
+                None => writeln!(self.out, "\\begin{{center}}\\texttt{{{}}}\\end{{center}}\n", escape(tex)).unwrap(),
+            },
+            // One that hasn't been expanded, or couldn't be.
+            Segment::Transclusion { ref source, ref fragment } => {
+                let mut url = self.linker.url(source);
+                if let Some(ref f) = *fragment {
+                    url.push('#');
+                    url.push_str(f);
+                }
+                writeln!(self.out, "\\url{{{}}}\n", url_escape(&url)).unwrap();
+            }
+            Segment::Conflict { ref ours, ref theirs } => {
+                // Like the markers git leaves behind
+                self.out.push_str("\\noindent\\texttt{<<<<<<< ours}\n\n");
+                self.segments(ours);
+                self.out.push_str("\\noindent\\texttt{=======}\n\n");
+                self.segments(theirs);
+                self.out.push_str("\\noindent\\texttt{>>>>>>> theirs}\n\n");
+            }
+        }
+    }
+
+    fn row<'c, I>(&mut self, cells: I, bold: bool)
+        where I: Iterator<Item = Cell<'c>>
+    {
+        for (i, cell) in cells.enumerate() {
+            if i > 0 {
+                self.out.push_str(" & ");
+            }
+            match cell {
+                Cell::Elements(e) if bold => {
+                    self.out.push_str("\\textbf{");
+                    self.elements(e);
+                    self.out.push('}');
+                }
+                Cell::Elements(e) => self.elements(e),
+                Cell::Segment(s) => self.cell(s),
+            }
+        }
+        self.out.push_str(" \\\\\n");
+    }
+
+    /// Segments inside table cells and list items.  A lone paragraph is
+    /// just its text; anything else gets a box of its own, since table
+    /// cells can't hold paragraphs.
+    fn cell(&mut self, segment: &Segment) {
+        match *segment {
+            Segment::Para(ref e) => self.elements(e),
+            _ => {
+                self.out.push_str("\\begin{minipage}[t]{0.4\\linewidth}\n");
+                self.segment(segment);
+                self.out.push_str("\\end{minipage}");
+            }
+        }
+    }
+
+    fn wrap(&mut self, command: &str, elements: &[Element]) {
+        write!(self.out, "\\{}{{", command).unwrap();
+        self.elements(elements);
+        self.out.push('}');
+    }
+
+    fn elements(&mut self, elements: &[Element]) {
+        for e in elements {
+            self.element(e);
+        }
+    }
+
+    fn element(&mut self, element: &Element) {
+        match *element {
+            Element::Text(ref s) => self.out.push_str(&escape(s)),
+            Element::Strong(ref e) => self.wrap("textbf", e),
+            Element::Emphasized(ref e) => self.wrap("emph", e),
+            Element::Footnote(ref e) => self.wrap("footnote", e),
+            Element::Xref { ref contents, ref target, ref fragment } => {
+                let mut url = self.linker.url(target);
+                if let Some(ref f) = *fragment {
+                    url.push('#');
+                    url.push_str(f);
+                }
+                write!(self.out, "\\href{{{}}}{{", url_escape(&url)).unwrap();
+                self.elements(contents);
+                self.out.push('}');
+            }
+            Element::Link { ref contents, ref url } => {
+                write!(self.out, "\\href{{{}}}{{", url_escape(url.as_str())).unwrap();
+                self.elements(contents);
+                self.out.push('}');
+            }
+            Element::Span { ref contents, .. } => self.elements(contents),
+            Element::Subscript(ref e) => self.wrap("textsubscript", e),
+            Element::Superscript(ref e) => self.wrap("textsuperscript", e),
+            Element::Insertion(ref e) => self.wrap("uline", e),
+            Element::Deletion(ref e) => self.wrap("sout", e),
+            Element::Preformatted(ref e) => self.wrap("texttt", e),
+            Element::Math(ref tex) => match safe_math(tex) {
+                Some(tex) => write!(self.out, "\\({}\\)", tex).unwrap(),
+                None => write!(self.out, "\\texttt{{{}}}", escape(tex)).unwrap(),
+            },
+            // Comments are notes for authors, not for readers.
+            Element::Comment(_) => (),
+            Element::Anchor(ref name) => write!(self.out, "\\hypertarget{{{}}}{{}}", anchor_name(name)).unwrap(),
+        }
+    }
+}
+
+enum Cell<'a> {
+    Elements(&'a [Element]),
+    Segment(&'a Segment),
+}
+
+/// The TeX source of an equation, if it's safe to hand to LaTeX as it
+/// is.  `math` already rejects commands it doesn't know, but it lets
+/// anything through inside `\text{}`, or takes the character after
+/// `\text` without braces as the text even when it starts another
+/// command, and treats characters like `%`
+/// and `&` as plain symbols where LaTeX wouldn't.  TeX also reads `^^`
+/// followed by two hex digits as the character with that code, which
+/// could spell out any command at all.
+fn safe_math(tex: &str) -> Option<&str> {
+    math::to_mathml(tex, false).ok()?;
+    if tex.contains(['%', '#', '$', '&', '~']) || tex.contains("^^") {
+        return None;
+    }
+    for command in &["\\text", "\\mathrm"] {
+        for (i, _) in tex.match_indices(command) {
+            let group = tex[i + command.len()..].trim_start().strip_prefix('{')?;
+            let inside = group.split('}').next().unwrap_or("");
+            if inside.contains(['\\', '{', '^', '_']) {
+                return None;
+            }
+        }
+    }
+    Some(tex)
+}
+
+/// Escapes a URL for `\href` and `\url`, which take most characters
+/// as they are.  `^` is percent-encoded instead, since inside another
+/// command's argument TeX reads `^^5c` as a backslash.
+fn url_escape(url: &str) -> String {
+    let mut out = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '^' => out.push_str("\\%5E"),
+            '\\' | '#' | '%' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Anchor names with anything but letters, digits and a few safe
+/// punctuation marks replaced, since they end up in PDF internals.
+fn anchor_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.:".contains(c) { c } else { '-' })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use chrono::prelude::*;
+
+    use document::tests::{cid, text, CID_A, CID_B};
+    use document::*;
+    use html::Gateway;
+    use url::Url;
+    use super::*;
+
+    fn para(s: &str) -> Segment {
+        Segment::Para(vec![text(s)])
+    }
+
+    /// Compares the output with `testdata/latex/<name>.tex`.  Run the
+    /// tests with `PALLASITE_BLESS=1` to write the files instead, after
+    /// checking that the new output is right.
+    fn golden(name: &str, doc: &Document, full: bool) {
+        let gateway = Gateway("https://ipfs.io".to_string());
+        let latex = if full { render(doc, &gateway) } else { render_fragment(doc, &gateway) };
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testdata", "latex", &format!("{}.tex", name)]
+            .iter().collect();
+        if env::var_os("PALLASITE_BLESS").is_some() {
+            fs::write(&path, &latex).unwrap();
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert_eq!(latex, expected, "output differs from {}", path.display());
+    }
+
+    fn body(segments: Vec<Segment>) -> Document {
+        Document::new(vec![Part::Body(segments)])
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("50% of $5 & #1_a {b} ~^\\ [x]"),
+                   "50\\% of \\$5 \\& \\#1\\_a \\{b\\} \\textasciitilde{}\\textasciicircum{}\\textbackslash{} {[}x{]}");
+        assert_eq!(url_escape("https://example.com/a%20b#c"), "https://example.com/a\\%20b\\#c");
+        assert_eq!(url_escape("https://x.example/^^5c"), "https://x.example/\\%5E\\%5E5c");
+    }
+
+    #[test]
+    fn test_url_character_codes() {
+        let evil = "^^5cinput^^7b/etc/passwd^^7d";
+        let link = Element::Link {
+            contents: vec![text("a")],
+            url: Url::parse(&format!("https://x.example/{}", evil)).unwrap(),
+        };
+        let xref = Element::Xref { contents: vec![text("b")], target: cid(CID_A), fragment: Some(evil.to_string()) };
+        let doc = body(vec![Segment::Para(vec![
+            Element::Footnote(vec![link.clone(), xref.clone()]),
+            Element::Strong(vec![link, xref]),
+        ])]);
+        let latex = render_fragment(&doc, &Gateway("https://ipfs.io".to_string()));
+        assert!(!latex.contains('^'), "{}", latex);
+        assert_eq!(latex.matches("\\%5E\\%5E5cinput\\%5E\\%5E7b/etc/passwd\\%5E\\%5E7d}").count(), 4, "{}", latex);
+    }
+
+    #[test]
+    fn test_safe_math() {
+        assert_eq!(safe_math("\\frac{a}{b}"), Some("\\frac{a}{b}"));
+        assert_eq!(safe_math("\\text{if } x"), Some("\\text{if } x"));
+        assert_eq!(safe_math("\\text{\\input{/etc/passwd}}"), None);
+        assert_eq!(safe_math("x % y"), None);
+        assert_eq!(safe_math("\\begin{x}"), None);
+        assert_eq!(safe_math("x^^5cinput^^7b/etc/passwd^^7d"), None);
+        assert_eq!(safe_math("\\text\\input{/etc/passwd}"), None);
+        assert_eq!(safe_math("\\mathrm x"), None);
+        assert_eq!(safe_math("e^{x^2}"), Some("e^{x^2}"));
+    }
+
+    #[test]
+    fn test_figure_file() {
+        assert_eq!(figure_file(&cid(CID_A), None), Some(format!("{}.png", CID_A)));
+        assert_eq!(figure_file(&cid(CID_A), Some("image/JPEG")), Some(format!("{}.jpg", CID_A)));
+        assert_eq!(figure_file(&cid(CID_A), Some("video/mp4")), None);
+    }
+
+    #[test]
+    fn test_golden_sections() {
+        golden("sections", &Document::new(vec![
+            Part::Body(vec![para("Before any section.")]),
+            Part::Section { level: 1, heading: vec![text("Intro")], contents: vec![para("One.")] },
+            Part::Section {
+                level: 2,
+                heading: vec![Element::Anchor("why it's so".to_string()), text("Why & how")],
+                contents: vec![para("Two.")],
+            },
+            Part::Section { level: 3, heading: vec![text("Three")], contents: vec![] },
+            Part::Section { level: 4, heading: vec![text("Four")], contents: vec![] },
+            Part::Section { level: 7, heading: vec![text("Seven")], contents: vec![] },
+        ]), false);
+    }
+
+    #[test]
+    fn test_golden_abstract() {
+        golden("abstract", &body(vec![
+            Segment::Abstract(vec![text("We show that "), Element::Emphasized(vec![text("things")]), text(" happen.")]),
+            Segment::Abstract(vec![text("Also, 100% of them.")]),
+            para("The paper itself."),
+        ]), false);
+    }
+
+    #[test]
+    fn test_golden_inline() {
+        golden("inline", &body(vec![Segment::Para(vec![
+            Element::Strong(vec![text("Bold")]),
+            text(", "),
+            Element::Emphasized(vec![text("emphasis")]),
+            text(", H"),
+            Element::Subscript(vec![text("2")]),
+            text("O, x"),
+            Element::Superscript(vec![text("2")]),
+            text(", "),
+            Element::Insertion(vec![text("added")]),
+            text(", "),
+            Element::Deletion(vec![text("removed")]),
+            text(", "),
+            Element::Preformatted(vec![text("code_name")]),
+            text(", "),
+            Element::Math("\\alpha^2".to_string()),
+            text(", "),
+            Element::Math("50\\%".to_string()),
+            text(", "),
+            Element::Xref { contents: vec![text("a doc")], target: cid(CID_A), fragment: Some("intro".to_string()) },
+            text(" and "),
+            Element::Link { contents: vec![text("a site")], url: Url::parse("https://example.com/a%20b").unwrap() },
+            Element::Comment("not printed".to_string()),
+            text("."),
+        ])]), false);
+    }
+
+    #[test]
+    fn test_golden_footnotes() {
+        golden("footnotes", &body(vec![Segment::Para(vec![
+            text("A claim."),
+            Element::Footnote(vec![text("Citation needed, see "), Element::Emphasized(vec![text("Smith")]), text(".")]),
+            text(" Another."),
+            Element::Footnote(vec![text("$5 & change")]),
+        ])]), false);
+    }
+
+    #[test]
+    fn test_golden_table() {
+        golden("table", &body(vec![Segment::Table {
+            header: vec![vec![text("Name")], vec![text("Cost")]],
+            body: vec![
+                vec![para("Tea"), para("$1")],
+                vec![para("Cake"), Segment::List {
+                    type_: ListType::Bulleted,
+                    elements: vec![para("small: $2"), para("big: $4")],
+                }],
+            ],
+            footer: vec![vec![text("Total")], vec![text("$7")]],
+        }]), false);
+    }
+
+    #[test]
+    fn test_golden_code() {
+        golden("code", &body(vec![
+            Segment::Code { language: Some("Python".to_string()), contents: "print(\"100%\")\n".to_string() },
+            Segment::Code { language: Some("rust".to_string()), contents: "fn main() {}\n".to_string() },
+            Segment::Code { language: None, contents: "\\end{lstlisting}\n  \\input{x}".to_string() },
+            Segment::Math("\\sum_{i=0}^n i".to_string()),
+        ]), false);
+    }
+
+    #[test]
+    fn test_golden_quotes_and_lists() {
+        golden("quotes_and_lists", &body(vec![
+            Segment::Quote(Box::new(para("To be,"))),
+            Segment::Quote(Box::new(para("or not to be."))),
+            Segment::List {
+                type_: ListType::Numbered,
+                elements: vec![
+                    para("First"),
+                    Segment::List { type_: ListType::Bulleted, elements: vec![para("[nested]")] },
+                    para("Second"),
+                ],
+            },
+        ]), false);
+    }
+
+    #[test]
+    fn test_golden_figures() {
+        golden("figures", &body(vec![
+            Segment::Figure {
+                caption: vec![vec![text("A "), Element::Emphasized(vec![text("cat")])], vec![text("Photo: me")]],
+                source: cid(CID_A),
+                media_type: Some("image/jpeg".to_string()),
+                alt: Some("A cat".to_string()),
+                dimensions: None,
+                size: None,
+            },
+            Segment::Figure {
+                caption: vec![],
+                source: cid(CID_B),
+                media_type: Some("video/mp4".to_string()),
+                alt: None,
+                dimensions: None,
+                size: None,
+            },
+        ]), false);
+    }
+
+    #[test]
+    fn test_golden_full() {
+        let mut doc = body(vec![para("Hello, world.")]);
+        doc.title = Some("On #1 & Others".to_string());
+        doc.author = Some("Simon Heath".to_string());
+        doc.date = Some(Utc.with_ymd_and_hms(2017, 11, 5, 18, 30, 0).unwrap());
+        doc.local_date = Some(FixedOffset::west_opt(5 * 3600).unwrap());
+        golden("full", &doc, true);
+        golden("untitled", &body(vec![para("No title.")]), true);
+    }
+}
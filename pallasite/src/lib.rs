@@ -1,11 +1,45 @@
 extern crate chrono;
 extern crate cid;
+extern crate multihash;
+extern crate pulldown_cmark;
+extern crate ring;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate untrusted;
 
+pub mod atom;
+pub mod cbor;
+pub mod diff;
 pub mod document;
+pub mod epub;
+pub mod fold;
+pub mod gemtext;
+pub mod history;
+pub mod html;
 pub mod identity;
+pub mod language;
+pub mod latex;
+pub mod markdown;
+pub mod math;
+pub mod merge;
+pub mod path;
+pub mod search;
+pub mod signing;
+pub mod store;
+pub mod text;
+pub mod threading;
+pub mod toc;
+pub mod transclude;
+pub mod url;
+pub mod validate;
+pub mod visit;
+pub mod visit_mut;
+pub mod vocabulary;
+pub mod xref;
+mod base64;
+mod serde_cid;
+mod zip;
 
 
 #[cfg(test)]
@@ -0,0 +1,869 @@
+//! Importing CommonMark (plus the usual GitHub extensions: tables,
+//! footnotes, strikethrough and task lists) as a `Document`.
+//!
+//! Markdown can say things a `Document` can't, such as raw HTML or links
+//! to relative URLs.  Those don't make it into the document; instead each
+//! one produces a `Warning` saying what was lost and where.
+
+use std::collections::HashMap;
+use std::mem;
+
+use cid::Cid;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use document::{plain_text, Document, Element, Elements, ListType, Part, Segment};
+use url::Url;
+
+/// The result of an import.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Imported {
+    pub document: Document,
+    /// Sorted by line.
+    pub warnings: Vec<Warning>,
+}
+
+/// Something in the source that couldn't be represented exactly.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Warning {
+    /// Line in the source where the construct starts, counting from 1.
+    pub line: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WarningKind {
+    /// Raw HTML, which is dropped.
+    Html(String),
+    /// A horizontal rule, which is dropped.
+    ThematicBreak,
+    /// A hard line break, which becomes a space.
+    HardBreak,
+    /// A link to something that's neither a CID nor a URL that
+    /// `Url::parse()` accepts, such as a relative path.  Only the link text is kept.
+    ExternalLink(String),
+    /// An image that isn't a CID, or isn't in a paragraph by itself.
+    /// Only the alt text is kept.
+    Image(String),
+    /// The title of a link or image, like `[a](b "title")`, which is dropped.
+    LinkTitle(String),
+    /// An ordered list that starts somewhere other than 1.
+    ListStart(u64),
+    /// A `[ ]` or `[x]` task list marker, which is dropped.
+    TaskListMarker,
+    /// A list item containing several blocks, which gets split into
+    /// several items.  Nested lists don't count.
+    SplitListItem,
+    /// A quote containing several blocks, which gets split into
+    /// several quotes.
+    SplitQuote,
+    /// Table column alignment, which is dropped.
+    TableAlignment,
+    /// A heading inside a list or quote, which becomes a paragraph.
+    NestedHeading,
+    /// A footnote reference with no matching definition.  It's kept as text.
+    UndefinedFootnote(String),
+    /// A footnote reference inside a footnote definition.  It's kept as text.
+    NestedFootnote(String),
+    /// A footnote definition that nothing refers to, which is dropped.
+    UnusedFootnote(String),
+    /// A footnote containing something other than paragraphs.
+    /// Its text is kept but its structure is flattened.
+    ComplexFootnote(String),
+}
+
+/// Parse a Markdown document.  This never fails; anything that can't
+/// be represented is reported in the warnings instead.
+pub fn parse(text: &str) -> Imported {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_SUPERSCRIPT);
+    options.insert(Options::ENABLE_SUBSCRIPT);
+    let events = Parser::new_ext(text, options)
+        .into_offset_iter()
+        .map(|(e, range)| (e, range.start))
+        .collect();
+    let mut c = Converter::new(text, events);
+    let document = c.document();
+    let mut warnings = c.warnings;
+    warnings.sort_by_key(|w| w.line);
+    Imported { document, warnings }
+}
+
+/// Like `link_target()`, but also allows a fragment naming an anchor in
+/// the document, like `ipfs://Qm...#intro`.
+pub fn link_reference(url: &str) -> Option<(Cid, Option<String>)> {
+    match url.find('#') {
+        Some(i) if i + 1 < url.len() => {
+            link_target(&url[..i]).map(|cid| (cid, Some(url[i + 1..].to_string())))
+        }
+        Some(i) => link_target(&url[..i]).map(|cid| (cid, None)),
+        None => link_target(url).map(|cid| (cid, None)),
+    }
+}
+
+/// Turns a link destination into a CID, if it is one.  Accepts a bare
+/// CID, `ipfs://<cid>`, `/ipfs/<cid>` and `dweb:/ipfs/<cid>`.
+pub fn link_target(url: &str) -> Option<Cid> {
+    let s = url.trim();
+    let s = if let Some(rest) = s.strip_prefix("ipfs://") {
+        rest
+    } else if let Some(rest) = s.strip_prefix("dweb:/ipfs/") {
+        rest
+    } else if let Some(rest) = s.strip_prefix("/ipfs/") {
+        rest
+    } else {
+        s
+    };
+    // Anything after the CID itself is a path, which we can't point at.
+    if s.is_empty() || s.contains(|c: char| !c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Cid::from(s).ok()
+}
+
+fn heading_level(level: HeadingLevel) -> u32 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Appends text, merging it with the previous element if that's text too.
+fn push_text(out: &mut Elements, s: &str) {
+    if let Some(&mut Element::Text(ref mut t)) = out.last_mut() {
+        t.push_str(s);
+        return;
+    }
+    out.push(Element::Text(s.to_string()));
+}
+
+/// Appends elements, merging text the same way as `push_text()`.
+fn push_elements(out: &mut Elements, elements: Elements) {
+    for e in elements {
+        match e {
+            Element::Text(ref t) => push_text(out, t),
+            other => out.push(other),
+        }
+    }
+}
+
+/// Is this the start of something that goes inside a paragraph?
+fn is_inline(event: &Event) -> bool {
+    matches!(*event,
+        Event::Start(Tag::Emphasis) | Event::Start(Tag::Strong) |
+        Event::Start(Tag::Strikethrough) | Event::Start(Tag::Superscript) |
+        Event::Start(Tag::Subscript) | Event::Start(Tag::Link { .. }) |
+        Event::Start(Tag::Image { .. }) |
+        Event::Text(_) | Event::Code(_) | Event::InlineHtml(_) | Event::FootnoteReference(_) |
+        Event::SoftBreak | Event::HardBreak | Event::TaskListMarker(_))
+}
+
+type Events<'a> = Vec<(Event<'a>, usize)>;
+
+struct Converter<'a> {
+    /// Each event with the byte offset it starts at
+    events: Events<'a>,
+    pos: usize,
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+    /// Footnote definitions, pulled out of the event stream
+    /// ahead of time since they can come after their references.
+    /// Keyed by `footnote_key()`, with the label as written.
+    footnote_defs: HashMap<String, (String, usize, Events<'a>)>,
+    footnotes: HashMap<String, Elements>,
+    in_footnote: bool,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(text: &str, all_events: Events<'a>) -> Converter<'a> {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+
+        let mut events = Vec::new();
+        let mut footnote_defs = HashMap::new();
+        let mut current: Option<(String, usize, Events<'a>)> = None;
+        for (event, offset) in all_events {
+            match event {
+                Event::Start(Tag::FootnoteDefinition(label)) => {
+                    current = Some((label.to_string(), offset, Vec::new()));
+                }
+                Event::End(TagEnd::FootnoteDefinition) => {
+                    if let Some((label, offset, evs)) = current.take() {
+                        footnote_defs.insert(footnote_key(&label), (label, offset, evs));
+                    }
+                }
+                other => match current {
+                    Some((_, _, ref mut evs)) => evs.push((other, offset)),
+                    None => events.push((other, offset)),
+                },
+            }
+        }
+
+        Converter {
+            events,
+            pos: 0,
+            line_starts,
+            footnote_defs,
+            footnotes: HashMap::new(),
+            in_footnote: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    fn warn(&mut self, offset: usize, kind: WarningKind) {
+        let line = self.line(offset);
+        self.warnings.push(Warning { line, kind });
+    }
+
+    fn next(&mut self) -> Option<(Event<'a>, usize)> {
+        let e = self.events.get(self.pos).cloned();
+        self.pos += 1;
+        e
+    }
+
+    fn peek(&self) -> Option<&Event<'a>> {
+        self.events.get(self.pos).map(|e| &e.0)
+    }
+
+    fn document(&mut self) -> Document {
+        let mut parts = Vec::new();
+        let mut current = Part::Body(Vec::new());
+        while let Some((event, offset)) = self.next() {
+            match event {
+                Event::Start(Tag::Heading { level, .. }) => {
+                    let heading = self.inlines();
+                    let next = Part::Section {
+                        level: heading_level(level),
+                        heading,
+                        contents: Vec::new(),
+                    };
+                    let prev = mem::replace(&mut current, next);
+                    match prev {
+                        Part::Body(ref s) if s.is_empty() => (),
+                        _ => parts.push(prev),
+                    }
+                }
+                other => {
+                    let segments = self.block(other, offset);
+                    match current {
+                        Part::Body(ref mut s) | Part::Section { contents: ref mut s, .. } => {
+                            s.extend(segments)
+                        }
+                    }
+                }
+            }
+        }
+        match current {
+            Part::Body(ref s) if s.is_empty() => (),
+            _ => parts.push(current),
+        }
+
+        let mut unused: Vec<_> = self.footnote_defs.iter()
+            .filter(|&(key, _)| !self.footnotes.contains_key(key))
+            .map(|(_, &(ref label, offset, _))| (offset, label.clone()))
+            .collect();
+        unused.sort();
+        for (offset, label) in unused {
+            self.warn(offset, WarningKind::UnusedFootnote(label));
+        }
+
+        Document::new(parts)
+    }
+
+    /// Converts blocks until the end of the enclosing block (or of the
+    /// input), consuming the end tag.
+    fn blocks(&mut self) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        loop {
+            match self.peek() {
+                None => break,
+                Some(&Event::End(_)) => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(e) if is_inline(e) => {
+                    // Tight list items have their text directly inside
+                    // the item instead of in a paragraph.
+                    let elements = self.inline_run();
+                    segments.push(Segment::Para(elements));
+                    continue;
+                }
+                _ => (),
+            }
+            let (event, offset) = self.next().unwrap();
+            segments.extend(self.block(event, offset));
+        }
+        segments
+    }
+
+    /// Converts a single block, whose start event has already been
+    /// consumed.  It may turn into any number of segments.
+    fn block(&mut self, event: Event<'a>, offset: usize) -> Vec<Segment> {
+        let tag = match event {
+            Event::Start(tag) => tag,
+            Event::Rule => {
+                self.warn(offset, WarningKind::ThematicBreak);
+                return vec![];
+            }
+            Event::Html(html) => {
+                self.warn(offset, WarningKind::Html(html.trim().to_string()));
+                return vec![];
+            }
+            _ => {
+                // Some stray inline thing; treat it as a paragraph.
+                self.pos -= 1;
+                return vec![Segment::Para(self.inline_run())];
+            }
+        };
+        match tag {
+            Tag::Paragraph => {
+                if let Some(figure) = self.figure() {
+                    return vec![figure];
+                }
+                vec![Segment::Para(self.inlines())]
+            }
+            Tag::Heading { .. } => {
+                self.warn(offset, WarningKind::NestedHeading);
+                vec![Segment::Para(self.inlines())]
+            }
+            Tag::BlockQuote(_) => {
+                let contents = self.blocks();
+                if contents.is_empty() {
+                    return vec![Segment::Quote(Box::new(Segment::Para(vec![])))];
+                }
+                if contents.len() > 1 {
+                    self.warn(offset, WarningKind::SplitQuote);
+                }
+                contents.into_iter().map(|s| Segment::Quote(Box::new(s))).collect()
+            }
+            Tag::CodeBlock(kind) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(ref info) => {
+                        info.split_whitespace().next().map(|s| s.to_string())
+                    }
+                    CodeBlockKind::Indented => None,
+                };
+                let mut contents = String::new();
+                while let Some((event, _)) = self.next() {
+                    match event {
+                        Event::Text(t) => contents.push_str(&t),
+                        Event::End(_) => break,
+                        _ => (),
+                    }
+                }
+                vec![Segment::Code { language, contents }]
+            }
+            Tag::HtmlBlock => {
+                let mut html = String::new();
+                while let Some((event, _)) = self.next() {
+                    match event {
+                        Event::Html(t) | Event::Text(t) => html.push_str(&t),
+                        Event::End(_) => break,
+                        _ => (),
+                    }
+                }
+                self.warn(offset, WarningKind::Html(html.trim().to_string()));
+                vec![]
+            }
+            Tag::List(start) => vec![self.list(start, offset)],
+            Tag::Table(alignments) => {
+                if alignments.iter().any(|a| *a != Alignment::None) {
+                    self.warn(offset, WarningKind::TableAlignment);
+                }
+                vec![self.table()]
+            }
+            _ => {
+                // Anything else is a container we don't know about;
+                // keep what's inside it.
+                self.blocks()
+            }
+        }
+    }
+
+    /// A paragraph containing nothing but an image of a CID becomes
+    /// a figure.  Otherwise leaves everything as it was.
+    fn figure(&mut self) -> Option<Segment> {
+        let start = self.pos;
+        let warnings = self.warnings.len();
+        let url = match self.next() {
+            Some((Event::Start(Tag::Image { dest_url, .. }), _)) => dest_url,
+            _ => {
+                self.pos = start;
+                return None;
+            }
+        };
+        let source = link_target(&url);
+        let alt = self.inlines();
+        match (source, self.peek()) {
+            (Some(source), Some(&Event::End(TagEnd::Paragraph))) => {
+                self.pos += 1;
+                let (caption, alt) = if alt.is_empty() {
+                    (vec![], None)
+                } else {
+                    let text = plain_text(&alt);
+                    (vec![alt], Some(text))
+                };
+                Some(Segment::Figure { caption, source, media_type: None, alt, dimensions: None, size: None })
+            }
+            _ => {
+                self.pos = start;
+                self.warnings.truncate(warnings);
+                None
+            }
+        }
+    }
+
+    fn list(&mut self, start: Option<u64>, offset: usize) -> Segment {
+        let type_ = match start {
+            Some(n) => {
+                if n != 1 {
+                    self.warn(offset, WarningKind::ListStart(n));
+                }
+                ListType::Numbered
+            }
+            None => ListType::Bulleted,
+        };
+        let mut elements = Vec::new();
+        while let Some((event, offset)) = self.next() {
+            match event {
+                Event::Start(Tag::Item) => {
+                    let mut segments = self.blocks().into_iter();
+                    elements.push(segments.next().unwrap_or_else(|| Segment::Para(vec![])));
+                    let mut split = false;
+                    for s in segments {
+                        match s {
+                            Segment::List { .. } => (),
+                            _ if !split => {
+                                self.warn(offset, WarningKind::SplitListItem);
+                                split = true;
+                            }
+                            _ => (),
+                        }
+                        elements.push(s);
+                    }
+                }
+                Event::End(_) => break,
+                _ => (),
+            }
+        }
+        Segment::List { type_, elements }
+    }
+
+    fn table(&mut self) -> Segment {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        while let Some((event, _)) = self.next() {
+            match event {
+                Event::Start(Tag::TableHead) => header = self.table_cells(),
+                Event::Start(Tag::TableRow) => {
+                    let row = self.table_cells().into_iter().map(Segment::Para).collect();
+                    body.push(row);
+                }
+                Event::End(_) => break,
+                _ => (),
+            }
+        }
+        Segment::Table { header, body, footer: vec![] }
+    }
+
+    fn table_cells(&mut self) -> Vec<Elements> {
+        let mut cells = Vec::new();
+        while let Some((event, _)) = self.next() {
+            match event {
+                Event::Start(Tag::TableCell) => cells.push(self.inlines()),
+                Event::End(_) => break,
+                _ => (),
+            }
+        }
+        cells
+    }
+
+    /// Converts inline content up to and including the end of the
+    /// enclosing tag.
+    fn inlines(&mut self) -> Elements {
+        let mut out = Vec::new();
+        while let Some((event, offset)) = self.next() {
+            if let Event::End(_) = event {
+                break;
+            }
+            self.inline(event, offset, &mut out);
+        }
+        out
+    }
+
+    /// Converts inline content up to the next thing that isn't inline.
+    fn inline_run(&mut self) -> Elements {
+        let mut out = Vec::new();
+        while self.peek().map(is_inline).unwrap_or(false) {
+            let (event, offset) = self.next().unwrap();
+            self.inline(event, offset, &mut out);
+        }
+        out
+    }
+
+    fn inline(&mut self, event: Event<'a>, offset: usize, out: &mut Elements) {
+        match event {
+            Event::Text(t) => push_text(out, &t),
+            Event::Code(t) => out.push(Element::Preformatted(vec![Element::Text(t.to_string())])),
+            Event::SoftBreak => push_text(out, " "),
+            Event::HardBreak => {
+                self.warn(offset, WarningKind::HardBreak);
+                push_text(out, " ");
+            }
+            Event::InlineHtml(html) | Event::Html(html) => {
+                self.warn(offset, WarningKind::Html(html.to_string()));
+            }
+            Event::TaskListMarker(_) => self.warn(offset, WarningKind::TaskListMarker),
+            Event::FootnoteReference(label) => {
+                let footnote = self.footnote(&label, offset);
+                match footnote {
+                    Some(e) => out.push(e),
+                    None => push_text(out, &format!("[^{}]", label)),
+                }
+            }
+            Event::Start(tag) => match tag {
+                Tag::Emphasis => out.push(Element::Emphasized(self.inlines())),
+                Tag::Strong => out.push(Element::Strong(self.inlines())),
+                Tag::Strikethrough => out.push(Element::Deletion(self.inlines())),
+                Tag::Superscript => out.push(Element::Superscript(self.inlines())),
+                Tag::Subscript => out.push(Element::Subscript(self.inlines())),
+                Tag::Link { dest_url, title, .. } => {
+                    if !title.is_empty() {
+                        self.warn(offset, WarningKind::LinkTitle(title.to_string()));
+                    }
+                    let contents = self.inlines();
+                    match link_reference(&dest_url) {
+                        Some((target, fragment)) => out.push(Element::Xref { contents, target, fragment }),
+                        None => match Url::parse(&dest_url) {
+                            Ok(url) => out.push(Element::Link { contents, url }),
+                            Err(_) => {
+                                self.warn(offset, WarningKind::ExternalLink(dest_url.to_string()));
+                                push_elements(out, contents);
+                            }
+                        },
+                    }
+                }
+                Tag::Image { dest_url, .. } => {
+                    self.warn(offset, WarningKind::Image(dest_url.to_string()));
+                    let alt = self.inlines();
+                    push_elements(out, alt);
+                }
+                _ => {
+                    let contents = self.inlines();
+                    push_elements(out, contents);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Looks up a footnote definition and converts it, if it exists.
+    fn footnote(&mut self, label: &str, offset: usize) -> Option<Element> {
+        if self.in_footnote {
+            self.warn(offset, WarningKind::NestedFootnote(label.to_string()));
+            return None;
+        }
+        let key = footnote_key(label);
+        if let Some(elements) = self.footnotes.get(&key) {
+            return Some(Element::Footnote(elements.clone()));
+        }
+        let (def_label, def_offset, events) = match self.footnote_defs.get(&key) {
+            Some(def) => def.clone(),
+            None => {
+                self.warn(offset, WarningKind::UndefinedFootnote(label.to_string()));
+                return None;
+            }
+        };
+
+        let saved_events = mem::replace(&mut self.events, events);
+        let saved_pos = mem::replace(&mut self.pos, 0);
+        self.in_footnote = true;
+        let segments = self.blocks();
+        self.in_footnote = false;
+        self.events = saved_events;
+        self.pos = saved_pos;
+
+        let mut elements = Vec::new();
+        let mut complex = false;
+        for segment in segments {
+            if !elements.is_empty() {
+                push_text(&mut elements, " ");
+            }
+            complex |= flatten(segment, &mut elements);
+        }
+        if complex {
+            self.warn(def_offset, WarningKind::ComplexFootnote(def_label));
+        }
+        self.footnotes.insert(key, elements.clone());
+        Some(Element::Footnote(elements))
+    }
+}
+
+/// What footnote labels are matched by.  CommonMark ignores case and
+/// treats any run of whitespace as a single space.  Upper-casing first
+/// folds the likes of `ß` into `ss`, as lower-casing alone wouldn't.
+fn footnote_key(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase().to_lowercase()
+}
+
+/// Flattens a segment into a run of elements.  Returns true if
+/// it was anything more complicated than a paragraph.
+fn flatten(segment: Segment, out: &mut Elements) -> bool {
+    match segment {
+        Segment::Para(e) => {
+            out.extend(e);
+            false
+        }
+        Segment::Abstract(e) => {
+            out.extend(e);
+            true
+        }
+        Segment::Code { contents, .. } => {
+            out.push(Element::Preformatted(vec![Element::Text(contents)]));
+            true
+        }
+        Segment::Math(tex) => {
+            out.push(Element::Math(tex));
+            true
+        }
+        Segment::Transclusion { source, fragment } => {
+            out.push(Element::Xref { contents: vec![Element::Text(source.to_string())], target: source, fragment });
+            true
+        }
+        Segment::Quote(s) => {
+            flatten(*s, out);
+            true
+        }
+        Segment::List { elements, .. } => {
+            for s in elements {
+                flatten(s, out);
+            }
+            true
+        }
+        Segment::Table { header, body, footer } => {
+            for e in header.into_iter().chain(footer) {
+                out.extend(e);
+            }
+            for s in body.into_iter().flatten() {
+                flatten(s, out);
+            }
+            true
+        }
+        Segment::Figure { caption, .. } => {
+            for e in caption {
+                out.extend(e);
+            }
+            true
+        }
+        Segment::Conflict { ours, theirs } => {
+            for s in ours.into_iter().chain(theirs) {
+                flatten(s, out);
+            }
+            true
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::tests::{cid, text, CID_A};
+    use document::*;
+    use super::*;
+
+    fn para(s: &str) -> Segment {
+        Segment::Para(vec![text(s)])
+    }
+
+    fn body(md: &str) -> Vec<Segment> {
+        let imported = parse(md);
+        assert_eq!(imported.warnings, vec![]);
+        match imported.document.contents.as_slice() {
+            [Part::Body(s)] => s.clone(),
+            other => panic!("expected a single body part, got {:?}", other),
+        }
+    }
+
+    fn warnings(md: &str) -> Vec<WarningKind> {
+        parse(md).warnings.into_iter().map(|w| w.kind).collect()
+    }
+
+    #[test]
+    fn test_sections() {
+        let doc = parse("intro\n\n# One\n\nfirst\n\n## Two *a*\n\nsecond\n").document;
+        assert_eq!(doc.contents, vec![
+            Part::Body(vec![para("intro")]),
+            Part::Section { level: 1, heading: vec![text("One")], contents: vec![para("first")] },
+            Part::Section {
+                level: 2,
+                heading: vec![text("Two "), Element::Emphasized(vec![text("a")])],
+                contents: vec![para("second")],
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_inline_styles() {
+        assert_eq!(body("*em* **strong** ~~del~~ `code` ^sup^ ~sub~"), vec![Segment::Para(vec![
+            Element::Emphasized(vec![text("em")]),
+            text(" "),
+            Element::Strong(vec![text("strong")]),
+            text(" "),
+            Element::Deletion(vec![text("del")]),
+            text(" "),
+            Element::Preformatted(vec![text("code")]),
+            text(" "),
+            Element::Superscript(vec![text("sup")]),
+            text(" "),
+            Element::Subscript(vec![text("sub")]),
+        ])]);
+        // Soft breaks become spaces, and text gets merged
+        assert_eq!(body("one\ntwo"), vec![para("one two")]);
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(body("```rust ignore\nfn main() {}\n```\n\n    indented\n"), vec![
+            Segment::Code { language: Some("rust".to_string()), contents: "fn main() {}\n".to_string() },
+            Segment::Code { language: None, contents: "indented\n".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(body("* a\n* b\n  1. c\n  2. d\n"), vec![Segment::List {
+            type_: ListType::Bulleted,
+            elements: vec![
+                para("a"),
+                para("b"),
+                Segment::List { type_: ListType::Numbered, elements: vec![para("c"), para("d")] },
+            ],
+        }]);
+        assert_eq!(warnings("3. a\n4. b\n"), vec![WarningKind::ListStart(3)]);
+        assert_eq!(warnings("* a\n\n  b\n"), vec![WarningKind::SplitListItem]);
+        assert_eq!(warnings("- [x] done\n"), vec![WarningKind::TaskListMarker]);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(body("> one\n> two\n"), vec![Segment::Quote(Box::new(para("one two")))]);
+        let imported = parse("> one\n>\n> two\n");
+        assert_eq!(imported.document.contents, vec![Part::Body(vec![
+            Segment::Quote(Box::new(para("one"))),
+            Segment::Quote(Box::new(para("two"))),
+        ])]);
+        assert_eq!(imported.warnings, vec![Warning { line: 1, kind: WarningKind::SplitQuote }]);
+        assert_eq!(warnings("> # heading\n"), vec![WarningKind::NestedHeading]);
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(body("| a | b |\n|---|---|\n| 1 | *2* |\n"), vec![Segment::Table {
+            header: vec![vec![text("a")], vec![text("b")]],
+            body: vec![vec![para("1"), Segment::Para(vec![Element::Emphasized(vec![text("2")])])]],
+            footer: vec![],
+        }]);
+        assert_eq!(warnings("| a |\n|--:|\n| 1 |\n"), vec![WarningKind::TableAlignment]);
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let md = "Text[^1] and more[^1].\n\n[^1]: The *note*.\n";
+        let note = Element::Footnote(vec![text("The "), Element::Emphasized(vec![text("note")]), text(".")]);
+        assert_eq!(body(md), vec![Segment::Para(vec![
+            text("Text"), note.clone(), text(" and more"), note, text("."),
+        ])]);
+
+        // References without a definition are just text as far as
+        // CommonMark is concerned.
+        let imported = parse("Missing[^x].\n\n[^y]: Unused.\n");
+        assert_eq!(imported.warnings, vec![
+            Warning { line: 3, kind: WarningKind::UnusedFootnote("y".to_string()) },
+        ]);
+        assert_eq!(imported.document.contents, vec![Part::Body(vec![para("Missing[^x].")])]);
+
+        // Labels match like CommonMark's do.
+        let md = "One[^Big  Note], two[^STRASSE].\n\n[^big note]: Big.\n\n[^straße]: Street.\n";
+        assert_eq!(body(md), vec![Segment::Para(vec![
+            text("One"), Element::Footnote(vec![text("Big.")]),
+            text(", two"), Element::Footnote(vec![text("Street.")]),
+            text("."),
+        ])]);
+    }
+
+    #[test]
+    fn test_links_and_figures() {
+        let md = format!("[there](ipfs://{})\n\n![A cat](/ipfs/{})\n", CID_A, CID_A);
+        assert_eq!(body(&md), vec![
+            Segment::Para(vec![Element::Xref { contents: vec![text("there")], target: cid(CID_A), fragment: None }]),
+            Segment::Figure {
+                caption: vec![vec![text("A cat")]],
+                source: cid(CID_A),
+                media_type: None,
+                alt: Some("A cat".to_string()),
+                dimensions: None,
+                size: None,
+            },
+        ]);
+
+        let imported = parse("[web](https://example.com) [rel](../up) [js](javascript:alert(1)) ![img](cat.png)\n");
+        assert_eq!(imported.document.contents, vec![Part::Body(vec![Segment::Para(vec![
+            Element::Link { contents: vec![text("web")], url: Url::parse("https://example.com").unwrap() },
+            text(" rel js img"),
+        ])])]);
+        assert_eq!(imported.warnings.into_iter().map(|w| w.kind).collect::<Vec<_>>(), vec![
+            WarningKind::ExternalLink("../up".to_string()),
+            WarningKind::ExternalLink("javascript:alert(1)".to_string()),
+            WarningKind::Image("cat.png".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        let imported = parse("<div>hi</div>\n\none\n\n---\n\ntwo  \nthree <b>x</b>\n");
+        assert_eq!(imported.document.contents, vec![Part::Body(vec![
+            para("one"),
+            para("two three x"),
+        ])]);
+        assert_eq!(imported.warnings, vec![
+            Warning { line: 1, kind: WarningKind::Html("<div>hi</div>".to_string()) },
+            Warning { line: 5, kind: WarningKind::ThematicBreak },
+            Warning { line: 7, kind: WarningKind::HardBreak },
+            Warning { line: 8, kind: WarningKind::Html("<b>".to_string()) },
+            Warning { line: 8, kind: WarningKind::Html("</b>".to_string()) },
+        ]);
+    }
+
+    #[test]
+    fn test_link_target() {
+        assert_eq!(link_target(CID_A), Some(cid(CID_A)));
+        assert_eq!(link_target(&format!("dweb:/ipfs/{}", CID_A)), Some(cid(CID_A)));
+        assert_eq!(link_target(&format!("/ipfs/{}/foo.png", CID_A)), None);
+        assert_eq!(link_target("http://example.com"), None);
+        assert_eq!(link_target(""), None);
+    }
+
+    #[test]
+    fn test_link_reference() {
+        assert_eq!(link_reference(&format!("ipfs://{}#intro", CID_A)),
+                   Some((cid(CID_A), Some("intro".to_string()))));
+        assert_eq!(link_reference(&format!("ipfs://{}#", CID_A)), Some((cid(CID_A), None)));
+        assert_eq!(link_reference(CID_A), Some((cid(CID_A), None)));
+        assert_eq!(link_reference("https://example.com/#intro"), None);
+    }
+}
@@ -0,0 +1,384 @@
+//! Converting TeX math to MathML, so browsers can display equations.
+//!
+//! Only a subset of TeX is understood: letters, numbers and operators,
+//! `^` and `_`, `{}` groups, `\frac`, `\sqrt`, `\text`, `\left` and
+//! `\right`, Greek letters, the usual function names and a few dozen
+//! symbols.  Anything else is an error rather than a guess.
+
+use std::error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use html::escape;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A command that isn't in the subset we know, like `\begin`.
+    UnknownCommand(String),
+    /// A `}` with no `{` before it, at this byte offset.
+    UnmatchedClose(usize),
+    /// The input ended inside a group or before a command got all its
+    /// arguments.
+    UnexpectedEnd,
+    /// `^` or `_` with nothing before it to attach to, or given twice.
+    MisplacedScript(usize),
+    /// Groups and commands nested more than `MAX_DEPTH` deep.
+    TooDeep,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownCommand(ref c) => write!(f, "unknown command \\{}", c),
+            Error::UnmatchedClose(at) => write!(f, "unmatched }} at offset {}", at),
+            Error::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Error::MisplacedScript(at) => write!(f, "misplaced superscript or subscript at offset {}", at),
+            Error::TooDeep => write!(f, "nested more than {} deep", MAX_DEPTH),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// How deeply groups and commands can nest.  Parsing recurses, so this
+/// keeps a long run of `{` from overflowing the stack.
+pub const MAX_DEPTH: usize = 100;
+
+/// Converts TeX math to a MathML `<math>` element.  Display math is
+/// set on its own line, like TeX's `$$ ... $$`.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, Error> {
+    let mut p = Parser { chars: tex.char_indices().peekable(), depth: 0 };
+    let body = p.sequence(false)?;
+    let display = if display { " display=\"block\"" } else { "" };
+    Ok(format!("<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{}>{}</math>", display, body))
+}
+
+const GREEK: &[(&str, char)] = &[
+    ("alpha", 'α'), ("beta", 'β'), ("gamma", 'γ'), ("delta", 'δ'), ("epsilon", 'ϵ'),
+    ("varepsilon", 'ε'), ("zeta", 'ζ'), ("eta", 'η'), ("theta", 'θ'), ("vartheta", 'ϑ'),
+    ("iota", 'ι'), ("kappa", 'κ'), ("lambda", 'λ'), ("mu", 'μ'), ("nu", 'ν'), ("xi", 'ξ'),
+    ("pi", 'π'), ("varpi", 'ϖ'), ("rho", 'ρ'), ("sigma", 'σ'), ("tau", 'τ'), ("upsilon", 'υ'),
+    ("phi", 'ϕ'), ("varphi", 'φ'), ("chi", 'χ'), ("psi", 'ψ'), ("omega", 'ω'),
+    ("Gamma", 'Γ'), ("Delta", 'Δ'), ("Theta", 'Θ'), ("Lambda", 'Λ'), ("Xi", 'Ξ'), ("Pi", 'Π'),
+    ("Sigma", 'Σ'), ("Upsilon", 'Υ'), ("Phi", 'Φ'), ("Psi", 'Ψ'), ("Omega", 'Ω'),
+];
+
+/// Symbols that are identifiers rather than operators.
+const IDENTIFIERS: &[(&str, char)] = &[
+    ("infty", '∞'), ("partial", '∂'), ("nabla", '∇'), ("hbar", 'ℏ'), ("ell", 'ℓ'),
+    ("emptyset", '∅'),
+];
+
+const OPERATORS: &[(&str, char)] = &[
+    ("times", '×'), ("cdot", '⋅'), ("div", '÷'), ("pm", '±'), ("mp", '∓'), ("ast", '∗'),
+    ("leq", '≤'), ("le", '≤'), ("geq", '≥'), ("ge", '≥'), ("neq", '≠'), ("ne", '≠'),
+    ("approx", '≈'), ("equiv", '≡'), ("sim", '∼'), ("propto", '∝'),
+    ("to", '→'), ("rightarrow", '→'), ("leftarrow", '←'), ("Rightarrow", '⇒'),
+    ("Leftarrow", '⇐'), ("Leftrightarrow", '⇔'), ("mapsto", '↦'),
+    ("in", '∈'), ("notin", '∉'), ("subset", '⊂'), ("subseteq", '⊆'), ("supset", '⊃'),
+    ("cup", '∪'), ("cap", '∩'), ("setminus", '∖'), ("forall", '∀'), ("exists", '∃'),
+    ("neg", '¬'), ("land", '∧'), ("lor", '∨'), ("circ", '∘'),
+    ("sum", '∑'), ("prod", '∏'), ("int", '∫'), ("oint", '∮'),
+    ("ldots", '…'), ("cdots", '⋯'), ("langle", '⟨'), ("rangle", '⟩'),
+    ("{", '{'), ("}", '}'), ("|", '‖'),
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh",
+    "tanh", "log", "ln", "lg", "exp", "lim", "max", "min", "sup", "inf", "det", "gcd", "deg",
+];
+
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"), (":", "0.2222em"), (";", "0.2778em"), (" ", "0.25em"),
+    ("quad", "1em"), ("qquad", "2em"),
+];
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    /// How many atoms we're inside.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Atoms up to the end of the input, or the `}` ending a group.
+    /// Wraps them in an `<mrow>` if there's more than one.
+    fn sequence(&mut self, in_group: bool) -> Result<String, Error> {
+        let mut atoms: Vec<Atom> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let (at, c) = match self.chars.peek() {
+                Some(&(at, c)) => (at, c),
+                None if in_group => return Err(Error::UnexpectedEnd),
+                None => break,
+            };
+            match c {
+                '}' if in_group => {
+                    self.chars.next();
+                    break;
+                }
+                '}' => return Err(Error::UnmatchedClose(at)),
+                '^' | '_' => {
+                    self.chars.next();
+                    let slot = match atoms.last_mut() {
+                        Some(a) if c == '^' => &mut a.sup,
+                        Some(a) => &mut a.sub,
+                        None => return Err(Error::MisplacedScript(at)),
+                    };
+                    if slot.is_some() {
+                        return Err(Error::MisplacedScript(at));
+                    }
+                    *slot = Some(self.argument()?);
+                }
+                _ => {
+                    let base = self.atom()?;
+                    atoms.push(Atom { base, sub: None, sup: None });
+                }
+            }
+        }
+        let mut atoms: Vec<String> = atoms.into_iter().map(Atom::render).collect();
+        Ok(if atoms.len() == 1 {
+            atoms.remove(0)
+        } else {
+            format!("<mrow>{}</mrow>", atoms.concat())
+        })
+    }
+
+    /// A single atom, such as a number, a command or a group.
+    fn atom(&mut self) -> Result<String, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.depth += 1;
+        let atom = self.nested_atom();
+        self.depth -= 1;
+        atom
+    }
+
+    fn nested_atom(&mut self) -> Result<String, Error> {
+        let (_, c) = self.chars.next().ok_or(Error::UnexpectedEnd)?;
+        Ok(match c {
+            '{' => self.sequence(true)?,
+            '\\' => self.command()?,
+            '0'..='9' | '.' => {
+                let mut n = c.to_string();
+                while let Some(&(_, d)) = self.chars.peek() {
+                    if d.is_ascii_digit() || d == '.' {
+                        n.push(d);
+                        self.chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                format!("<mn>{}</mn>", n)
+            }
+            '\'' => "<mo>′</mo>".to_string(),
+            c if c.is_alphabetic() => format!("<mi>{}</mi>", c),
+            c => format!("<mo>{}</mo>", escape(&c.to_string())),
+        })
+    }
+
+    /// Whatever follows a `\`.
+    fn command(&mut self) -> Result<String, Error> {
+        let name = self.command_name()?;
+        let name = &*name;
+        if let Some(&(_, c)) = GREEK.iter().chain(IDENTIFIERS).find(|&&(n, _)| n == name) {
+            return Ok(format!("<mi>{}</mi>", c));
+        }
+        if let Some(&(_, c)) = OPERATORS.iter().find(|&&(n, _)| n == name) {
+            return Ok(format!("<mo>{}</mo>", escape(&c.to_string())));
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok(format!("<mi>{}</mi>", name));
+        }
+        if let Some(&(_, w)) = SPACES.iter().find(|&&(n, _)| n == name) {
+            return Ok(format!("<mspace width=\"{}\"/>", w));
+        }
+        match name {
+            "frac" => {
+                let num = self.argument()?;
+                let den = self.argument()?;
+                Ok(format!("<mfrac>{}{}</mfrac>", num, den))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if let Some(&(_, '[')) = self.chars.peek() {
+                    self.chars.next();
+                    let index = self.until(']')?;
+                    let mut p = Parser { chars: index.char_indices().peekable(), depth: self.depth };
+                    let index = p.sequence(false)?;
+                    let radicand = self.argument()?;
+                    Ok(format!("<mroot>{}{}</mroot>", radicand, index))
+                } else {
+                    Ok(format!("<msqrt>{}</msqrt>", self.argument()?))
+                }
+            }
+            "text" | "mathrm" => {
+                self.skip_whitespace();
+                match self.chars.next() {
+                    Some((_, '{')) => Ok(format!("<mtext>{}</mtext>", escape(&self.until('}')?))),
+                    Some((_, c)) => Ok(format!("<mtext>{}</mtext>", escape(&c.to_string()))),
+                    None => Err(Error::UnexpectedEnd),
+                }
+            }
+            "left" | "right" => {
+                self.skip_whitespace();
+                let delim = match self.chars.next() {
+                    Some((_, '\\')) => {
+                        let name = self.command_name()?;
+                        OPERATORS.iter().find(|&&(n, _)| n == name)
+                            .map(|&(_, c)| c.to_string())
+                            .ok_or(Error::UnknownCommand(name))?
+                    }
+                    // `\left.` means no delimiter at all
+                    Some((_, '.')) => String::new(),
+                    Some((_, c)) => c.to_string(),
+                    None => return Err(Error::UnexpectedEnd),
+                };
+                Ok(format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(&delim)))
+            }
+            _ => Err(Error::UnknownCommand(name.to_string())),
+        }
+    }
+
+    /// A run of letters, or a single other character.
+    fn command_name(&mut self) -> Result<String, Error> {
+        let (_, c) = self.chars.next().ok_or(Error::UnexpectedEnd)?;
+        let mut name = c.to_string();
+        if c.is_ascii_alphabetic() {
+            while let Some(&(_, c)) = self.chars.peek() {
+                if !c.is_ascii_alphabetic() {
+                    break;
+                }
+                name.push(c);
+                self.chars.next();
+            }
+        }
+        Ok(name)
+    }
+
+    /// The argument of a command or script: a group or a single atom.
+    /// As in TeX, a number only counts as its first digit, so `x^23`
+    /// is x squared times 3.
+    fn argument(&mut self) -> Result<String, Error> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some(&(_, d)) if d.is_ascii_digit() => {
+                self.chars.next();
+                Ok(format!("<mn>{}</mn>", d))
+            }
+            _ => self.atom(),
+        }
+    }
+
+    /// Raw text up to the given character, which is skipped.
+    fn until(&mut self, end: char) -> Result<String, Error> {
+        let mut s = String::new();
+        for (_, c) in self.chars.by_ref() {
+            if c == end {
+                return Ok(s);
+            }
+            s.push(c);
+        }
+        Err(Error::UnexpectedEnd)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+}
+
+/// Something with optional subscripts and superscripts, as MathML.
+struct Atom {
+    base: String,
+    sub: Option<String>,
+    sup: Option<String>,
+}
+
+impl Atom {
+    fn render(self) -> String {
+        match (self.sub, self.sup) {
+            (None, None) => self.base,
+            (Some(sub), None) => format!("<msub>{}{}</msub>", self.base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", self.base, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", self.base, sub, sup),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(tex: &str) -> String {
+        let m = to_mathml(tex, false).unwrap();
+        let start = m.find('>').unwrap() + 1;
+        m[start..m.len() - "</math>".len()].to_string()
+    }
+
+    #[test]
+    fn test_wrapper() {
+        assert_eq!(to_mathml("x", true).unwrap(),
+                   "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><mi>x</mi></math>");
+        assert_eq!(to_mathml("", false).unwrap(),
+                   "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow></mrow></math>");
+    }
+
+    #[test]
+    fn test_basics() {
+        assert_eq!(inner("x + 1.5 < y"), "<mrow><mi>x</mi><mo>+</mo><mn>1.5</mn><mo>&lt;</mo><mi>y</mi></mrow>");
+        assert_eq!(inner("\\alpha \\leq \\infty"), "<mrow><mi>α</mi><mo>≤</mo><mi>∞</mi></mrow>");
+        assert_eq!(inner("\\sin x"), "<mrow><mi>sin</mi><mi>x</mi></mrow>");
+        assert_eq!(inner("a\\,b"), "<mrow><mi>a</mi><mspace width=\"0.1667em\"/><mi>b</mi></mrow>");
+        assert_eq!(inner("\\text{if } x"), "<mrow><mtext>if </mtext><mi>x</mi></mrow>");
+    }
+
+    #[test]
+    fn test_scripts() {
+        assert_eq!(inner("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(inner("x^23"), "<mrow><msup><mi>x</mi><mn>2</mn></msup><mn>3</mn></mrow>");
+        assert_eq!(inner("x_{i+1}"), "<msub><mi>x</mi><mrow><mi>i</mi><mo>+</mo><mn>1</mn></mrow></msub>");
+        assert_eq!(inner("x_i^2"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+        assert_eq!(inner("x^2_i"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+        assert_eq!(inner("{a^b}^c"), "<msup><msup><mi>a</mi><mi>b</mi></msup><mi>c</mi></msup>");
+        assert_eq!(inner("\\sum_{n=1}^\\infty"),
+                   "<msubsup><mo>∑</mo><mrow><mi>n</mi><mo>=</mo><mn>1</mn></mrow><mi>∞</mi></msubsup>");
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(inner("\\frac{1}{2}"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+        assert_eq!(inner("\\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+        assert_eq!(inner("\\sqrt{x}"), "<msqrt><mi>x</mi></msqrt>");
+        assert_eq!(inner("\\sqrt[3]{x}"), "<mroot><mi>x</mi><mn>3</mn></mroot>");
+        assert_eq!(inner("\\left( x \\right)"),
+                   "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi>\
+                    <mo fence=\"true\" stretchy=\"true\">)</mo></mrow>");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(to_mathml("\\begin{matrix}", false), Err(Error::UnknownCommand("begin".to_string())));
+        assert_eq!(to_mathml("x}", false), Err(Error::UnmatchedClose(1)));
+        assert_eq!(to_mathml("{x", false), Err(Error::UnexpectedEnd));
+        assert_eq!(to_mathml("\\frac{1}", false), Err(Error::UnexpectedEnd));
+        assert_eq!(to_mathml("^2", false), Err(Error::MisplacedScript(0)));
+        assert_eq!(to_mathml("x^2^3", false), Err(Error::MisplacedScript(3)));
+    }
+
+    #[test]
+    fn test_depth() {
+        let nested = |n| format!("{}x{}", "{".repeat(n), "}".repeat(n));
+        assert_eq!(inner(&nested(MAX_DEPTH - 1)), "<mi>x</mi>");
+        assert_eq!(to_mathml(&nested(MAX_DEPTH), false), Err(Error::TooDeep));
+        assert_eq!(to_mathml(&"{".repeat(200_000), false), Err(Error::TooDeep));
+        assert_eq!(to_mathml(&format!("{}x", "\\sqrt".repeat(200_000)), false), Err(Error::TooDeep));
+        assert_eq!(to_mathml(&format!("\\sqrt[{}]x", "{".repeat(200_000)), true), Err(Error::TooDeep));
+    }
+}
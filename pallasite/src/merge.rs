@@ -0,0 +1,429 @@
+//! Three-way merges of forked revisions.
+//!
+//! Two revisions are compared against the latest revision they have in
+//! common.  Anything only one side changed is taken from that side.
+//! Where both sides changed the same segments in different ways, both
+//! versions go into a `Segment::Conflict` for someone to sort out.
+
+use std::error;
+use std::fmt;
+
+use cid::Cid;
+
+use diff::{align, parts_match, segments_match, Align};
+use document::{Document, Element, Part, Segment};
+use history::{self, History};
+use path::{Path, Step};
+use store::{self, Lookup};
+use visit::{self, Visit};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    Store(store::Error),
+    /// Part of the history is its own ancestor.
+    Cycle(Cid),
+    /// The two revisions don't share any history.
+    NoCommonAncestor,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Store(ref e) => write!(f, "{}", e),
+            Error::Cycle(ref cid) => write!(f, "revision {} is its own ancestor", cid),
+            Error::NoCommonAncestor => write!(f, "revisions have no common ancestor"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<store::Error> for Error {
+    fn from(e: store::Error) -> Error {
+        Error::Store(e)
+    }
+}
+
+impl From<history::Error> for Error {
+    fn from(e: history::Error) -> Error {
+        match e {
+            history::Error::Store(e) => Error::Store(e),
+            history::Error::Cycle(cid) => Error::Cycle(cid),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Merged {
+    pub document: Document,
+    /// Where the conflicts are: `Segment::Conflict`s in the document, or
+    /// metadata fields that both sides changed.  For conflicting fields,
+    /// the document has our value.
+    pub conflicts: Vec<Path>,
+}
+
+impl Merged {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merges two revisions, finding their common ancestor by following
+/// `previous_revisions` back through `store`.  The result lists both
+/// revisions as its previous revisions.
+///
+/// The whole history has to be there, or we might pick the wrong ancestor.
+pub fn merge<L>(store: &L, ours: &Cid, theirs: &Cid) -> Result<Merged, Error>
+    where L: Lookup + ?Sized
+{
+    let history = History::load_all(store, &[ours.clone(), theirs.clone()])?;
+    if let Some(cid) = history.missing().first() {
+        return Err(Error::Store(store::Error::Missing(cid.clone())));
+    }
+    let base = history.merge_base(ours, theirs).ok_or(Error::NoCommonAncestor)?;
+    let document = |cid| history.document(cid).expect("history has every revision");
+    let mut merged = merge3(document(base), document(ours), document(theirs));
+    merged.document.previous_revisions = Some(vec![ours.clone(), theirs.clone()]);
+    Ok(merged)
+}
+
+/// Merges `ours` and `theirs`, which both came from `base`.
+///
+/// The merged document has no date or previous revisions; it's up to
+/// whoever publishes it to fill those in.
+pub fn merge3(base: &Document, ours: &Document, theirs: &Document) -> Merged {
+    let mut conflicts = Vec::new();
+    let contents = merge_parts(&base.contents, &ours.contents, &theirs.contents);
+    let mut document = Document {
+        contents,
+        date: None,
+        local_date: None,
+        previous_revisions: None,
+        ..ours.clone()
+    };
+
+    macro_rules! field {
+        ($name:ident) => {
+            match pick(&base.$name, &ours.$name, &theirs.$name) {
+                Some(v) => document.$name = v.clone(),
+                None => conflicts.push(Path(vec![Step::Field(stringify!($name))])),
+            }
+        }
+    }
+    field!(title);
+    field!(author);
+    field!(author_id);
+    field!(subject);
+    field!(tags);
+    field!(in_response_to);
+    field!(reply_to);
+    field!(language);
+
+    let mut finder = ConflictFinder(&mut conflicts);
+    finder.visit_document(&mut Path::new(), &document);
+    Merged { document, conflicts }
+}
+
+/// The merged value, or None if both sides changed it differently.
+fn pick<'a, T: PartialEq>(base: &'a T, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if ours == base || ours == theirs {
+        Some(theirs)
+    } else if theirs == base {
+        Some(ours)
+    } else {
+        None
+    }
+}
+
+/// A stretch of three lists lined up against each other.
+enum Chunk<'a, T: 'a> {
+    /// Something all three have.
+    Stable(&'a T),
+    /// Something changed between stable points.
+    Changed {
+        base: &'a [T],
+        ours: &'a [T],
+        theirs: &'a [T],
+    },
+}
+
+/// Splits the lists into stretches where nobody changed anything and
+/// stretches where somebody did.
+fn diff3<'a, T: PartialEq>(base: &'a [T], ours: &'a [T], theirs: &'a [T]) -> Vec<Chunk<'a, T>> {
+    let matches = |other: &[T]| {
+        let mut m = vec![None; base.len()];
+        for a in align(base, other, |x, y| x == y) {
+            if let Align::Both(i, j) = a {
+                m[i] = Some(j);
+            }
+        }
+        m
+    };
+    let in_ours = matches(ours);
+    let in_theirs = matches(theirs);
+
+    let mut out = Vec::new();
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        let stable = (i..base.len()).find(|&x| in_ours[x].is_some() && in_theirs[x].is_some());
+        let (bi, oj, tk) = match stable {
+            Some(x) => (x, in_ours[x].unwrap(), in_theirs[x].unwrap()),
+            None => (base.len(), ours.len(), theirs.len()),
+        };
+        if bi > i || oj > j || tk > k {
+            out.push(Chunk::Changed {
+                base: &base[i..bi],
+                ours: &ours[j..oj],
+                theirs: &theirs[k..tk],
+            });
+        }
+        if stable.is_none() {
+            break;
+        }
+        out.push(Chunk::Stable(&ours[oj]));
+        i = bi + 1;
+        j = oj + 1;
+        k = tk + 1;
+    }
+    out
+}
+
+fn merge_parts(base: &[Part], ours: &[Part], theirs: &[Part]) -> Vec<Part> {
+    let mut out = Vec::new();
+    for chunk in diff3(base, ours, theirs) {
+        match chunk {
+            Chunk::Stable(p) => out.push(p.clone()),
+            Chunk::Changed { base, ours, theirs } => {
+                if let Some(resolved) = pick(&base, &ours, &theirs) {
+                    out.extend(resolved.iter().cloned());
+                } else if base.len() == 1 && ours.len() == 1 && theirs.len() == 1 &&
+                          parts_match(&base[0], &ours[0]) && parts_match(&base[0], &theirs[0]) {
+                    out.push(merge_part(&base[0], &ours[0], &theirs[0]));
+                } else {
+                    out.push(Part::Body(vec![Segment::Conflict {
+                        ours: flatten(ours),
+                        theirs: flatten(theirs),
+                    }]));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Merges two parts of the same kind.
+fn merge_part(base: &Part, ours: &Part, theirs: &Part) -> Part {
+    match (base, ours, theirs) {
+        (Part::Body(b), Part::Body(o), Part::Body(t)) => Part::Body(merge_segments(b, o, t)),
+        (Part::Section { heading: bh, contents: b, .. },
+         Part::Section { level, heading: oh, contents: o },
+         Part::Section { heading: th, contents: t, .. }) => {
+            let mut contents = merge_segments(b, o, t);
+            let heading = match pick(bh, oh, th) {
+                Some(h) => h.clone(),
+                None => {
+                    contents.insert(0, Segment::Conflict {
+                        ours: vec![Segment::Para(oh.clone())],
+                        theirs: vec![Segment::Para(th.clone())],
+                    });
+                    oh.clone()
+                }
+            };
+            Part::Section { level: *level, heading, contents }
+        }
+        _ => unreachable!("parts_match() said these parts are the same kind"),
+    }
+}
+
+fn merge_segments(base: &[Segment], ours: &[Segment], theirs: &[Segment]) -> Vec<Segment> {
+    let mut out = Vec::new();
+    for chunk in diff3(base, ours, theirs) {
+        match chunk {
+            Chunk::Stable(s) => out.push(s.clone()),
+            Chunk::Changed { base, ours, theirs } => {
+                if let Some(resolved) = pick(&base, &ours, &theirs) {
+                    out.extend(resolved.iter().cloned());
+                    continue;
+                }
+                if base.len() == 1 && ours.len() == 1 && theirs.len() == 1 {
+                    if let Some(s) = merge_segment(&base[0], &ours[0], &theirs[0]) {
+                        out.push(s);
+                        continue;
+                    }
+                }
+                out.push(Segment::Conflict { ours: ours.to_vec(), theirs: theirs.to_vec() });
+            }
+        }
+    }
+    out
+}
+
+/// Merges the insides of segments that hold other segments.
+fn merge_segment(base: &Segment, ours: &Segment, theirs: &Segment) -> Option<Segment> {
+    if !segments_match(base, ours) || !segments_match(base, theirs) {
+        return None;
+    }
+    match (base, ours, theirs) {
+        (Segment::Quote(b), Segment::Quote(o), Segment::Quote(t)) => {
+            merge_segment(b, o, t).map(|s| Segment::Quote(Box::new(s)))
+        }
+        (Segment::List { elements: b, .. },
+         Segment::List { type_, elements: o },
+         Segment::List { elements: t, .. }) => Some(Segment::List {
+            type_: type_.clone(),
+            elements: merge_segments(b, o, t),
+        }),
+        _ => None,
+    }
+}
+
+/// Squashes parts into segments so they fit in a `Segment::Conflict`.
+/// Section headings become bold paragraphs.
+fn flatten(parts: &[Part]) -> Vec<Segment> {
+    let mut out = Vec::new();
+    for part in parts {
+        match *part {
+            Part::Body(ref segments) => out.extend(segments.iter().cloned()),
+            Part::Section { ref heading, ref contents, .. } => {
+                if !heading.is_empty() {
+                    out.push(Segment::Para(vec![Element::Strong(heading.clone())]));
+                }
+                out.extend(contents.iter().cloned());
+            }
+        }
+    }
+    out
+}
+
+struct ConflictFinder<'a>(&'a mut Vec<Path>);
+
+impl<'a, 'ast> Visit<'ast> for ConflictFinder<'a> {
+    fn visit_segment(&mut self, path: &mut Path, node: &'ast Segment) {
+        if let Segment::Conflict { .. } = *node {
+            self.0.push(path.clone());
+        }
+        visit::visit_segment(self, path, node);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::*;
+    use language::LanguageTag;
+    use path::{Path, Step};
+    use store::MemoryStore;
+    use validate::DiagnosticKind;
+    use super::*;
+
+    fn para(s: &str) -> Segment {
+        Segment::Para(vec![Element::Text(s.to_string())])
+    }
+
+    fn body(paras: &[&str]) -> Document {
+        Document::new(vec![Part::Body(paras.iter().map(|s| para(s)).collect())])
+    }
+
+    #[test]
+    fn test_clean_merge() {
+        let base = body(&["a", "b", "c", "d"]);
+        let ours = body(&["a2", "b", "c", "d"]);
+        let theirs = body(&["a", "b", "d", "e"]);
+        let merged = merge3(&base, &ours, &theirs);
+        assert!(merged.is_clean());
+        assert_eq!(merged.document.contents, body(&["a2", "b", "d", "e"]).contents);
+    }
+
+    #[test]
+    fn test_same_change() {
+        let base = body(&["a", "b"]);
+        let ours = body(&["a", "c"]);
+        let merged = merge3(&base, &ours, &ours);
+        assert!(merged.is_clean());
+        assert_eq!(merged.document.contents, ours.contents);
+    }
+
+    #[test]
+    fn test_conflict() {
+        let base = body(&["a", "b", "c"]);
+        let ours = body(&["a", "ours", "c"]);
+        let theirs = body(&["a", "theirs", "c"]);
+        let merged = merge3(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts, vec![Path(vec![Step::Part(0), Step::Segment(1)])]);
+        assert_eq!(merged.document.contents, vec![Part::Body(vec![
+            para("a"),
+            Segment::Conflict { ours: vec![para("ours")], theirs: vec![para("theirs")] },
+            para("c"),
+        ])]);
+        let problems = merged.document.validate();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, DiagnosticKind::UnresolvedConflict);
+    }
+
+    #[test]
+    fn test_sections() {
+        let section = |heading: &str, contents: Vec<Segment>| Part::Section {
+            level: 1,
+            heading: vec![Element::Text(heading.to_string())],
+            contents,
+        };
+        let base = Document::new(vec![section("Intro", vec![para("a"), para("b")])]);
+        let ours = Document::new(vec![section("Introduction", vec![para("a"), para("b")])]);
+        let theirs = Document::new(vec![section("Intro", vec![para("a"), para("b"), para("c")])]);
+        let merged = merge3(&base, &ours, &theirs);
+        assert!(merged.is_clean());
+        assert_eq!(merged.document.contents,
+                   vec![section("Introduction", vec![para("a"), para("b"), para("c")])]);
+
+        let theirs = Document::new(vec![section("Preface", vec![para("a")])]);
+        let merged = merge3(&base, &ours, &theirs);
+        assert_eq!(merged.document.contents, vec![section("Introduction", vec![
+            Segment::Conflict { ours: vec![para("Introduction")], theirs: vec![para("Preface")] },
+            para("a"),
+        ])]);
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut base = body(&["a"]);
+        base.title = Some("Title".to_string());
+        let mut ours = base.clone();
+        ours.title = Some("Our title".to_string());
+        ours.author = Some("Us".to_string());
+        let mut theirs = base.clone();
+        theirs.title = Some("Their title".to_string());
+        theirs.language = Some(LanguageTag::parse("en").unwrap());
+        let merged = merge3(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts, vec![Path(vec![Step::Field("title")])]);
+        assert_eq!(merged.document.title, Some("Our title".to_string()));
+        assert_eq!(merged.document.author, Some("Us".to_string()));
+        assert_eq!(merged.document.language, Some(LanguageTag::parse("en").unwrap()));
+    }
+
+    #[test]
+    fn test_merge_from_store() {
+        let mut store = MemoryStore::new();
+        let root = body(&["a", "b", "c"]);
+        let root_cid = store.put_document(&root);
+        let child = |store: &mut MemoryStore, parent: &Cid, paras: &[&str]| {
+            let mut doc = body(paras);
+            doc.previous_revisions = Some(vec![parent.clone()]);
+            store.put_document(&doc)
+        };
+        let base = child(&mut store, &root_cid, &["a", "b", "c", "d"]);
+        let ours = child(&mut store, &base, &["A", "b", "c", "d"]);
+        let ours = child(&mut store, &ours, &["A", "B", "c", "d"]);
+        let theirs = child(&mut store, &base, &["a", "b", "c", "D"]);
+
+        let merged = merge(&store, &ours, &theirs).unwrap();
+        assert!(merged.is_clean());
+        assert_eq!(merged.document.contents, body(&["A", "B", "c", "D"]).contents);
+        assert_eq!(merged.document.previous_revisions, Some(vec![ours.clone(), theirs.clone()]));
+
+        let stranger = store.put_document(&body(&["x"]));
+        assert_eq!(merge(&store, &ours, &stranger), Err(Error::NoCommonAncestor));
+
+        store.remove(&base);
+        assert_eq!(merge(&store, &ours, &theirs), Err(Error::Store(store::Error::Missing(base))));
+    }
+}
@@ -0,0 +1,99 @@
+//! Paths pointing at a particular spot in a document tree.
+
+use std::fmt;
+
+/// One step on the way down from a `Document` to something inside it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Step {
+    /// A named field, such as the document's `language` or a figure's `source`.
+    Field(&'static str),
+    /// An entry in a list-valued field, such as `previous_revisions`.
+    Index(usize),
+    /// An entry in `Document::contents`.
+    Part(usize),
+    /// The heading of a `Part::Section`.
+    Heading,
+    /// A segment in a part or list, or a cell in a table row.
+    Segment(usize),
+    /// A header cell of a table.
+    TableHeader(usize),
+    /// A row in a table body; the cells are `Segment`s.
+    TableRow(usize),
+    /// A footer cell of a table.
+    TableFooter(usize),
+    /// One of the captions of a figure.
+    Caption(usize),
+    /// The segment inside a `Segment::Quote`.
+    Quote,
+    /// A segment on our side of a `Segment::Conflict`.
+    Ours(usize),
+    /// A segment on their side of a `Segment::Conflict`.
+    Theirs(usize),
+    /// An element in a run of elements.
+    Element(usize),
+}
+
+/// Where something is in a document, as a list of steps from the top.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Path(pub Vec<Step>);
+
+impl Path {
+    pub fn new() -> Path {
+        Path(Vec::new())
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.0.push(step);
+    }
+
+    pub fn pop(&mut self) -> Option<Step> {
+        self.0.pop()
+    }
+
+    /// A new path one step further down.
+    pub fn join(&self, step: Step) -> Path {
+        let mut p = self.clone();
+        p.push(step);
+        p
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Step::Field(name) => write!(f, "{}", name),
+            Step::Index(i) => write!(f, "[{}]", i),
+            Step::Part(i) => write!(f, "part[{}]", i),
+            Step::Heading => write!(f, "heading"),
+            Step::Segment(i) => write!(f, "segment[{}]", i),
+            Step::TableHeader(i) => write!(f, "header[{}]", i),
+            Step::TableRow(i) => write!(f, "row[{}]", i),
+            Step::TableFooter(i) => write!(f, "footer[{}]", i),
+            Step::Caption(i) => write!(f, "caption[{}]", i),
+            Step::Quote => write!(f, "quote"),
+            Step::Ours(i) => write!(f, "ours[{}]", i),
+            Step::Theirs(i) => write!(f, "theirs[{}]", i),
+            Step::Element(i) => write!(f, "element[{}]", i),
+        }
+    }
+}
+
+/// Like `part[1]/segment[0]/element[3]`.  The empty path is `/`.
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "/");
+        }
+        for (i, step) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
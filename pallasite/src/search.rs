@@ -0,0 +1,557 @@
+//! Full-text search over documents.
+//!
+//! An inverted index from words to the documents (and fields, and
+//! positions) they appear in, keyed by CID.  Documents never change once
+//! they have a CID, so the index only ever grows.
+//!
+//! Queries are words and `"quoted phrases"`, all of which have to match.
+//! Either can be limited to one field with a prefix like `author:` or
+//! `language:`.  Results are ranked by tf-idf, with matches in the title
+//! counting for more than matches in the body.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path as FsPath;
+use std::slice;
+
+use cid::Cid;
+
+use cbor;
+use document::{Document, Element, Segment};
+use language::LanguageTag;
+use path::Path;
+use visit::{self, Visit};
+
+/// Version of the on-disk index format.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Declared in the order a document's fields are indexed, which is the
+/// order postings for one document are kept in.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Subject,
+    Author,
+    Language,
+    /// All the text in the contents, including code.
+    Body,
+}
+
+impl Field {
+    /// The field named by a query prefix like `author:`.
+    pub fn from_name(name: &str) -> Option<Field> {
+        match &*name.to_lowercase() {
+            "title" => Some(Field::Title),
+            "subject" => Some(Field::Subject),
+            "author" => Some(Field::Author),
+            "language" | "lang" => Some(Field::Language),
+            "body" => Some(Field::Body),
+            _ => None,
+        }
+    }
+
+    /// How much a match in this field counts for.
+    fn weight(self) -> f64 {
+        match self {
+            Field::Title => 3.0,
+            Field::Subject | Field::Author => 2.0,
+            Field::Language | Field::Body => 1.0,
+        }
+    }
+}
+
+/// Unqualified words are looked for in all of these.
+const DEFAULT_FIELDS: &[Field] = &[Field::Title, Field::Subject, Field::Author, Field::Body];
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(cbor::Error),
+    /// The index was written by a different version of this code.
+    Version(u32),
+    /// The index decoded, but doesn't make sense, like a posting for a
+    /// document that isn't there.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Decode(ref e) => write!(f, "corrupt index: {}", e),
+            Error::Version(v) => write!(f, "unsupported index version {}", v),
+            Error::Corrupt(s) => write!(f, "corrupt index: {}", s),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Splits text into lowercase words.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// One part of a query.  More than one word makes it a phrase.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Clause {
+    pub field: Option<Field>,
+    pub words: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+impl Query {
+    /// Parses something like `author:simon "revision history" merge`.
+    /// Prefixes that aren't field names are searched for like any other
+    /// word, and an unclosed quote runs to the end.
+    pub fn parse(s: &str) -> Query {
+        let mut clauses = Vec::new();
+        let mut rest = s.trim_start();
+        while !rest.is_empty() {
+            let mut field = None;
+            if let Some(colon) = rest.find(':') {
+                let name = &rest[..colon];
+                if !name.contains(char::is_whitespace) && !name.contains('"') {
+                    field = Field::from_name(name);
+                    if field.is_some() {
+                        rest = &rest[colon + 1..];
+                    }
+                }
+            }
+            let text;
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                text = &quoted[..end];
+                rest = quoted.get(end + 1..).unwrap_or("");
+            } else {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                text = &rest[..end];
+                rest = &rest[end..];
+            }
+            let words = tokenize(text);
+            if !words.is_empty() {
+                clauses.push(Clause { field, words });
+            }
+            rest = rest.trim_start();
+        }
+        Query { clauses }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hit {
+    pub cid: Cid,
+    pub score: f64,
+}
+
+/// Where a word shows up in one field of one document.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Posting {
+    doc: u32,
+    field: Field,
+    positions: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    version: u32,
+    /// CIDs of the indexed documents.  A document's number is its
+    /// position in this list.
+    docs: Vec<String>,
+    /// Postings for each word, in order of document number and then
+    /// field, with positions in order.
+    terms: HashMap<String, Vec<Posting>>,
+    #[serde(skip)]
+    numbers: HashMap<String, u32>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index {
+            version: INDEX_VERSION,
+            ..Index::default()
+        }
+    }
+
+    /// Reads an index written by `save()`.
+    pub fn open(path: &FsPath) -> Result<Index, Error> {
+        let bytes = fs::read(path)?;
+        let mut index: Index = cbor::from_slice(&bytes).map_err(Error::Decode)?;
+        if index.version != INDEX_VERSION {
+            return Err(Error::Version(index.version));
+        }
+        index.numbers = index.docs.iter().enumerate().map(|(i, c)| (c.clone(), i as u32)).collect();
+        index.check().map_err(Error::Corrupt)?;
+        Ok(index)
+    }
+
+    /// Makes sure a loaded index is something `add()` could have made,
+    /// so searching it can't go wrong.
+    fn check(&self) -> Result<(), &'static str> {
+        if self.numbers.len() != self.docs.len() {
+            return Err("the same document is in it twice");
+        }
+        if self.docs.iter().any(|c| Cid::from(&**c).is_err()) {
+            return Err("invalid CID");
+        }
+        for postings in self.terms.values() {
+            if postings.iter().any(|p| p.doc as usize >= self.docs.len()) {
+                return Err("posting for a document that isn't there");
+            }
+            if postings.windows(2).any(|w| (w[0].doc, w[0].field) >= (w[1].doc, w[1].field)) {
+                return Err("postings out of order");
+            }
+            if postings.iter().any(|p| p.positions.is_empty() || p.positions.windows(2).any(|w| w[0] >= w[1])) {
+                return Err("positions out of order");
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the index to disk.  The old file stays intact until the
+    /// new one is completely written.
+    pub fn save(&self, path: &FsPath) -> io::Result<()> {
+        let bytes = cbor::to_vec(self).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        {
+            let mut f = fs::File::create(&tmp)?;
+            f.write_all(&bytes)?;
+            f.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.numbers.contains_key(&cid.to_string())
+    }
+
+    /// Adds a document to the index.  Returns false if it was already there.
+    pub fn add(&mut self, cid: &Cid, doc: &Document) -> bool {
+        let key = cid.to_string();
+        if self.numbers.contains_key(&key) {
+            return false;
+        }
+        let n = self.docs.len() as u32;
+        self.docs.push(key.clone());
+        self.numbers.insert(key, n);
+
+        let mut body = BodyText { words: Vec::new(), next: 0 };
+        body.visit_document(&mut Path::new(), doc);
+        let fields = vec![
+            (Field::Title, positioned(doc.title.as_deref())),
+            (Field::Subject, positioned(doc.subject.as_deref())),
+            (Field::Author, positioned(doc.author.as_deref())),
+            (Field::Language, positioned(doc.language.as_ref().map(LanguageTag::as_str))),
+            (Field::Body, body.words),
+        ];
+        for (field, words) in fields {
+            let mut by_term: HashMap<String, Vec<u32>> = HashMap::new();
+            for (word, pos) in words {
+                by_term.entry(word).or_default().push(pos);
+            }
+            for (term, positions) in by_term {
+                self.terms.entry(term).or_default().push(Posting { doc: n, field, positions });
+            }
+        }
+        true
+    }
+
+    /// Documents matching every clause of the query, best first.
+    pub fn search(&self, query: &Query) -> Vec<Hit> {
+        if query.clauses.is_empty() {
+            return Vec::new();
+        }
+        let mut scores: Option<HashMap<u32, f64>> = None;
+        for clause in &query.clauses {
+            let matches = self.clause(clause);
+            scores = Some(match scores {
+                None => matches,
+                Some(s) => s.into_iter()
+                    .filter_map(|(doc, score)| matches.get(&doc).map(|m| (doc, score + m)))
+                    .collect(),
+            });
+        }
+        let mut hits: Vec<Hit> = scores.unwrap_or_default().into_iter()
+            .map(|(doc, score)| {
+                let cid = Cid::from(&*self.docs[doc as usize]).expect("indexed CIDs are valid");
+                Hit { cid, score }
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score).unwrap()
+                .then_with(|| a.cid.to_string().cmp(&b.cid.to_string()))
+        });
+        hits
+    }
+
+    /// Scores for every document matching one clause.
+    fn clause(&self, clause: &Clause) -> HashMap<u32, f64> {
+        let fields = match clause.field {
+            Some(ref f) => slice::from_ref(f),
+            None => DEFAULT_FIELDS,
+        };
+        let empty = Vec::new();
+        let postings: Vec<&Vec<Posting>> = clause.words.iter()
+            .map(|w| self.terms.get(w).unwrap_or(&empty))
+            .collect();
+
+        // How many times the clause appears in each field of each document
+        let mut counts: HashMap<(u32, Field), usize> = HashMap::new();
+        for first in postings[0].iter().filter(|p| fields.contains(&p.field)) {
+            let rest: Option<Vec<&Posting>> = postings[1..].iter()
+                .map(|ps| {
+                    ps.binary_search_by_key(&(first.doc, first.field), |p| (p.doc, p.field))
+                        .ok().map(|i| &ps[i])
+                })
+                .collect();
+            let rest = match rest {
+                Some(r) => r,
+                None => continue,
+            };
+            let count = first.positions.iter()
+                .filter(|&&start| {
+                    rest.iter().enumerate().all(|(k, p)| p.positions.binary_search(&(start + k as u32 + 1)).is_ok())
+                })
+                .count();
+            if count > 0 {
+                counts.insert((first.doc, first.field), count);
+            }
+        }
+
+        let mut docs: HashMap<u32, f64> = HashMap::new();
+        for (&(doc, field), &count) in &counts {
+            *docs.entry(doc).or_insert(0.0) += field.weight() * (1.0 + (count as f64).ln());
+        }
+        let idf = (1.0 + self.docs.len() as f64 / docs.len().max(1) as f64).ln();
+        for score in docs.values_mut() {
+            *score *= idf;
+        }
+        docs
+    }
+}
+
+fn positioned(text: Option<&str>) -> Vec<(String, u32)> {
+    text.map(|t| tokenize(t).into_iter().zip(0..).collect()).unwrap_or_default()
+}
+
+/// Collects the words of a document's contents, with their positions.
+struct BodyText {
+    words: Vec<(String, u32)>,
+    next: u32,
+}
+
+impl BodyText {
+    fn add(&mut self, text: &str) {
+        for word in tokenize(text) {
+            self.words.push((word, self.next));
+            self.next += 1;
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for BodyText {
+    fn visit_segment(&mut self, path: &mut Path, node: &'ast Segment) {
+        // Leave a gap so phrases don't match across paragraphs.
+        self.next += 1;
+        if let Segment::Code { ref contents, .. } = *node {
+            self.add(contents);
+        }
+        visit::visit_segment(self, path, node);
+    }
+
+    fn visit_element(&mut self, path: &mut Path, node: &'ast Element) {
+        match *node {
+            Element::Text(ref s) => self.add(s),
+            Element::Comment(_) => return,
+            _ => (),
+        }
+        visit::visit_element(self, path, node);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use cid::Cid;
+
+    use document::*;
+    use super::*;
+
+    fn doc(title: &str, author: &str, language: &str, paras: &[&str]) -> Document {
+        let mut d = Document::new(vec![Part::Body(
+            paras.iter().map(|p| Segment::Para(vec![Element::Text(p.to_string())])).collect(),
+        )]);
+        d.title = Some(title.to_string());
+        d.author = Some(author.to_string());
+        d.language = Some(LanguageTag::parse(language).unwrap());
+        d
+    }
+
+    fn corpus() -> (Index, Vec<Cid>) {
+        let docs = vec![
+            doc("Cats", "Simon Heath", "en", &["The quick brown fox.", "Cats are not foxes."]),
+            doc("Foxes", "Ada", "en-GB", &["A brown quick fox, and another fox."]),
+            doc("Füchse", "Simon Heath", "de", &["Der schnelle braune Fuchs."]),
+        ];
+        let mut index = Index::new();
+        let cids: Vec<Cid> = docs.iter().map(|d| d.cid()).collect();
+        for (c, d) in cids.iter().zip(&docs) {
+            assert!(index.add(c, d));
+        }
+        (index, cids)
+    }
+
+    fn search(index: &Index, q: &str) -> Vec<Cid> {
+        index.search(&Query::parse(q)).into_iter().map(|h| h.cid).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let q = Query::parse(r#"author:simon "Brown  Fox" re:thing language:"en""#);
+        assert_eq!(q.clauses, vec![
+            Clause { field: Some(Field::Author), words: vec!["simon".to_string()] },
+            Clause { field: None, words: vec!["brown".to_string(), "fox".to_string()] },
+            Clause { field: None, words: vec!["re".to_string(), "thing".to_string()] },
+            Clause { field: Some(Field::Language), words: vec!["en".to_string()] },
+        ]);
+        assert_eq!(Query::parse("  \"unclosed quote").clauses.len(), 1);
+        assert_eq!(Query::parse(" -- ").clauses, vec![]);
+    }
+
+    #[test]
+    fn test_words_and_ranking() {
+        let (index, cids) = corpus();
+        // "fox" is in the title of the second document and twice in its body.
+        assert_eq!(search(&index, "fox"), vec![cids[1].clone(), cids[0].clone()]);
+        assert_eq!(search(&index, "FUCHS"), vec![cids[2].clone()]);
+        assert_eq!(search(&index, "fox cats"), vec![cids[0].clone()]);
+        assert_eq!(search(&index, "nothing"), vec![]);
+        assert_eq!(search(&index, ""), vec![]);
+    }
+
+    #[test]
+    fn test_phrases() {
+        let (index, cids) = corpus();
+        assert_eq!(search(&index, "\"quick brown fox\""), vec![cids[0].clone()]);
+        assert_eq!(search(&index, "\"brown quick\""), vec![cids[1].clone()]);
+        // Not across paragraphs
+        assert_eq!(search(&index, "\"fox cats\""), vec![]);
+    }
+
+    #[test]
+    fn test_fields() {
+        let (index, cids) = corpus();
+        let mut simon = search(&index, "author:\"simon heath\"");
+        simon.sort_by_key(|c| c.to_string());
+        let mut expected = vec![cids[0].clone(), cids[2].clone()];
+        expected.sort_by_key(|c| c.to_string());
+        assert_eq!(simon, expected);
+        assert_eq!(search(&index, "author:simon fox"), vec![cids[0].clone()]);
+        assert_eq!(search(&index, "language:de"), vec![cids[2].clone()]);
+        assert_eq!(search(&index, "language:gb"), vec![cids[1].clone()]);
+        // The language isn't searched unless asked for.
+        assert_eq!(search(&index, "de"), vec![]);
+        assert_eq!(search(&index, "title:fox"), vec![]);
+        assert_eq!(search(&index, "title:foxes"), vec![cids[1].clone()]);
+    }
+
+    #[test]
+    fn test_incremental() {
+        let (mut index, cids) = corpus();
+        let code = Document::new(vec![Part::Body(vec![Segment::Code {
+            language: None,
+            contents: "let fox = Fox::new();".to_string(),
+        }])]);
+        let c = code.cid();
+        assert!(!index.contains(&c));
+        assert!(index.add(&c, &code));
+        assert!(!index.add(&c, &code));
+        assert!(!index.add(&cids[0], &code));
+        assert_eq!(index.len(), 4);
+        assert!(search(&index, "fox").contains(&c));
+    }
+
+    #[test]
+    fn test_persist() {
+        let (index, _) = corpus();
+        let path = env::temp_dir().join(format!("pallasite-search-test-{}.idx", ::std::process::id()));
+        index.save(&path).unwrap();
+        let loaded = Index::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(search(&loaded, "\"quick brown\""), search(&index, "\"quick brown\""));
+
+        let mut old = Index::new();
+        old.version = 0;
+        old.save(&path).unwrap();
+        let result = Index::open(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(Error::Version(0)) => (),
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let path = env::temp_dir().join(format!("pallasite-corrupt-test-{}.idx", ::std::process::id()));
+        let open = |index: &Index| {
+            index.save(&path).unwrap();
+            let result = Index::open(&path);
+            fs::remove_file(&path).unwrap();
+            match result {
+                Err(Error::Corrupt(s)) => s,
+                other => panic!("expected a corrupt index, got {:?}", other),
+            }
+        };
+        let (index, _) = corpus();
+
+        let mut bad = index.clone();
+        bad.docs.push("not a CID".to_string());
+        assert_eq!(open(&bad), "invalid CID");
+
+        let mut bad = index.clone();
+        bad.docs.push(bad.docs[0].clone());
+        assert_eq!(open(&bad), "the same document is in it twice");
+
+        let mut bad = index.clone();
+        bad.terms.get_mut("fox").unwrap()[0].doc = 3;
+        assert_eq!(open(&bad), "posting for a document that isn't there");
+
+        let mut bad = index.clone();
+        bad.terms.get_mut("fox").unwrap().reverse();
+        assert_eq!(open(&bad), "postings out of order");
+
+        let mut bad = index.clone();
+        bad.terms.get_mut("fox").unwrap()[0].positions = vec![2, 1];
+        assert_eq!(open(&bad), "positions out of order");
+    }
+}
@@ -0,0 +1,115 @@
+//! Serde helpers for `Cid`, which doesn't implement `Serialize` or
+//! `Deserialize` itself.  Use with `#[serde(with = "serde_cid")]`.
+//!
+//! A CID is serialized as its usual base58 string form, wrapped in a
+//! newtype struct named `TOKEN` so that formats that have a native
+//! representation for links (such as DAG-CBOR) can spot it.
+
+use std::fmt;
+
+use cid::Cid;
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::Serializer;
+
+/// Name of the newtype struct a CID is serialized as.
+pub const TOKEN: &str = "$pallasite::Cid";
+
+pub fn serialize<S>(cid: &Cid, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer
+{
+    serializer.serialize_newtype_struct(TOKEN, &cid.to_string())
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Cid, D::Error>
+    where D: Deserializer<'de>
+{
+    deserializer.deserialize_newtype_struct(TOKEN, CidVisitor)
+}
+
+struct CidVisitor;
+
+impl<'de> Visitor<'de> for CidVisitor {
+    type Value = Cid;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a CID")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Cid, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(self)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Cid, E>
+        where E: de::Error
+    {
+        Cid::from(v).map_err(|e| E::custom(format!("invalid CID {:?}: {:?}", v, e)))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Cid, E>
+        where E: de::Error
+    {
+        Cid::from(v).map_err(|e| E::custom(format!("invalid binary CID: {:?}", e)))
+    }
+}
+
+/// Wrapper so we can put a `Cid` inside other generic containers.
+struct Wrap(Cid);
+
+impl ::serde::Serialize for Wrap {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> ::serde::Deserialize<'de> for Wrap {
+    fn deserialize<D>(deserializer: D) -> Result<Wrap, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserialize(deserializer).map(Wrap)
+    }
+}
+
+/// For `Option<Cid>`
+pub mod option {
+    use cid::Cid;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::Wrap;
+
+    pub fn serialize<S>(cid: &Option<Cid>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        cid.clone().map(Wrap).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Cid>, D::Error>
+        where D: Deserializer<'de>
+    {
+        Option::<Wrap>::deserialize(deserializer).map(|o| o.map(|w| w.0))
+    }
+}
+
+/// For `Option<Vec<Cid>>`
+pub mod option_vec {
+    use cid::Cid;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::Wrap;
+
+    pub fn serialize<S>(cids: &Option<Vec<Cid>>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        cids.as_ref()
+            .map(|v| v.iter().cloned().map(Wrap).collect::<Vec<_>>())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<Cid>>, D::Error>
+        where D: Deserializer<'de>
+    {
+        Option::<Vec<Wrap>>::deserialize(deserializer)
+            .map(|o| o.map(|v| v.into_iter().map(|w| w.0).collect()))
+    }
+}
@@ -0,0 +1,273 @@
+//! Proving who wrote a document.
+//!
+//! `Document::author_id` only claims an author.  A `SignedDocument`
+//! carries the document together with its author's signature over the
+//! document's canonical DAG-CBOR encoding, the same bytes its CID is the
+//! hash of.  The signature lives outside the document, so signing one
+//! doesn't change its CID, and a document fetched from anywhere can be
+//! checked against the author's published keys.
+
+use std::error;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+use ring::{rand, signature};
+use untrusted::Input;
+
+use cbor;
+use document::Document;
+use identity::{Algorithm, Identity, Key, Pubkey, Signature};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The document doesn't say who wrote it, so there's nobody to
+    /// check the signature against.
+    NoAuthor,
+    /// The document doesn't say when it was written, so we can't tell
+    /// which of the author's keys should have signed it.
+    NoDate,
+    /// None of the author's keys were valid when the document was
+    /// written.
+    NoKey { author: Identity, date: DateTime<Utc> },
+    /// The system couldn't give us random bytes for a new key.
+    Random,
+    /// A key or signature that isn't well-formed.
+    Malformed,
+    /// The signature doesn't match the document and any of the keys that
+    /// could have made it.
+    BadSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoAuthor => write!(f, "document has no author_id"),
+            Error::NoDate => write!(f, "document has no date"),
+            Error::NoKey { ref author, ref date } => write!(f, "{} had no key valid at {}", author, date),
+            Error::Random => write!(f, "no randomness available to generate a key"),
+            Error::Malformed => write!(f, "malformed key or signature"),
+            Error::BadSignature => write!(f, "signature does not match"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// An Ed25519 private key, for signing things.
+pub struct SigningKey {
+    pair: signature::Ed25519KeyPair,
+}
+
+impl SigningKey {
+    /// Makes a new random key.  The private half is returned as well,
+    /// since there's no way to get it back out afterwards; keep it
+    /// somewhere safe.
+    pub fn generate() -> Result<(SigningKey, [u8; 32]), Error> {
+        let (pair, bytes) = signature::Ed25519KeyPair::generate_serializable(&rand::SystemRandom::new())
+            .map_err(|_| Error::Random)?;
+        Ok((SigningKey { pair }, bytes.private_key))
+    }
+
+    /// Checks that the halves actually belong together.
+    pub fn from_bytes(private_key: &[u8], public_key: &[u8]) -> Result<SigningKey, Error> {
+        let pair = signature::Ed25519KeyPair::from_bytes(private_key, public_key)
+            .map_err(|_| Error::Malformed)?;
+        Ok(SigningKey { pair })
+    }
+
+    pub fn public_key(&self) -> Key {
+        Key::from_bytes(self.pair.public_key_bytes())
+    }
+
+    /// The public half, to publish as `username`'s key from `created`
+    /// on.
+    pub fn pubkey(&self, username: Identity, created: DateTime<Utc>) -> Pubkey {
+        Pubkey::new(username, Algorithm::Ed25519, self.public_key(), created)
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature::from_bytes(self.pair.sign(message).as_slice())
+    }
+}
+
+/// A document and its author's signature.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SignedDocument {
+    pub document: Document,
+    pub algorithm: Algorithm,
+    pub signature: Signature,
+}
+
+impl SignedDocument {
+    /// Signs the document.  Nothing stops you from signing a document
+    /// with the wrong key, or one with no author or date, but it won't
+    /// verify.
+    pub fn sign(document: Document, key: &SigningKey) -> SignedDocument {
+        let signature = key.sign(&document.to_cbor());
+        SignedDocument {
+            document,
+            algorithm: Algorithm::Ed25519,
+            signature,
+        }
+    }
+
+    /// The CID of the document; the signature doesn't change it.
+    pub fn cid(&self) -> Cid {
+        self.document.cid()
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        cbor::to_vec(self).expect("signed documents contain nothing DAG-CBOR can't represent")
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<SignedDocument, cbor::Error> {
+        cbor::from_block(bytes)
+    }
+
+    /// Checks that the document was signed by its `author_id`, with a
+    /// key that was valid at its `date`.  `keys` are whatever keys we
+    /// know of; ones for other people, other algorithms or other times
+    /// are ignored.  Returns the key that made the signature.
+    pub fn verify<'a>(&self, keys: &'a [Pubkey]) -> Result<&'a Pubkey, Error> {
+        let author = self.document.author_id.as_ref().ok_or(Error::NoAuthor)?;
+        let date = self.document.date.ok_or(Error::NoDate)?;
+        let candidates: Vec<&Pubkey> = keys.iter()
+            .filter(|k| k.username() == author && k.algorithm() == self.algorithm && k.is_valid_at(date))
+            .collect();
+        if candidates.is_empty() {
+            return Err(Error::NoKey { author: author.clone(), date });
+        }
+
+        let message = self.document.to_cbor();
+        let signature = self.signature.to_bytes().ok_or(Error::Malformed)?;
+        for key in candidates {
+            // A key we can't decode can't have made the signature, but
+            // another of the author's keys still might have.
+            let public_key = match key.public_key().to_bytes() {
+                Some(public_key) => public_key,
+                None => continue,
+            };
+            let verified = match self.algorithm {
+                Algorithm::Ed25519 => signature::verify(
+                    &signature::ED25519,
+                    Input::from(&public_key),
+                    Input::from(&message),
+                    Input::from(&signature),
+                ),
+            };
+            if verified.is_ok() {
+                return Ok(key);
+            }
+        }
+        Err(Error::BadSignature)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use chrono::TimeZone;
+
+    use document::tests::kitchen_sink;
+    use document::{Element, Part, Segment};
+    use super::*;
+
+    fn at(year: i32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn icefox() -> Identity {
+        Identity::new("icefox", "alopex.li")
+    }
+
+    fn letter(year: i32) -> Document {
+        let mut doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![Element::Text("Hi.".to_string())])])]);
+        doc.author_id = Some(icefox());
+        doc.date = Some(at(year));
+        doc
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (key, private) = SigningKey::generate().unwrap();
+        let pubkey = key.pubkey(icefox(), at(2017));
+        let signed = SignedDocument::sign(letter(2018), &key);
+        assert_eq!(signed.cid(), letter(2018).cid());
+        assert_eq!(signed.verify(std::slice::from_ref(&pubkey)), Ok(&pubkey));
+
+        let decoded = SignedDocument::from_cbor(&signed.to_cbor()).unwrap();
+        assert_eq!(decoded, signed);
+        assert!(decoded.verify(std::slice::from_ref(&pubkey)).is_ok());
+
+        let public = pubkey.public_key().to_bytes().unwrap();
+        let again = SigningKey::from_bytes(&private, &public).unwrap();
+        assert_eq!(SignedDocument::sign(letter(2018), &again), signed);
+        assert_eq!(SigningKey::from_bytes(&public, &private).err(), Some(Error::Malformed));
+    }
+
+    #[test]
+    fn test_tampering() {
+        let (key, _) = SigningKey::generate().unwrap();
+        let keys = [key.pubkey(icefox(), at(2017))];
+        let mut signed = SignedDocument::sign(letter(2018), &key);
+        signed.document.title = Some("Forged".to_string());
+        assert_eq!(signed.verify(&keys), Err(Error::BadSignature));
+
+        // Someone else's key, claiming to be icefox.
+        let (mallory, _) = SigningKey::generate().unwrap();
+        let forged = SignedDocument::sign(letter(2018), &mallory);
+        assert_eq!(forged.verify(&keys), Err(Error::BadSignature));
+        let keys = [keys[0].clone(), mallory.pubkey(Identity::new("mallory", "evil.example"), at(2017))];
+        assert_eq!(forged.verify(&keys), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn test_key_validity() {
+        let (old, _) = SigningKey::generate().unwrap();
+        let (new, _) = SigningKey::generate().unwrap();
+        let keys = [
+            old.pubkey(icefox(), at(2017)).expiring(at(2019)),
+            new.pubkey(icefox(), at(2019)),
+        ];
+        assert_eq!(SignedDocument::sign(letter(2018), &old).verify(&keys), Ok(&keys[0]));
+        assert_eq!(SignedDocument::sign(letter(2020), &new).verify(&keys), Ok(&keys[1]));
+        // An expired key can't sign new documents, and a new key can't
+        // sign backdated ones.
+        assert_eq!(SignedDocument::sign(letter(2019), &old).verify(&keys), Err(Error::BadSignature));
+        assert_eq!(SignedDocument::sign(letter(2018), &new).verify(&keys), Err(Error::BadSignature));
+        assert_eq!(SignedDocument::sign(letter(2016), &old).verify(&keys),
+                   Err(Error::NoKey { author: icefox(), date: at(2016) }));
+    }
+
+    #[test]
+    fn test_undecodable_key() {
+        let (key, _) = SigningKey::generate().unwrap();
+        let garbage: Key = serde_json::from_str("\"not base64!\"").unwrap();
+        let keys = [
+            Pubkey::new(icefox(), Algorithm::Ed25519, garbage, at(2017)),
+            key.pubkey(icefox(), at(2017)),
+        ];
+        let signed = SignedDocument::sign(letter(2018), &key);
+        assert_eq!(signed.verify(&keys), Ok(&keys[1]));
+        assert_eq!(signed.verify(&keys[..1]), Err(Error::BadSignature));
+    }
+
+    #[test]
+    fn test_unverifiable() {
+        let (key, _) = SigningKey::generate().unwrap();
+        let keys = [key.pubkey(icefox(), at(2017))];
+        let mut doc = kitchen_sink();
+        doc.author_id = None;
+        assert_eq!(SignedDocument::sign(doc.clone(), &key).verify(&keys), Err(Error::NoAuthor));
+        doc.author_id = Some(icefox());
+        doc.date = None;
+        assert_eq!(SignedDocument::sign(doc, &key).verify(&keys), Err(Error::NoDate));
+
+        let mut signed = SignedDocument::sign(letter(2018), &key);
+        signed.signature = Signature::from_bytes(b"short");
+        assert_eq!(signed.verify(&keys), Err(Error::BadSignature));
+    }
+}
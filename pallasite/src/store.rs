@@ -0,0 +1,133 @@
+//! Getting documents by their content address.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use cid::Cid;
+
+use cbor;
+use document::Document;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// Nobody had the block with this CID.
+    Missing(Cid),
+    /// The block was there, but wasn't what we were looking for, like
+    /// a document or a vocabulary.
+    Decode(Cid, cbor::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Missing(ref cid) => write!(f, "block {} not found", cid),
+            Error::Decode(ref cid, ref e) => write!(f, "block {} could not be decoded: {}", cid, e),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// Somewhere to look up blocks by CID, such as an IPFS node.
+pub trait Lookup {
+    /// The raw bytes of a block, if we can find it.
+    fn get(&self, cid: &Cid) -> Option<Vec<u8>>;
+
+    fn document(&self, cid: &Cid) -> Result<Document, Error> {
+        let bytes = self.get(cid).ok_or_else(|| Error::Missing(cid.clone()))?;
+        Document::from_cbor(&bytes).map_err(|e| Error::Decode(cid.clone(), e))
+    }
+}
+
+impl<L: Lookup + ?Sized> Lookup for &L {
+    fn get(&self, cid: &Cid) -> Option<Vec<u8>> {
+        (**self).get(cid)
+    }
+}
+
+/// A CID in a form that can key a `HashMap` or go in a `HashSet`.
+/// `Cid` doesn't implement `Hash`, so this holds its binary form.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CidKey(Vec<u8>);
+
+impl CidKey {
+    pub fn new(cid: &Cid) -> CidKey {
+        CidKey(cid.to_bytes())
+    }
+}
+
+/// Blocks kept in memory.  Handy for tests and caches.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct MemoryStore {
+    blocks: HashMap<CidKey, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    /// Stores a DAG-CBOR block and returns its CID.
+    pub fn put(&mut self, bytes: Vec<u8>) -> Cid {
+        let cid = cbor::block_cid(&bytes);
+        self.blocks.insert(CidKey::new(&cid), bytes);
+        cid
+    }
+
+    pub fn put_document(&mut self, doc: &Document) -> Cid {
+        self.put(doc.to_cbor())
+    }
+
+    pub fn remove(&mut self, cid: &Cid) -> Option<Vec<u8>> {
+        self.blocks.remove(&CidKey::new(cid))
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl Lookup for MemoryStore {
+    fn get(&self, cid: &Cid) -> Option<Vec<u8>> {
+        self.blocks.get(&CidKey::new(cid)).cloned()
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use document::tests::{cid, kitchen_sink, CID_A};
+    use super::*;
+
+    /// A store that hands out whatever it's told to for each CID,
+    /// without checking hashes, so tests can make up cycles.
+    pub struct Liar(pub HashMap<String, Vec<u8>>);
+
+    impl Lookup for Liar {
+        fn get(&self, cid: &Cid) -> Option<Vec<u8>> {
+            self.0.get(&cid.to_string()).cloned()
+        }
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStore::new();
+        let doc = kitchen_sink();
+        let c = store.put_document(&doc);
+        assert_eq!(c, doc.cid());
+        assert_eq!(store.document(&c), Ok(doc));
+        assert_eq!(store.document(&cid(CID_A)), Err(Error::Missing(cid(CID_A))));
+
+        let junk = store.put(vec![0x01]);
+        match store.document(&junk) {
+            Err(Error::Decode(ref c, _)) => assert_eq!(*c, junk),
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+        assert_eq!(store.len(), 2);
+    }
+}
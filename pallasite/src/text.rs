@@ -0,0 +1,723 @@
+//! Rendering documents as plain text for a terminal, wrapped to a
+//! given width.
+//!
+//! With color turned on, strong, emphasized, inserted and deleted text
+//! are shown with ANSI styles.  Without it they get marked up with
+//! punctuation instead, which is what you want when piping the output
+//! somewhere.
+
+use std::fmt::Write;
+
+use document::{plain_text, Document, Element, ListType, Part, Segment};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Options {
+    /// Maximum line width, in characters.  Code blocks, tables and
+    /// single words longer than this will still overflow it.
+    pub width: usize,
+    /// Whether to use ANSI escape codes.
+    pub color: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            width: 80,
+            color: true,
+        }
+    }
+}
+
+/// Lines never get narrower than this, no matter how deeply indented.
+const MIN_WIDTH: usize = 10;
+
+pub fn render(doc: &Document, options: &Options) -> String {
+    let mut r = Renderer {
+        options,
+        out: String::new(),
+        footnotes: Vec::new(),
+    };
+    r.header(doc);
+    for part in &doc.contents {
+        r.part(part);
+    }
+    r.footnotes();
+    r.out
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+struct Style {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strike: bool,
+}
+
+impl Style {
+    /// The escape sequence that switches from any style to this one.
+    fn sgr(&self) -> String {
+        let mut s = String::from("\x1b[0");
+        if self.bold {
+            s.push_str(";1");
+        }
+        if self.italic {
+            s.push_str(";3");
+        }
+        if self.underline {
+            s.push_str(";4");
+        }
+        if self.strike {
+            s.push_str(";9");
+        }
+        s.push('m');
+        s
+    }
+}
+
+/// A bit of text in one style.
+type Run = (String, Style);
+
+/// A word that can't be broken across lines.  It may be made of several
+/// runs, for instance if only part of it is emphasized.
+type Word = Vec<Run>;
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
+
+fn word_width(word: &[Run]) -> usize {
+    word.iter().map(|r| width(&r.0)).sum()
+}
+
+/// Control characters in a document could be escape sequences telling
+/// the terminal to do who knows what, so they're never written out as
+/// they are.
+fn printable(c: char) -> char {
+    if c.is_control() && c != '\n' && c != '\t' {
+        '\u{fffd}'
+    } else {
+        c
+    }
+}
+
+/// Splits styled runs into words at whitespace.
+fn words(runs: &[Run]) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current: Word = Vec::new();
+    for &(ref text, style) in runs {
+        let mut piece = String::new();
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !piece.is_empty() {
+                    current.push((piece, style));
+                    piece = String::new();
+                }
+                if !current.is_empty() {
+                    words.push(current);
+                    current = Vec::new();
+                }
+            } else {
+                piece.push(printable(c));
+            }
+        }
+        if !piece.is_empty() {
+            current.push((piece, style));
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+struct Renderer<'a> {
+    options: &'a Options,
+    out: String,
+    footnotes: Vec<&'a [Element]>,
+}
+
+impl<'a> Renderer<'a> {
+    /// Separates blocks from each other with a blank line.
+    fn blank(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Wraps some styled text and writes it out.  The first line starts
+    /// with `first`, and the rest with `rest`.
+    fn wrap(&mut self, runs: &[Run], first: &str, rest: &str) {
+        let words = words(runs);
+        let mut prefix = first;
+        let mut line: Vec<&Word> = Vec::new();
+        let mut line_width = 0;
+        for word in &words {
+            let available = self.options.width.saturating_sub(width(prefix)).max(MIN_WIDTH);
+            let w = word_width(word);
+            if !line.is_empty() && line_width + 1 + w > available {
+                self.line(prefix, &line);
+                prefix = rest;
+                line.clear();
+                line_width = 0;
+            }
+            if !line.is_empty() {
+                line_width += 1;
+            }
+            line.push(word);
+            line_width += w;
+        }
+        if !line.is_empty() || words.is_empty() {
+            self.line(prefix, &line);
+        }
+    }
+
+    fn line(&mut self, prefix: &str, words: &[&Word]) {
+        let mut s = String::from(prefix);
+        let mut current = Style::default();
+        for (i, word) in words.iter().enumerate() {
+            if i > 0 {
+                // The space between two words is styled only if both sides
+                // of it are, so strikethrough doesn't spill into the gaps.
+                let before = words[i - 1].last().map(|r| r.1).unwrap_or_default();
+                let after = word.first().map(|r| r.1).unwrap_or_default();
+                let space = if before == after { before } else { Style::default() };
+                self.switch(&mut s, &mut current, space);
+                s.push(' ');
+            }
+            for &(ref text, style) in word.iter() {
+                self.switch(&mut s, &mut current, style);
+                s.push_str(text);
+            }
+        }
+        self.switch(&mut s, &mut current, Style::default());
+        self.out.push_str(s.trim_end());
+        self.out.push('\n');
+    }
+
+    fn switch(&self, s: &mut String, current: &mut Style, to: Style) {
+        if self.options.color && *current != to {
+            s.push_str(&to.sgr());
+            *current = to;
+        }
+    }
+
+    fn styled(&self, text: &str, style: Style) -> String {
+        if self.options.color {
+            format!("{}{}{}", style.sgr(), text, Style::default().sgr())
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn header(&mut self, doc: &Document) {
+        if let Some(ref title) = doc.title {
+            let bold = Style { bold: true, ..Style::default() };
+            self.wrap(&[(title.clone(), bold)], "", "");
+            let underline = "=".repeat(width(title).min(self.options.width));
+            self.out.push_str(&underline);
+            self.out.push('\n');
+        }
+        let mut byline = Vec::new();
+        if let Some(ref author) = doc.author {
+            byline.push(author.clone());
+        }
+        if let Some(date) = doc.local_date_time() {
+            byline.push(date.format("%Y-%m-%d %H:%M %:z").to_string());
+        }
+        if !byline.is_empty() {
+            self.wrap(&[(byline.join(", "), Style::default())], "", "");
+        }
+        if !doc.tags.is_empty() {
+            let terms: Vec<_> = doc.tags.iter().map(|t| &*t.term).collect();
+            self.wrap(&[(format!("Tags: {}", terms.join(", ")), Style::default())], "", "");
+        }
+    }
+
+    fn part(&mut self, part: &'a Part) {
+        match *part {
+            Part::Body(ref segments) => self.segments(segments, "", ""),
+            Part::Section { level, ref heading, ref contents } => {
+                if !heading.is_empty() {
+                    self.blank();
+                    let bold = Style { bold: true, ..Style::default() };
+                    let mut runs = Vec::new();
+                    self.inline(heading, bold, &mut runs);
+                    match level {
+                        1 | 2 => {
+                            self.wrap(&runs, "", "");
+                            let w = word_width(&runs).min(self.options.width);
+                            let c = if level == 1 { "=" } else { "-" };
+                            self.out.push_str(&c.repeat(w));
+                            self.out.push('\n');
+                        }
+                        _ => {
+                            let prefix = format!("{} ", "#".repeat(level as usize));
+                            self.wrap(&runs, &prefix, "");
+                        }
+                    }
+                }
+                self.segments(contents, "", "");
+            }
+        }
+    }
+
+    fn segments(&mut self, segments: &'a [Segment], first: &str, rest: &str) {
+        for s in segments {
+            self.blank();
+            self.segment(s, first, rest);
+        }
+    }
+
+    fn segment(&mut self, segment: &'a Segment, first: &str, rest: &str) {
+        match *segment {
+            Segment::Para(ref e) => {
+                let mut runs = Vec::new();
+                self.inline(e, Style::default(), &mut runs);
+                self.wrap(&runs, first, rest);
+            }
+            Segment::Abstract(ref e) => {
+                let mut runs = vec![("Abstract:".to_string(), Style { bold: true, ..Style::default() })];
+                runs.push((" ".to_string(), Style::default()));
+                self.inline(e, Style::default(), &mut runs);
+                self.wrap(&runs, first, rest);
+            }
+            Segment::Table { ref header, ref body, ref footer } => {
+                self.table(header, body, footer, first, rest);
+            }
+            Segment::Figure { ref caption, ref source, ref media_type, ref alt, dimensions, size } => {
+                let mut label: Vec<_> = caption.iter().map(|c| plain_text(c)).collect();
+                if label.is_empty() {
+                    label.extend(alt.clone());
+                }
+                let mut details = Vec::new();
+                details.extend(media_type.clone());
+                details.extend(dimensions.map(|d| format!("{}x{}", d.width, d.height)));
+                details.extend(size.map(|s| format!("{} bytes", s)));
+                let mut text = format!("[Figure: {}] <{}>", label.join(" "), source);
+                if !details.is_empty() {
+                    write!(text, " ({})", details.join(", ")).unwrap();
+                }
+                self.wrap(&[(text, Style::default())], first, rest);
+            }
+            Segment::List { ref type_, ref elements } => {
+                let mut n = 0;
+                let indent = " ".repeat(width(first).max(width(rest)));
+                let mut first_item = true;
+                for e in elements {
+                    if !first_item {
+                        self.blank();
+                    }
+                    match (type_, e) {
+                        // A list following an item is a sublist of that item.
+                        (_, &Segment::List { .. }) if n > 0 => {
+                            let nested = format!("{}    ", indent);
+                            self.segment(e, &nested, &nested);
+                        }
+                        (&ListType::Bulleted, _) => {
+                            n += 1;
+                            let f = format!("{}  * ", if first_item { first } else { rest });
+                            let r = format!("{}    ", rest);
+                            self.segment(e, &f, &r);
+                        }
+                        (&ListType::Numbered, _) => {
+                            n += 1;
+                            let marker = format!("{:>3}. ", n);
+                            let f = format!("{}{}", if first_item { first } else { rest }, marker);
+                            let r = format!("{}{}", rest, " ".repeat(width(&marker)));
+                            self.segment(e, &f, &r);
+                        }
+                    }
+                    first_item = false;
+                }
+            }
+            Segment::Code { ref contents, .. } | Segment::Math(ref contents) => {
+                let mut prefix = first;
+                for line in contents.lines() {
+                    let line: String = line.chars().map(printable).collect();
+                    let line = format!("    {}", line);
+                    let dim = if self.options.color {
+                        format!("\x1b[0;2m{}\x1b[0m", line)
+                    } else {
+                        line
+                    };
+                    writeln!(self.out, "{}{}", prefix, dim).unwrap();
+                    prefix = rest;
+                }
+            }
+            Segment::Transclusion { ref source, ref fragment } => {
+                let text = match *fragment {
+                    Some(ref f) => format!("[Included from <{}#{}>]", source, f),
+                    None => format!("[Included from <{}>]", source),
+                };
+                self.wrap(&[(text, Style::default())], first, rest);
+            }
+            Segment::Quote(ref s) => {
+                let f = format!("{}> ", first);
+                let r = format!("{}> ", rest);
+                self.segment(s, &f, &r);
+            }
+            Segment::Conflict { ref ours, ref theirs } => {
+                // Like the markers git leaves behind
+                writeln!(self.out, "{}<<<<<<< ours", first).unwrap();
+                self.segments(ours, rest, rest);
+                self.blank();
+                writeln!(self.out, "{}=======", rest).unwrap();
+                self.segments(theirs, rest, rest);
+                self.blank();
+                writeln!(self.out, "{}>>>>>>> theirs", rest).unwrap();
+            }
+        }
+    }
+
+    fn table(&mut self, header: &'a [Vec<Element>], body: &'a [Vec<Segment>],
+             footer: &'a [Vec<Element>], first: &str, rest: &str) {
+        // Tables don't get wrapped; each cell is flattened to a single line.
+        let cells = |elements: &'a [Vec<Element>], r: &mut Renderer<'a>| -> Vec<String> {
+            elements.iter().map(|e| r.cell_text(e)).collect()
+        };
+        let header_row = cells(header, self);
+        let footer_row = cells(footer, self);
+        let mut body_rows = Vec::new();
+        for row in body {
+            let mut cols = Vec::new();
+            for segment in row {
+                let text = match *segment {
+                    Segment::Para(ref e) => self.cell_text(e),
+                    ref other => {
+                        // Render it on its own and squash it onto one line.
+                        let mut sub = Renderer {
+                            options: self.options,
+                            out: String::new(),
+                            footnotes: Vec::new(),
+                        };
+                        sub.segment(other, "", "");
+                        self.footnotes.extend(sub.footnotes);
+                        sub.out.split_whitespace().collect::<Vec<_>>().join(" ")
+                    }
+                };
+                cols.push(text);
+            }
+            body_rows.push(cols);
+        }
+
+        let all_rows = || {
+            ::std::iter::once(&header_row)
+                .chain(body_rows.iter())
+                .chain(::std::iter::once(&footer_row))
+        };
+        let ncols = all_rows().map(|r| r.len()).max().unwrap_or(0);
+        let mut widths = vec![0; ncols];
+        for row in all_rows() {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(self.visible_width(cell));
+            }
+        }
+        let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        let rule = rule.join("-+-");
+
+        let mut prefix = first;
+        let mut lines = Vec::new();
+        if !header_row.is_empty() {
+            lines.push(self.table_row(&header_row, &widths));
+            lines.push(rule.clone());
+        }
+        for row in &body_rows {
+            lines.push(self.table_row(row, &widths));
+        }
+        if !footer_row.is_empty() {
+            lines.push(rule);
+            lines.push(self.table_row(&footer_row, &widths));
+        }
+        for line in lines {
+            writeln!(self.out, "{}{}", prefix, line.trim_end()).unwrap();
+            prefix = rest;
+        }
+    }
+
+    fn table_row(&self, row: &[String], widths: &[usize]) -> String {
+        let cols: Vec<String> = widths.iter().enumerate().map(|(i, w)| {
+            let cell = row.get(i).map(|s| s.as_str()).unwrap_or("");
+            let pad = w - self.visible_width(cell);
+            format!("{}{}", cell, " ".repeat(pad))
+        }).collect();
+        cols.join(" | ")
+    }
+
+    /// Inline content squashed onto one line, styles and all.
+    fn cell_text(&mut self, elements: &'a [Element]) -> String {
+        let mut runs = Vec::new();
+        self.inline(elements, Style::default(), &mut runs);
+        let words = words(&runs);
+        let mut s = String::new();
+        for (i, word) in words.iter().enumerate() {
+            if i > 0 {
+                s.push(' ');
+            }
+            for &(ref text, style) in word {
+                if style == Style::default() {
+                    s.push_str(text);
+                } else {
+                    s.push_str(&self.styled(text, style));
+                }
+            }
+        }
+        s
+    }
+
+    /// Width of a string with any escape codes left out.
+    fn visible_width(&self, s: &str) -> usize {
+        let mut w = 0;
+        let mut in_escape = false;
+        for c in s.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                _ if in_escape => (),
+                _ => w += 1,
+            }
+        }
+        w
+    }
+
+    /// Turns elements into styled runs of text.
+    fn inline(&mut self, elements: &'a [Element], style: Style, out: &mut Vec<Run>) {
+        for e in elements {
+            match *e {
+                Element::Text(ref s) => out.push((s.clone(), style)),
+                Element::Strong(ref e) => {
+                    self.marked(e, "*", "*", Style { bold: true, ..style }, style, out)
+                }
+                Element::Emphasized(ref e) => {
+                    self.marked(e, "_", "_", Style { italic: true, ..style }, style, out)
+                }
+                Element::Insertion(ref e) => {
+                    self.marked(e, "{+", "+}", Style { underline: true, ..style }, style, out)
+                }
+                Element::Deletion(ref e) => {
+                    self.marked(e, "[-", "-]", Style { strike: true, ..style }, style, out)
+                }
+                Element::Subscript(ref e) => {
+                    out.push(("_(".to_string(), style));
+                    self.inline(e, style, out);
+                    out.push((")".to_string(), style));
+                }
+                Element::Superscript(ref e) => {
+                    out.push(("^(".to_string(), style));
+                    self.inline(e, style, out);
+                    out.push((")".to_string(), style));
+                }
+                Element::Math(ref tex) => out.push((format!("${}$", tex), style)),
+                Element::Preformatted(ref e) => {
+                    out.push(("`".to_string(), style));
+                    self.inline(e, style, out);
+                    out.push(("`".to_string(), style));
+                }
+                Element::Footnote(ref e) => {
+                    self.footnotes.push(e);
+                    out.push((format!("[{}]", self.footnotes.len()), style));
+                }
+                Element::Xref { ref contents, ref target, ref fragment } => {
+                    self.inline(contents, style, out);
+                    match *fragment {
+                        Some(ref f) => out.push((format!(" <{}#{}>", target, f), style)),
+                        None => out.push((format!(" <{}>", target), style)),
+                    }
+                }
+                // A terminal has no way to switch languages, and handles
+                // direction itself, if at all.
+                Element::Span { ref contents, .. } => self.inline(contents, style, out),
+                Element::Link { ref contents, ref url } => {
+                    self.inline(contents, style, out);
+                    out.push((format!(" <{}>", url), style));
+                }
+                Element::Comment(_) | Element::Anchor(_) => (),
+            }
+        }
+    }
+
+    /// Styled text, or text surrounded by markers when we can't use color.
+    fn marked(&mut self, elements: &'a [Element], open: &str, close: &str,
+              styled: Style, plain: Style, out: &mut Vec<Run>) {
+        if self.options.color {
+            self.inline(elements, styled, out);
+        } else {
+            out.push((open.to_string(), plain));
+            self.inline(elements, plain, out);
+            out.push((close.to_string(), plain));
+        }
+    }
+
+    fn footnotes(&mut self) {
+        if self.footnotes.is_empty() {
+            return;
+        }
+        self.blank();
+        self.out.push_str("Notes:\n");
+        let mut i = 0;
+        while i < self.footnotes.len() {
+            let marker = format!("[{}] ", i + 1);
+            let indent = " ".repeat(width(&marker));
+            let mut runs = Vec::new();
+            let e = self.footnotes[i];
+            self.inline(e, Style::default(), &mut runs);
+            self.wrap(&runs, &marker, &indent);
+            i += 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::tests::{cid, kitchen_sink, text, CID_A};
+    use document::*;
+    use url::Url;
+    use super::*;
+
+    fn plain(width: usize, segments: Vec<Segment>) -> String {
+        let doc = Document::new(vec![Part::Body(segments)]);
+        render(&doc, &Options { width, color: false })
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let s = plain(20, vec![Segment::Para(vec![text("The quick brown fox jumps over the lazy dog.")])]);
+        assert_eq!(s, "The quick brown fox\njumps over the lazy\ndog.\n");
+    }
+
+    #[test]
+    fn test_plain_markers() {
+        let s = plain(80, vec![Segment::Para(vec![
+            Element::Strong(vec![text("bold")]),
+            text(" "),
+            Element::Emphasized(vec![text("it")]),
+            text(" "),
+            Element::Deletion(vec![text("gone")]),
+        ])]);
+        assert_eq!(s, "*bold* _it_ [-gone-]\n");
+    }
+
+    #[test]
+    fn test_control_characters() {
+        let mut doc = Document::new(vec![Part::Body(vec![
+            Segment::Para(vec![text("a\x1b]0;pwned\x07b \u{9b}2J")]),
+            Segment::Code { language: None, contents: "x\x1b[2J\ty\n".to_string() },
+        ])]);
+        doc.title = Some("\x1b[31mRed".to_string());
+        let s = render(&doc, &Options { width: 80, color: false });
+        assert_eq!(s, "\u{fffd}[31mRed\n========\n\na\u{fffd}]0;pwned\u{fffd}b \u{fffd}2J\n\n    x\u{fffd}[2J\ty\n");
+
+        // With color on, the only escapes are our own.
+        let s = render(&doc, &Options::default());
+        assert_eq!(s.matches('\x1b').count(), s.matches("\x1b[0").count());
+        assert!(!s.contains("\x1b[2J") && !s.contains("\x1b]"));
+    }
+
+    #[test]
+    fn test_figure() {
+        let doc = Document::new(vec![Part::Body(vec![Segment::Figure {
+            caption: vec![],
+            source: cid(CID_A),
+            media_type: Some("image/png".to_string()),
+            alt: Some("A cat".to_string()),
+            dimensions: Some(Dimensions { width: 640, height: 480 }),
+            size: Some(2048),
+        }])]);
+        assert_eq!(render(&doc, &Options { width: 200, color: false }),
+                   format!("[Figure: A cat] <{}> (image/png, 640x480, 2048 bytes)\n", CID_A));
+    }
+
+    #[test]
+    fn test_transclusion() {
+        let s = plain(200, vec![Segment::Transclusion { source: cid(CID_A), fragment: None }]);
+        assert_eq!(s, format!("[Included from <{}>]\n", CID_A));
+    }
+
+    #[test]
+    fn test_math() {
+        let s = plain(80, vec![
+            Segment::Para(vec![text("where "), Element::Math("x^2".to_string())]),
+            Segment::Math("E = mc^2".to_string()),
+        ]);
+        assert_eq!(s, "where $x^2$\n\n    E = mc^2\n");
+    }
+
+    #[test]
+    fn test_external_link() {
+        let s = plain(80, vec![Segment::Para(vec![Element::Link {
+            contents: vec![text("Example")],
+            url: Url::parse("https://example.com").unwrap(),
+        }])]);
+        assert_eq!(s, "Example <https://example.com>\n");
+    }
+
+    #[test]
+    fn test_color() {
+        let doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![
+            text("a "),
+            Element::Strong(vec![text("b c")]),
+            text(" d"),
+        ])])]);
+        let s = render(&doc, &Options { width: 80, color: true });
+        assert_eq!(s, "a \x1b[0;1mb c\x1b[0m d\n");
+    }
+
+    #[test]
+    fn test_lists() {
+        let s = plain(20, vec![Segment::List {
+            type_: ListType::Numbered,
+            elements: vec![
+                Segment::Para(vec![text("one two three four five")]),
+                Segment::List { type_: ListType::Bulleted, elements: vec![Segment::Para(vec![text("sub")])] },
+                Segment::Para(vec![text("six")]),
+            ],
+        }]);
+        assert_eq!(s, "  1. one two three\n     four five\n\n      * sub\n\n  2. six\n");
+    }
+
+    #[test]
+    fn test_quote() {
+        let s = plain(20, vec![Segment::Quote(Box::new(Segment::Quote(Box::new(
+            Segment::Para(vec![text("a b c d e f g h i j k l")]),
+        ))))]);
+        assert_eq!(s, "> > a b c d e f g h\n> > i j k l\n");
+    }
+
+    #[test]
+    fn test_table() {
+        let s = plain(80, vec![Segment::Table {
+            header: vec![vec![text("Name")], vec![text("N")]],
+            body: vec![
+                vec![Segment::Para(vec![text("apples")]), Segment::Para(vec![text("3")])],
+                vec![Segment::Para(vec![text("figs")]), Segment::Para(vec![text("12")])],
+            ],
+            footer: vec![vec![text("total")], vec![text("15")]],
+        }]);
+        assert_eq!(s, "Name   | N\n\
+                       -------+---\n\
+                       apples | 3\n\
+                       figs   | 12\n\
+                       -------+---\n\
+                       total  | 15\n");
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let s = plain(80, vec![Segment::Para(vec![
+            text("Hello"),
+            Element::Footnote(vec![text("First.")]),
+            text(" world"),
+            Element::Footnote(vec![text("Second.")]),
+        ])]);
+        assert_eq!(s, "Hello[1] world[2]\n\nNotes:\n[1] First.\n[2] Second.\n");
+    }
+
+    #[test]
+    fn test_everything() {
+        let s = render(&kitchen_sink(), &Options { width: 40, color: false });
+        assert!(s.starts_with("Everything\n==========\nSimon Heath, 2017-11-05 13:30 -05:00\n"));
+        assert!(!s.contains("not rendered"));
+        let s = render(&kitchen_sink(), &Options::default());
+        assert!(s.contains("\x1b[0;9mremoved\x1b[0m"));
+    }
+}
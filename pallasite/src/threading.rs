@@ -0,0 +1,423 @@
+//! Conversations: the reply trees you get by following `in_response_to`
+//! links between documents.
+//!
+//! A document that isn't a reply to anything starts a thread.  A reply
+//! to something we don't have is an orphan; it still heads a tree of
+//! its own, so nothing gets lost, but displays usually want to mark it.
+//! Replies are ordered by `date`, oldest first, with undated documents
+//! after dated ones.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Ordering;
+use std::slice;
+
+use chrono::{DateTime, Utc};
+use cid::Cid;
+
+use document::Document;
+use store::{self, CidKey, Lookup};
+
+#[derive(Clone, Debug)]
+struct Node {
+    cid: Cid,
+    document: Document,
+    /// Index of the document this is a reply to, if we have it.
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// Numbers describing one thread, or one branch of it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Stats {
+    /// How many documents there are, counting the one at the top.
+    pub messages: usize,
+    /// How far down the longest chain of replies goes.  A document with
+    /// no replies has depth 0.
+    pub depth: usize,
+    /// Everyone who wrote something, in the order they first turn up in
+    /// the thread.  Authors are named by their identity if they have
+    /// one, and by `author` otherwise; anonymous documents aren't
+    /// counted.
+    pub participants: Vec<String>,
+    /// The earliest and latest dates in the thread.
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Threads {
+    nodes: Vec<Node>,
+    index: HashMap<CidKey, usize>,
+    roots: Vec<usize>,
+    orphans: Vec<usize>,
+    missing: Vec<Cid>,
+}
+
+impl Threads {
+    /// Threads together documents we already have.  Duplicates are
+    /// only counted once.
+    pub fn new<I>(documents: I) -> Threads
+        where I: IntoIterator<Item = Document>
+    {
+        let mut threads = Threads::empty();
+        for document in documents {
+            let cid = document.cid();
+            if !threads.index.contains_key(&CidKey::new(&cid)) {
+                threads.insert(cid, document);
+            }
+        }
+        threads.link();
+        threads
+    }
+
+    /// Fetches some documents and everything they're replies to, all the
+    /// way up to the start of each thread.
+    ///
+    /// Documents the store doesn't have don't stop us; they're listed by
+    /// `missing()`, and their replies become orphans.  Blocks that
+    /// aren't documents do.
+    pub fn load<L>(store: &L, cids: &[Cid]) -> Result<Threads, store::Error>
+        where L: Lookup + ?Sized
+    {
+        let mut threads = Threads::empty();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<Cid> = VecDeque::new();
+        for cid in cids {
+            if seen.insert(CidKey::new(cid)) {
+                queue.push_back(cid.clone());
+            }
+        }
+        while let Some(cid) = queue.pop_front() {
+            let document = match store.get(&cid) {
+                Some(bytes) => Document::from_cbor(&bytes)
+                    .map_err(|e| store::Error::Decode(cid.clone(), e))?,
+                None => {
+                    threads.missing.push(cid);
+                    continue;
+                }
+            };
+            if let Some(ref parent) = document.in_response_to {
+                if seen.insert(CidKey::new(parent)) {
+                    queue.push_back(parent.clone());
+                }
+            }
+            threads.insert(cid, document);
+        }
+        threads.link();
+        Ok(threads)
+    }
+
+    fn empty() -> Threads {
+        Threads {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            roots: Vec::new(),
+            orphans: Vec::new(),
+            missing: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, cid: Cid, document: Document) {
+        self.index.insert(CidKey::new(&cid), self.nodes.len());
+        self.nodes.push(Node {
+            cid,
+            document,
+            parent: None,
+            children: Vec::new(),
+        });
+    }
+
+    /// Connects replies to what they reply to, and sorts everything.
+    fn link(&mut self) {
+        for i in 0..self.nodes.len() {
+            let parent = self.nodes[i].document.in_response_to.as_ref()
+                .and_then(|p| self.index.get(&CidKey::new(p)))
+                .cloned();
+            self.nodes[i].parent = parent;
+        }
+
+        // Honest content addressing can't make a cycle, since a reply has
+        // to know the CID of what it replies to.  A lying store can, so
+        // cut any we find; the document where we noticed becomes an
+        // orphan.
+        let mut done = vec![false; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            let mut chain = Vec::new();
+            let mut i = start;
+            while !done[i] {
+                done[i] = true;
+                chain.push(i);
+                match self.nodes[i].parent {
+                    Some(p) if chain.contains(&p) => {
+                        self.nodes[i].parent = None;
+                        break;
+                    }
+                    Some(p) => i = p,
+                    None => break,
+                }
+            }
+        }
+
+        for i in 0..self.nodes.len() {
+            match self.nodes[i].parent {
+                Some(p) => self.nodes[p].children.push(i),
+                None if self.nodes[i].document.in_response_to.is_some() => self.orphans.push(i),
+                None => self.roots.push(i),
+            }
+        }
+        let mut roots = self.roots.clone();
+        self.sort(&mut roots);
+        self.roots = roots;
+        let mut orphans = self.orphans.clone();
+        self.sort(&mut orphans);
+        self.orphans = orphans;
+        for i in 0..self.nodes.len() {
+            let mut children = self.nodes[i].children.clone();
+            self.sort(&mut children);
+            self.nodes[i].children = children;
+        }
+    }
+
+    /// Oldest first, then undated documents, then by CID so the order
+    /// never depends on how we found things.
+    fn sort(&self, indices: &mut [usize]) {
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (&self.nodes[a], &self.nodes[b]);
+            let by_date = match (a.document.date, b.document.date) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            by_date.then_with(|| a.cid.to_string().cmp(&b.cid.to_string()))
+        });
+    }
+
+    fn find(&self, cid: &Cid) -> Option<usize> {
+        self.index.get(&CidKey::new(cid)).cloned()
+    }
+
+    fn cids(&self, indices: &[usize]) -> Vec<&Cid> {
+        indices.iter().map(|&i| &self.nodes[i].cid).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.find(cid).is_some()
+    }
+
+    pub fn document(&self, cid: &Cid) -> Option<&Document> {
+        self.find(cid).map(|i| &self.nodes[i].document)
+    }
+
+    /// Documents that were asked for or replied to, but that the store
+    /// didn't have.
+    pub fn missing(&self) -> &[Cid] {
+        &self.missing
+    }
+
+    /// The documents that start threads.
+    pub fn roots(&self) -> Vec<&Cid> {
+        self.cids(&self.roots)
+    }
+
+    /// Replies to documents we don't have.
+    pub fn orphans(&self) -> Vec<&Cid> {
+        self.cids(&self.orphans)
+    }
+
+    /// The document `cid` replies to, if we have it.
+    pub fn parent(&self, cid: &Cid) -> Option<&Cid> {
+        self.find(cid)
+            .and_then(|i| self.nodes[i].parent)
+            .map(|p| &self.nodes[p].cid)
+    }
+
+    /// Direct replies to `cid`, oldest first.
+    pub fn children(&self, cid: &Cid) -> Vec<&Cid> {
+        match self.find(cid) {
+            Some(i) => self.cids(&self.nodes[i].children),
+            None => Vec::new(),
+        }
+    }
+
+    /// The top of the tree `cid` is in: a root, or an orphan.
+    pub fn root_of(&self, cid: &Cid) -> Option<&Cid> {
+        let mut i = self.find(cid)?;
+        while let Some(p) = self.nodes[i].parent {
+            i = p;
+        }
+        Some(&self.nodes[i].cid)
+    }
+
+    /// `cid` and all the replies under it, in the order they'd be
+    /// shown: each document followed by its replies, with how deep it
+    /// is below `cid`.
+    pub fn thread(&self, cid: &Cid) -> Vec<(usize, &Cid)> {
+        let mut out = Vec::new();
+        let mut stack: Vec<(usize, usize)> = self.find(cid).into_iter().map(|i| (0, i)).collect();
+        while let Some((depth, i)) = stack.pop() {
+            out.push((depth, &self.nodes[i].cid));
+            stack.extend(self.nodes[i].children.iter().rev().map(|&c| (depth + 1, c)));
+        }
+        out
+    }
+
+    /// Statistics for `cid` and all the replies under it.  Use
+    /// `root_of()` first to get them for the whole thread.
+    pub fn stats(&self, cid: &Cid) -> Option<Stats> {
+        self.find(cid)?;
+        let mut stats = Stats {
+            messages: 0,
+            depth: 0,
+            participants: Vec::new(),
+            first: None,
+            last: None,
+        };
+        for (depth, c) in self.thread(cid) {
+            let document = self.document(c).expect("threaded document");
+            stats.messages += 1;
+            stats.depth = stats.depth.max(depth);
+            let author = document.author_id.as_ref().map(|a| a.to_string())
+                .or_else(|| document.author.clone());
+            if let Some(author) = author {
+                if !stats.participants.contains(&author) {
+                    stats.participants.push(author);
+                }
+            }
+            if let Some(date) = document.date {
+                stats.first = Some(stats.first.map_or(date, |d| d.min(date)));
+                stats.last = Some(stats.last.map_or(date, |d| d.max(date)));
+            }
+        }
+        Some(stats)
+    }
+}
+
+impl<'a> IntoIterator for &'a Threads {
+    type Item = (&'a Cid, &'a Document);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        Iter(self.nodes.iter())
+    }
+}
+
+/// Every document, in no particular order.
+pub struct Iter<'a>(slice::Iter<'a, Node>);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Cid, &'a Document);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|n| (&n.cid, &n.document))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::TimeZone;
+
+    use document::{Element, Part, Segment};
+    use identity::Identity;
+    use store::MemoryStore;
+    use store::tests::Liar;
+    use super::*;
+
+    fn post(text: &str, minute: Option<u32>, reply_to: Option<&Cid>) -> Document {
+        let mut doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![Element::Text(text.to_string())])])]);
+        doc.date = minute.map(|m| Utc.with_ymd_and_hms(2018, 1, 1, 12, m, 0).unwrap());
+        doc.in_response_to = reply_to.cloned();
+        doc
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut store = MemoryStore::new();
+        let mut root = post("root", Some(0), None);
+        root.author_id = Some(Identity::new("icefox", "alopex.li"));
+        let root = store.put_document(&root);
+        let late = store.put_document(&post("late", Some(30), Some(&root)));
+        let undated = store.put_document(&post("undated", None, Some(&root)));
+        let mut early = post("early", Some(10), Some(&root));
+        early.author = Some("Someone".to_string());
+        let early = store.put_document(&early);
+        let deep = store.put_document(&post("deep", Some(20), Some(&early)));
+
+        let threads = Threads::load(&store, &[deep.clone(), late.clone(), undated.clone()]).unwrap();
+        assert_eq!(threads.len(), 5);
+        assert!(threads.missing().is_empty());
+        assert_eq!(threads.roots(), vec![&root]);
+        assert!(threads.orphans().is_empty());
+        assert_eq!(threads.children(&root), vec![&early, &late, &undated]);
+        assert_eq!(threads.parent(&deep), Some(&early));
+        assert_eq!(threads.root_of(&deep), Some(&root));
+        assert_eq!(threads.thread(&root), vec![
+            (0, &root),
+            (1, &early),
+            (2, &deep),
+            (1, &late),
+            (1, &undated),
+        ]);
+        assert_eq!(threads.stats(&root), Some(Stats {
+            messages: 5,
+            depth: 2,
+            participants: vec!["icefox@alopex.li".to_string(), "Someone".to_string()],
+            first: threads.document(&root).unwrap().date,
+            last: threads.document(&late).unwrap().date,
+        }));
+        assert_eq!(threads.stats(&early).map(|s| (s.messages, s.depth)), Some((2, 1)));
+        assert_eq!(threads.stats(&post("elsewhere", None, None).cid()), None);
+    }
+
+    #[test]
+    fn test_orphans() {
+        let gone = post("gone", Some(0), None);
+        let gone_cid = gone.cid();
+        let a = post("a", Some(5), Some(&gone_cid));
+        let b = post("b", Some(1), Some(&gone_cid));
+        let reply = post("reply", Some(6), Some(&a.cid()));
+        let other = post("other", None, None);
+
+        let threads = Threads::new(vec![reply.clone(), a.clone(), b.clone(), other.clone(), a.clone()]);
+        assert_eq!(threads.len(), 4);
+        assert_eq!(threads.roots(), vec![&other.cid()]);
+        assert_eq!(threads.orphans(), vec![&b.cid(), &a.cid()]);
+        assert_eq!(threads.root_of(&reply.cid()), Some(&a.cid()));
+        assert_eq!(threads.parent(&a.cid()), None);
+        // Nothing was fetched, so nothing is missing.
+        assert!(threads.missing().is_empty());
+
+        let mut store = MemoryStore::new();
+        store.put_document(&a);
+        let loaded = Threads::load(&store, &[a.cid()]).unwrap();
+        assert_eq!(loaded.missing(), &[gone_cid]);
+        assert_eq!(loaded.orphans(), vec![&a.cid()]);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let x = post("x", None, None).cid();
+        let y = post("y", None, None).cid();
+        let mut liar = Liar(HashMap::new());
+        liar.0.insert(x.to_string(), post("x", Some(0), Some(&y)).to_cbor());
+        liar.0.insert(y.to_string(), post("y", Some(1), Some(&x)).to_cbor());
+
+        let threads = Threads::load(&liar, slice::from_ref(&x)).unwrap();
+        assert_eq!(threads.len(), 2);
+        assert!(threads.roots().is_empty());
+        assert_eq!(threads.orphans().len(), 1);
+        let top = threads.orphans()[0].clone();
+        assert_eq!(threads.thread(&top).len(), 2);
+    }
+}
@@ -0,0 +1,384 @@
+//! Tables of contents and section numbering.
+
+use std::collections::HashSet;
+
+use document::{plain_text, Document, Element, Elements, ListType, Part, Segment};
+use path::Path;
+use visit::{self, Visit};
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Options {
+    /// Put numbers like `1.2.3` in front of section headings.
+    pub numbered: bool,
+    /// Put the table of contents itself at the top of the document.
+    pub insert: bool,
+}
+
+/// A section in the table of contents.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    /// Where the section is in the outline, counting from 1, so
+    /// `[1, 2]` is the second subsection of the first section.
+    pub number: Vec<usize>,
+    /// Index of the section in the document's `contents`.
+    pub part: usize,
+    pub heading: Elements,
+    /// The first anchor in the heading, or one made up from its text.
+    pub anchor: String,
+    /// Whether `anchor` was made up, rather than already being in the
+    /// heading.
+    pub generated: bool,
+    pub children: Vec<Entry>,
+}
+
+impl Entry {
+    /// The number as it's usually written, like `1.2.3`.
+    pub fn number_string(&self) -> String {
+        let n: Vec<String> = self.number.iter().map(|n| n.to_string()).collect();
+        n.join(".")
+    }
+}
+
+/// Turns heading text into something usable as an anchor name:
+/// lower case letters and digits, with runs of anything else turned
+/// into a single `-`.
+pub fn slug(text: &str) -> String {
+    let mut s = String::new();
+    for c in text.chars().flat_map(|c| c.to_lowercase()) {
+        if c.is_alphanumeric() {
+            s.push(c);
+        } else if !s.is_empty() && !s.ends_with('-') {
+            s.push('-');
+        }
+    }
+    while s.ends_with('-') {
+        s.pop();
+    }
+    if s.is_empty() {
+        s.push_str("section");
+    }
+    s
+}
+
+/// Builds the outline of a document from its sections.  A section
+/// belongs to the closest section before it with a lower level, so a
+/// level 3 section right after a level 1 one still gets numbered 1.1.
+///
+/// Generated anchors depend only on the headings, and never clash with
+/// an anchor already in the document.  When two headings have the same
+/// text, the later ones get `-2`, `-3` and so on.
+pub fn contents(doc: &Document) -> Vec<Entry> {
+    let mut taken = all_anchors(doc);
+    let mut roots: Vec<Entry> = Vec::new();
+    // Levels of the sections enclosing the current one
+    let mut levels: Vec<u32> = Vec::new();
+    for (i, part) in doc.contents.iter().enumerate() {
+        if let Part::Section { level, ref heading, .. } = *part {
+            while levels.last().is_some_and(|&l| l >= level) {
+                levels.pop();
+            }
+            let mut siblings = &mut roots;
+            let mut number = Vec::new();
+            for _ in 0..levels.len() {
+                number.push(siblings.len());
+                siblings = &mut { siblings }.last_mut().expect("enclosing section").children;
+            }
+            number.push(siblings.len() + 1);
+            levels.push(level);
+
+            let (anchor, generated) = match first_anchor(heading) {
+                Some(a) => (a.to_string(), false),
+                None => (unique(slug(&plain_text(heading)), &mut taken), true),
+            };
+            siblings.push(Entry {
+                number,
+                part: i,
+                heading: heading.clone(),
+                anchor,
+                generated,
+                children: Vec::new(),
+            });
+        }
+    }
+    roots
+}
+
+/// The table of contents as a bulleted list, with nested lists for
+/// subsections.
+pub fn to_list(entries: &[Entry], numbered: bool) -> Segment {
+    let mut elements = Vec::new();
+    for e in entries {
+        let mut item = Vec::new();
+        if numbered {
+            item.push(Element::Text(format!("{} ", e.number_string())));
+        }
+        item.extend(without_anchors(&e.heading));
+        elements.push(Segment::Para(item));
+        if !e.children.is_empty() {
+            elements.push(to_list(&e.children, numbered));
+        }
+    }
+    Segment::List { type_: ListType::Bulleted, elements }
+}
+
+/// The index of the section an anchor names, whether it's written in
+/// the heading or generated by `contents()`.
+pub fn section(doc: &Document, anchor: &str) -> Option<usize> {
+    fn find(entries: &[Entry], anchor: &str) -> Option<usize> {
+        entries.iter()
+            .find_map(|e| if e.anchor == anchor { Some(e.part) } else { find(&e.children, anchor) })
+    }
+    find(&contents(doc), anchor)
+}
+
+/// Gives every section heading an anchor, numbers the headings and adds
+/// a table of contents, depending on the options.
+///
+/// The table of contents goes in the first body part, after any
+/// abstracts, or in a new body part at the start if there isn't one
+/// before the first section.  Numbering a document that's already
+/// numbered will number it again.
+pub fn apply(doc: &Document, options: &Options) -> Document {
+    let entries = contents(doc);
+    let mut doc = doc.clone();
+
+    let mut stack: Vec<&Entry> = entries.iter().rev().collect();
+    while let Some(e) = stack.pop() {
+        stack.extend(e.children.iter().rev());
+        if let Part::Section { ref mut heading, .. } = doc.contents[e.part] {
+            let mut prefix = Vec::new();
+            if e.generated {
+                prefix.push(Element::Anchor(e.anchor.clone()));
+            }
+            if options.numbered {
+                prefix.push(Element::Text(format!("{} ", e.number_string())));
+            }
+            heading.splice(0..0, prefix);
+        }
+    }
+
+    if options.insert && !entries.is_empty() {
+        let list = to_list(&entries, options.numbered);
+        match doc.contents.first_mut() {
+            Some(Part::Body(segments)) => {
+                let at = segments.iter().take_while(|s| matches!(s, Segment::Abstract(_))).count();
+                segments.insert(at, list);
+            }
+            _ => doc.contents.insert(0, Part::Body(vec![list])),
+        }
+    }
+    doc
+}
+
+/// A heading as it goes in the table of contents.  Anchors and footnotes
+/// are left out, wherever they are, since they'd be duplicates of the
+/// ones in the heading itself.
+fn without_anchors(elements: &[Element]) -> Elements {
+    elements.iter().filter_map(|e| Some(match *e {
+        Element::Anchor(_) | Element::Footnote(_) => return None,
+        Element::Strong(ref e) => Element::Strong(without_anchors(e)),
+        Element::Emphasized(ref e) => Element::Emphasized(without_anchors(e)),
+        Element::Subscript(ref e) => Element::Subscript(without_anchors(e)),
+        Element::Superscript(ref e) => Element::Superscript(without_anchors(e)),
+        Element::Insertion(ref e) => Element::Insertion(without_anchors(e)),
+        Element::Deletion(ref e) => Element::Deletion(without_anchors(e)),
+        Element::Preformatted(ref e) => Element::Preformatted(without_anchors(e)),
+        Element::Span { ref contents, ref lang, dir } => {
+            Element::Span { contents: without_anchors(contents), lang: lang.clone(), dir }
+        }
+        Element::Xref { ref contents, ref target, ref fragment } => {
+            Element::Xref { contents: without_anchors(contents), target: target.clone(), fragment: fragment.clone() }
+        }
+        Element::Link { ref contents, ref url } => {
+            Element::Link { contents: without_anchors(contents), url: url.clone() }
+        }
+        ref other => other.clone(),
+    })).collect()
+}
+
+fn first_anchor(elements: &[Element]) -> Option<&str> {
+    elements.iter().find_map(|e| match *e {
+        Element::Anchor(ref a) => Some(&**a),
+        Element::Strong(ref e) |
+        Element::Emphasized(ref e) |
+        Element::Subscript(ref e) |
+        Element::Superscript(ref e) |
+        Element::Insertion(ref e) |
+        Element::Deletion(ref e) |
+        Element::Preformatted(ref e) |
+        Element::Span { contents: ref e, .. } => first_anchor(e),
+        _ => None,
+    })
+}
+
+/// `name`, or `name-2`, `name-3` and so on if it's taken.
+fn unique(name: String, taken: &mut HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut n = 2;
+    while taken.contains(&candidate) {
+        candidate = format!("{}-{}", name, n);
+        n += 1;
+    }
+    taken.insert(candidate.clone());
+    candidate
+}
+
+fn all_anchors(doc: &Document) -> HashSet<String> {
+    struct Collector(HashSet<String>);
+
+    impl<'ast> Visit<'ast> for Collector {
+        fn visit_element(&mut self, path: &mut Path, node: &'ast Element) {
+            if let Element::Anchor(ref a) = *node {
+                self.0.insert(a.clone());
+            }
+            visit::visit_element(self, path, node);
+        }
+    }
+
+    let mut c = Collector(HashSet::new());
+    c.visit_document(&mut Path::new(), doc);
+    c.0
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::tests::text;
+    use document::*;
+    use super::*;
+
+    fn sec(level: u32, heading: &str) -> Part {
+        Part::Section { level, heading: vec![text(heading)], contents: vec![] }
+    }
+
+    fn outline(entries: &[Entry], out: &mut Vec<(String, String)>) {
+        for e in entries {
+            out.push((e.number_string(), e.anchor.clone()));
+            outline(&e.children, out);
+        }
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Hello, World!"), "hello-world");
+        assert_eq!(slug("  1.2 Über   alles "), "1-2-über-alles");
+        assert_eq!(slug("???"), "section");
+        assert_eq!(slug(""), "section");
+    }
+
+    #[test]
+    fn test_numbering() {
+        let doc = Document::new(vec![
+            Part::Body(vec![]),
+            sec(1, "Intro"),
+            sec(2, "Background"),
+            sec(3, "History"),
+            sec(2, "Goals"),
+            sec(1, "Design"),
+            sec(3, "Deep"),
+            sec(2, "Shallow"),
+        ]);
+        let entries = contents(&doc);
+        let mut out = Vec::new();
+        outline(&entries, &mut out);
+        let expected = [("1", "intro"), ("1.1", "background"), ("1.1.1", "history"), ("1.2", "goals"),
+                        ("2", "design"), ("2.1", "deep"), ("2.2", "shallow")];
+        assert_eq!(out, expected.iter().map(|&(n, a)| (n.to_string(), a.to_string())).collect::<Vec<_>>());
+        assert_eq!(entries[1].part, 5);
+        assert_eq!(section(&doc, "goals"), Some(4));
+        assert_eq!(section(&doc, "nope"), None);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let doc = Document::new(vec![
+            Part::Body(vec![Segment::Para(vec![Element::Anchor("notes".to_string())])]),
+            sec(1, "Notes"),
+            sec(1, "Notes"),
+            Part::Section {
+                level: 1,
+                heading: vec![Element::Emphasized(vec![Element::Anchor("mine".to_string())]), text("Notes")],
+                contents: vec![],
+            },
+            Part::Section { level: 1, heading: vec![], contents: vec![] },
+        ]);
+        let anchors: Vec<(String, bool)> = contents(&doc).into_iter().map(|e| (e.anchor, e.generated)).collect();
+        assert_eq!(anchors, vec![
+            ("notes-2".to_string(), true),
+            ("notes-3".to_string(), true),
+            ("mine".to_string(), false),
+            ("section".to_string(), true),
+        ]);
+    }
+
+    #[test]
+    fn test_apply() {
+        let doc = Document::new(vec![
+            Part::Body(vec![Segment::Abstract(vec![text("Short.")]), Segment::Para(vec![text("Long.")])]),
+            sec(1, "One"),
+            sec(2, "Two"),
+        ]);
+        let done = apply(&doc, &Options { numbered: true, insert: true });
+        assert_eq!(done.contents, vec![
+            Part::Body(vec![
+                Segment::Abstract(vec![text("Short.")]),
+                Segment::List {
+                    type_: ListType::Bulleted,
+                    elements: vec![
+                        Segment::Para(vec![text("1 "), text("One")]),
+                        Segment::List {
+                            type_: ListType::Bulleted,
+                            elements: vec![Segment::Para(vec![text("1.1 "), text("Two")])],
+                        },
+                    ],
+                },
+                Segment::Para(vec![text("Long.")]),
+            ]),
+            Part::Section {
+                level: 1,
+                heading: vec![Element::Anchor("one".to_string()), text("1 "), text("One")],
+                contents: vec![],
+            },
+            Part::Section {
+                level: 2,
+                heading: vec![Element::Anchor("two".to_string()), text("1.1 "), text("Two")],
+                contents: vec![],
+            },
+        ]);
+        assert!(done.is_valid());
+
+        // Anchors stay the same once they're in the document.
+        let again = apply(&done, &Options::default());
+        assert_eq!(again, done);
+    }
+
+    #[test]
+    fn test_nested_anchors() {
+        let doc = Document::new(vec![Part::Section {
+            level: 1,
+            heading: vec![
+                Element::Emphasized(vec![Element::Anchor("mine".to_string()), text("One")]),
+                Element::Strong(vec![Element::Footnote(vec![text("Note")])]),
+            ],
+            contents: vec![],
+        }]);
+        assert!(doc.is_valid());
+        let done = apply(&doc, &Options { numbered: false, insert: true });
+        assert_eq!(done.contents[0], Part::Body(vec![Segment::List {
+            type_: ListType::Bulleted,
+            elements: vec![Segment::Para(vec![Element::Emphasized(vec![text("One")]), Element::Strong(vec![])])],
+        }]));
+        assert!(done.is_valid());
+    }
+
+    #[test]
+    fn test_insert_without_body() {
+        let doc = Document::new(vec![sec(1, "Only")]);
+        let done = apply(&doc, &Options { numbered: false, insert: true });
+        assert_eq!(done.contents[0], Part::Body(vec![Segment::List {
+            type_: ListType::Bulleted,
+            elements: vec![Segment::Para(vec![text("Only")])],
+        }]));
+        assert_eq!(done.contents.len(), 2);
+    }
+}
@@ -0,0 +1,442 @@
+//! Expanding `Segment::Transclusion`s into the content they point at.
+
+use std::error;
+use std::fmt;
+
+use cid::Cid;
+
+use document::{Document, Element, Part, Segment};
+use path::{Path, Step};
+use store::Lookup;
+use xref;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The source document or anchor couldn't be found.
+    Xref(xref::Error),
+    /// The source includes itself, directly or through other documents.
+    /// Since a document can't know its own CID, this only happens when
+    /// a store hands out blocks that don't match their CIDs.
+    Cycle(Cid),
+    /// Transclusions nested deeper than `Options::max_depth`.
+    TooDeep(Cid),
+    /// More transclusions in all than `Options::max_expansions`.
+    TooMany(Cid),
+    /// A transclusion in a table cell that came to this many segments
+    /// rather than one, which is all a cell can hold.
+    Cell(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Xref(ref e) => write!(f, "{}", e),
+            Error::Cycle(ref cid) => write!(f, "document {} includes itself", cid),
+            Error::TooDeep(ref cid) => write!(f, "transclusion of {} is nested too deeply", cid),
+            Error::TooMany(ref cid) => write!(f, "too many transclusions to expand {}", cid),
+            Error::Cell(n) => write!(f, "transclusion in a table cell came to {} segments, not one", n),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<xref::Error> for Error {
+    fn from(e: xref::Error) -> Error {
+        Error::Xref(e)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Options {
+    /// How many transclusions deep to go.  Zero expands nothing.
+    pub max_depth: usize,
+    /// How many transclusions to expand in all, counting nested ones.
+    /// A document that includes several documents that each include
+    /// several more can take a lot of fetching, even within `max_depth`.
+    pub max_expansions: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { max_depth: 8, max_expansions: 1000 }
+    }
+}
+
+/// A document with its transclusions expanded.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Expanded {
+    pub document: Document,
+    /// The transclusions that couldn't be expanded, and why.  For ones
+    /// nested inside other documents, the path is that of the outermost
+    /// transclusion.  They're left in the document as they were, so
+    /// renderers show them as links.
+    pub errors: Vec<(Path, Error)>,
+}
+
+/// The segments a transclusion stands for, without expanding any
+/// transclusions inside them.
+///
+/// A fragment naming a section gets that section's contents, along
+/// with any subsections; one naming an anchor anywhere else gets the
+/// segment it's in.  Without a fragment, the whole document is
+/// included.  Sections are flattened along the way, with each heading
+/// that's included turned into a paragraph of strong text.
+pub fn resolve<L>(store: &L, source: &Cid, fragment: Option<&str>) -> Result<Vec<Segment>, Error>
+    where L: Lookup + ?Sized
+{
+    let xref::Resolved { document, anchor } = xref::resolve(store, source, fragment)?;
+    let mut parts = document.contents;
+    let steps = anchor.as_ref().map(|p| p.steps()).unwrap_or(&[]);
+    Ok(match *steps {
+        [Step::Part(i), Step::Heading, ..] => {
+            let level = match parts[i] {
+                Part::Section { level, .. } => level,
+                Part::Body(_) => unreachable!("bodies have no heading"),
+            };
+            let end = parts[i + 1..].iter()
+                .position(|p| match *p {
+                    Part::Section { level: l, .. } => l <= level,
+                    Part::Body(_) => true,
+                })
+                .map_or(parts.len(), |n| i + 1 + n);
+            let mut parts: Vec<Part> = parts.drain(i..end).collect();
+            // The transcluding document supplies its own heading.
+            if let Part::Section { ref mut heading, .. } = parts[0] {
+                heading.clear();
+            }
+            flatten(parts)
+        }
+        [Step::Part(i), Step::Segment(j), ..] => {
+            let mut segments = match parts.swap_remove(i) {
+                Part::Body(s) | Part::Section { contents: s, .. } => s,
+            };
+            vec![segments.swap_remove(j)]
+        }
+        _ => flatten(parts),
+    })
+}
+
+/// Replaces every transclusion in a document with what it points at,
+/// recursively.  Transclusions that can't be expanded are left alone
+/// and reported.
+pub fn expand<L>(store: &L, doc: &Document, options: &Options) -> Expanded
+    where L: Lookup + ?Sized
+{
+    let mut e = Expander {
+        store,
+        options,
+        stack: vec![(doc.cid(), None)],
+        origin: Path::new(),
+        expansions: 0,
+        errors: Vec::new(),
+    };
+    let mut document = doc.clone();
+    let mut path = Path::new();
+    document.contents = doc.contents.iter().enumerate().map(|(i, part)| {
+        path.push(Step::Part(i));
+        let part = match *part {
+            Part::Body(ref s) => Part::Body(e.segments(&mut path, s)),
+            Part::Section { level, ref heading, ref contents } => Part::Section {
+                level,
+                heading: heading.clone(),
+                contents: e.segments(&mut path, contents),
+            },
+        };
+        path.pop();
+        part
+    }).collect();
+    Expanded { document, errors: e.errors }
+}
+
+fn flatten(parts: Vec<Part>) -> Vec<Segment> {
+    let mut out = Vec::new();
+    for part in parts {
+        match part {
+            Part::Body(s) => out.extend(s),
+            Part::Section { heading, contents, .. } => {
+                if !heading.is_empty() {
+                    out.push(Segment::Para(vec![Element::Strong(heading)]));
+                }
+                out.extend(contents);
+            }
+        }
+    }
+    out
+}
+
+struct Expander<'s, 'o, L: 's + ?Sized> {
+    store: &'s L,
+    options: &'o Options,
+    /// The transclusions we're inside of, starting with the document
+    /// itself, for spotting cycles.
+    stack: Vec<(Cid, Option<String>)>,
+    /// Where the outermost transclusion we're inside of is.
+    origin: Path,
+    /// How many transclusions we've expanded so far.
+    expansions: usize,
+    errors: Vec<(Path, Error)>,
+}
+
+impl<'s, 'o, L: Lookup + ?Sized> Expander<'s, 'o, L> {
+    fn segments(&mut self, path: &mut Path, segments: &[Segment]) -> Vec<Segment> {
+        let mut out = Vec::new();
+        for (i, s) in segments.iter().enumerate() {
+            path.push(Step::Segment(i));
+            out.extend(self.segment(path, s));
+            path.pop();
+        }
+        out
+    }
+
+    /// A segment can turn into several, so this returns a list.
+    fn segment(&mut self, path: &mut Path, segment: &Segment) -> Vec<Segment> {
+        match *segment {
+            Segment::Transclusion { ref source, ref fragment } => {
+                match self.transclude(path, source, fragment) {
+                    Ok(segments) => segments,
+                    Err(e) => {
+                        let at = if self.stack.len() == 1 { path.clone() } else { self.origin.clone() };
+                        self.errors.push((at, e));
+                        vec![segment.clone()]
+                    }
+                }
+            }
+            Segment::Table { ref header, ref body, ref footer } => {
+                // Each segment in a row is a cell, so a transclusion
+                // there is only expanded if it comes to a single segment.
+                let body = body.iter().enumerate().map(|(i, row)| {
+                    path.push(Step::TableRow(i));
+                    let row = row.iter().enumerate().map(|(j, cell)| {
+                        path.push(Step::Segment(j));
+                        let mut expanded = self.segment(path, cell);
+                        let cell = if expanded.len() == 1 {
+                            expanded.remove(0)
+                        } else {
+                            let at = if self.stack.len() == 1 { path.clone() } else { self.origin.clone() };
+                            self.errors.push((at, Error::Cell(expanded.len())));
+                            cell.clone()
+                        };
+                        path.pop();
+                        cell
+                    }).collect();
+                    path.pop();
+                    row
+                }).collect();
+                vec![Segment::Table { header: header.clone(), body, footer: footer.clone() }]
+            }
+            Segment::List { ref type_, ref elements } => {
+                vec![Segment::List { type_: type_.clone(), elements: self.segments(path, elements) }]
+            }
+            // A quotation of several segments is a run of quotes.
+            Segment::Quote(ref s) => {
+                path.push(Step::Quote);
+                let quoted = self.segment(path, s);
+                path.pop();
+                quoted.into_iter().map(|s| Segment::Quote(Box::new(s))).collect()
+            }
+            Segment::Conflict { ref ours, ref theirs } => {
+                let ours = ours.iter().enumerate().flat_map(|(i, s)| {
+                    path.push(Step::Ours(i));
+                    let s = self.segment(path, s);
+                    path.pop();
+                    s
+                }).collect();
+                let theirs = theirs.iter().enumerate().flat_map(|(i, s)| {
+                    path.push(Step::Theirs(i));
+                    let s = self.segment(path, s);
+                    path.pop();
+                    s
+                }).collect();
+                vec![Segment::Conflict { ours, theirs }]
+            }
+            _ => vec![segment.clone()],
+        }
+    }
+
+    fn transclude(&mut self, path: &mut Path, source: &Cid, fragment: &Option<String>)
+                  -> Result<Vec<Segment>, Error> {
+        let key = (source.clone(), fragment.clone());
+        if self.stack.contains(&key) {
+            return Err(Error::Cycle(source.clone()));
+        }
+        if self.stack.len() > self.options.max_depth {
+            return Err(Error::TooDeep(source.clone()));
+        }
+        if self.expansions == self.options.max_expansions {
+            return Err(Error::TooMany(source.clone()));
+        }
+        self.expansions += 1;
+        let segments = resolve(self.store, source, fragment.as_ref().map(|f| &**f))?;
+        if self.stack.len() == 1 {
+            self.origin = path.clone();
+        }
+        self.stack.push(key);
+        let mut out = Vec::new();
+        for s in &segments {
+            out.extend(self.segment(path, s));
+        }
+        self.stack.pop();
+        Ok(out)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use document::tests::{cid, text, CID_A, CID_B};
+    use document::*;
+    use store::{self, MemoryStore};
+    use store::tests::Liar;
+    use super::*;
+
+    fn para(s: &str) -> Segment {
+        Segment::Para(vec![text(s)])
+    }
+
+    fn section(level: u32, heading: &str, contents: Vec<Segment>) -> Part {
+        Part::Section { level, heading: vec![text(heading)], contents }
+    }
+
+    fn transclusion(source: &Cid, fragment: Option<&str>) -> Segment {
+        Segment::Transclusion { source: source.clone(), fragment: fragment.map(|f| f.to_string()) }
+    }
+
+    fn body(segments: Vec<Segment>) -> Document {
+        Document::new(vec![Part::Body(segments)])
+    }
+
+    fn source() -> Document {
+        Document::new(vec![
+            Part::Body(vec![para("intro"), Segment::Para(vec![text("x"), Element::Anchor("here".to_string())])]),
+            section(1, "One", vec![para("one")]),
+            section(2, "Sub", vec![para("sub")]),
+            section(1, "Two", vec![para("two")]),
+        ])
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut store = MemoryStore::new();
+        let c = store.put_document(&source());
+        assert_eq!(resolve(&store, &c, None).unwrap(), vec![
+            para("intro"),
+            Segment::Para(vec![text("x"), Element::Anchor("here".to_string())]),
+            Segment::Para(vec![Element::Strong(vec![text("One")])]),
+            para("one"),
+            Segment::Para(vec![Element::Strong(vec![text("Sub")])]),
+            para("sub"),
+            Segment::Para(vec![Element::Strong(vec![text("Two")])]),
+            para("two"),
+        ]);
+        assert_eq!(resolve(&store, &c, Some("one")).unwrap(), vec![
+            para("one"),
+            Segment::Para(vec![Element::Strong(vec![text("Sub")])]),
+            para("sub"),
+        ]);
+        assert_eq!(resolve(&store, &c, Some("two")).unwrap(), vec![para("two")]);
+        assert_eq!(resolve(&store, &c, Some("here")).unwrap(),
+                   vec![Segment::Para(vec![text("x"), Element::Anchor("here".to_string())])]);
+        assert_eq!(resolve(&store, &c, Some("nowhere")), Err(Error::Xref(xref::Error::DanglingFragment {
+            target: c.clone(),
+            fragment: "nowhere".to_string(),
+        })));
+    }
+
+    #[test]
+    fn test_expand_nested() {
+        let mut store = MemoryStore::new();
+        let inner = store.put_document(&body(vec![para("inner")]));
+        let middle = store.put_document(&body(vec![para("before"), transclusion(&inner, None)]));
+        let doc = Document::new(vec![section(1, "Top", vec![
+            Segment::Quote(Box::new(transclusion(&middle, None))),
+            para("after"),
+        ])]);
+        let expanded = expand(&store, &doc, &Options::default());
+        assert_eq!(expanded.errors, vec![]);
+        assert_eq!(expanded.document, Document::new(vec![section(1, "Top", vec![
+            Segment::Quote(Box::new(para("before"))),
+            Segment::Quote(Box::new(para("inner"))),
+            para("after"),
+        ])]));
+
+        // Not deep enough for the inner one
+        let expanded = expand(&store, &doc, &Options { max_depth: 1, ..Options::default() });
+        assert_eq!(expanded.errors, vec![
+            (Path(vec![Step::Part(0), Step::Segment(0), Step::Quote]), Error::TooDeep(inner.clone())),
+        ]);
+        assert_eq!(expanded.document.contents[0], section(1, "Top", vec![
+            Segment::Quote(Box::new(para("before"))),
+            Segment::Quote(Box::new(transclusion(&inner, None))),
+            para("after"),
+        ]));
+    }
+
+    #[test]
+    fn test_expand_failures() {
+        let mut blocks = HashMap::new();
+        blocks.insert(CID_A.to_string(), body(vec![para("a"), transclusion(&cid(CID_B), None)]).to_cbor());
+        blocks.insert(CID_B.to_string(), body(vec![para("b"), transclusion(&cid(CID_A), None)]).to_cbor());
+        let store = Liar(blocks);
+        let gone = body(vec![]).cid();
+        let doc = body(vec![transclusion(&cid(CID_A), None), transclusion(&gone, None)]);
+
+        let expanded = expand(&store, &doc, &Options::default());
+        assert_eq!(expanded.errors, vec![
+            (Path(vec![Step::Part(0), Step::Segment(0)]), Error::Cycle(cid(CID_A))),
+            (Path(vec![Step::Part(0), Step::Segment(1)]),
+             Error::Xref(xref::Error::Store(store::Error::Missing(gone.clone())))),
+        ]);
+        assert_eq!(expanded.document, body(vec![
+            para("a"),
+            para("b"),
+            transclusion(&cid(CID_A), None),
+            transclusion(&gone, None),
+        ]));
+    }
+
+    #[test]
+    fn test_expansion_limit() {
+        let mut store = MemoryStore::new();
+        let leaf = store.put_document(&body(vec![para("leaf")]));
+        let mut source = leaf.clone();
+        for _ in 0..3 {
+            let t = transclusion(&source, None);
+            source = store.put_document(&body(vec![t.clone(), t.clone(), t]));
+        }
+        let doc = body(vec![transclusion(&source, None)]);
+
+        let expanded = expand(&store, &doc, &Options::default());
+        assert_eq!(expanded.errors, vec![]);
+        assert_eq!(expanded.document, body(vec![para("leaf"); 27]));
+
+        // 1 + 3 + 9 + 27 transclusions would be needed.
+        let options = Options { max_expansions: 39, ..Options::default() };
+        let expanded = expand(&store, &doc, &options);
+        assert_eq!(expanded.errors.len(), 1);
+        assert_eq!(expanded.errors[0].0, Path(vec![Step::Part(0), Step::Segment(0)]));
+        match expanded.errors[0].1 {
+            Error::TooMany(_) => (),
+            ref other => panic!("expected too many transclusions, got {:?}", other),
+        }
+        let mut expected = vec![para("leaf"); 26];
+        expected.push(transclusion(&leaf, None));
+        assert_eq!(expanded.document, body(expected));
+    }
+
+    #[test]
+    fn test_table_cells() {
+        let mut store = MemoryStore::new();
+        let one = store.put_document(&body(vec![para("one")]));
+        let two = store.put_document(&body(vec![para("a"), para("b")]));
+        let table = |row| Segment::Table { header: vec![], body: vec![row], footer: vec![] };
+        let doc = body(vec![table(vec![transclusion(&one, None), transclusion(&two, None)])]);
+        let expanded = expand(&store, &doc, &Options::default());
+        assert_eq!(expanded.document, body(vec![table(vec![para("one"), transclusion(&two, None)])]));
+        assert_eq!(expanded.errors, vec![
+            (Path(vec![Step::Part(0), Step::Segment(0), Step::TableRow(0), Step::Segment(1)]), Error::Cell(2)),
+        ]);
+    }
+}
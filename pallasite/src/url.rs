@@ -0,0 +1,137 @@
+//! URLs for linking to things that aren't documents, like ordinary
+//! web pages.  Only a few schemes are allowed, so a document can't
+//! smuggle in something like a `javascript:` link.
+
+use std::error;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de;
+
+/// The schemes a `Url` may have.
+pub const SCHEMES: &[&str] = &["http", "https", "mailto", "ipfs", "ipns"];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// There's no `scheme:` at the start, as with a relative URL.
+    NoScheme,
+    /// A scheme that isn't in `SCHEMES`.
+    Scheme(String),
+    /// Nothing after the scheme, like `https://` or `mailto:`.
+    NoHost,
+    /// Whitespace or a control character, which should have been
+    /// percent-encoded.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoScheme => write!(f, "URL has no scheme"),
+            Error::Scheme(ref s) => write!(f, "URL scheme {:?} is not allowed", s),
+            Error::NoHost => write!(f, "URL has no host or address"),
+            Error::InvalidCharacter(c) => write!(f, "URL contains invalid character {:?}", c),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// An absolute URL with one of the allowed `SCHEMES`.  It's kept
+/// exactly as written; the only way to make one is `parse()`, so
+/// anything holding a `Url` knows it's safe to hand to a browser.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Url(String);
+
+impl Url {
+    pub fn parse(s: &str) -> Result<Url, Error> {
+        if let Some(c) = s.chars().find(|c| c.is_whitespace() || c.is_control()) {
+            return Err(Error::InvalidCharacter(c));
+        }
+        let colon = s.find(':').ok_or(Error::NoScheme)?;
+        let scheme = &s[..colon];
+        let scheme_ok = scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+        if !scheme_ok {
+            return Err(Error::NoScheme);
+        }
+        let scheme = scheme.to_ascii_lowercase();
+        if !SCHEMES.contains(&&*scheme) {
+            return Err(Error::Scheme(scheme));
+        }
+        let rest = &s[colon + 1..];
+        let host = if scheme == "mailto" {
+            rest.split(['?', '#']).next()
+        } else {
+            rest.strip_prefix("//").and_then(|r| r.split(['/', '?', '#']).next())
+        };
+        match host {
+            Some(h) if !h.is_empty() => Ok(Url(s.to_string())),
+            _ => Err(Error::NoHost),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The scheme, in lower case.
+    pub fn scheme(&self) -> String {
+        let colon = self.0.find(':').expect("a Url always has a scheme");
+        self.0[..colon].to_ascii_lowercase()
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Url {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Url {
+    fn deserialize<D>(deserializer: D) -> Result<Url, D::Error>
+        where D: Deserializer<'de>
+    {
+        let s = String::deserialize(deserializer)?;
+        Url::parse(&s).map_err(|e| de::Error::custom(format!("invalid URL {:?}: {}", s, e)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed() {
+        for good in &["http://example.com", "https://example.com/a/b?c=d#e", "HTTPS://EXAMPLE.COM",
+                      "mailto:icefox@alopex.li", "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi/cat.png",
+                      "ipns://example.com"] {
+            let url = Url::parse(good).unwrap();
+            assert_eq!(url.as_str(), *good);
+        }
+        assert_eq!(Url::parse("HTTPS://EXAMPLE.COM").unwrap().scheme(), "https");
+    }
+
+    #[test]
+    fn test_rejected() {
+        assert_eq!(Url::parse("javascript:alert(1)"), Err(Error::Scheme("javascript".to_string())));
+        assert_eq!(Url::parse("JavaScript:alert(1)"), Err(Error::Scheme("javascript".to_string())));
+        assert_eq!(Url::parse("data:text/html,hi"), Err(Error::Scheme("data".to_string())));
+        assert_eq!(Url::parse("java\tscript:alert(1)"), Err(Error::InvalidCharacter('\t')));
+        assert_eq!(Url::parse("/relative/path"), Err(Error::NoScheme));
+        assert_eq!(Url::parse("cat.png"), Err(Error::NoScheme));
+        assert_eq!(Url::parse(":nothing"), Err(Error::NoScheme));
+        assert_eq!(Url::parse("https://"), Err(Error::NoHost));
+        assert_eq!(Url::parse("https:example.com"), Err(Error::NoHost));
+        assert_eq!(Url::parse("mailto:"), Err(Error::NoHost));
+    }
+}
@@ -0,0 +1,443 @@
+//! Checking documents for structural problems the type system can't
+//! catch, such as tables with ragged rows, and for things that are
+//! allowed but unfriendly, such as figures without alt text.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use document::{Document, Element, Part, Segment};
+use path::{Path, Step};
+use visit::{self, Visit};
+
+/// Something wrong with a document, and where it is.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Diagnostic {
+    pub path: Path,
+    pub kind: DiagnosticKind,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DiagnosticKind {
+    /// The document has no contents at all.
+    EmptyContents,
+    /// Section levels start at 1.
+    InvalidSectionLevel(u32),
+    /// A section is nested more than one level deeper than the one
+    /// before it, like an `<h1>` followed by an `<h5>`.
+    SectionLevelJump { from: u32, to: u32 },
+    /// A table row or footer with a different number of cells than the header.
+    TableWidth { expected: usize, found: usize },
+    /// An anchor with the same name as an earlier one.
+    DuplicateAnchor(String),
+    /// An anchor with an empty name.
+    EmptyAnchor,
+    /// A language tag that isn't well-formed BCP 47, from a document
+    /// written before tags were checked.
+    InvalidLanguageTag(String),
+    /// `date` is set but `local_date` isn't, so we can't tell what
+    /// time it was for the author.
+    DateWithoutLocalDate,
+    /// A `Segment::Conflict` left over from a merge.
+    UnresolvedConflict,
+    /// An `Element::Xref` or `Element::Link` inside another one, which
+    /// HTML can't represent.
+    NestedLink,
+    /// A figure's `media_type` isn't a well-formed MIME type.
+    InvalidMediaType(String),
+    /// A tag with an empty term.
+    EmptyTag,
+    /// The same tag as an earlier one.
+    DuplicateTag(String),
+    /// A figure with no alt text.  Only `lint()` reports this.
+    MissingAltText,
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DiagnosticKind::EmptyContents => write!(f, "document has no contents"),
+            DiagnosticKind::InvalidSectionLevel(l) => write!(f, "invalid section level {}", l),
+            DiagnosticKind::SectionLevelJump { from, to } => {
+                write!(f, "section level jumps from {} to {}", from, to)
+            }
+            DiagnosticKind::TableWidth { expected, found } => {
+                write!(f, "table row has {} cells, expected {}", found, expected)
+            }
+            DiagnosticKind::DuplicateAnchor(ref a) => write!(f, "duplicate anchor {:?}", a),
+            DiagnosticKind::EmptyAnchor => write!(f, "anchor with an empty name"),
+            DiagnosticKind::InvalidLanguageTag(ref t) => write!(f, "invalid language tag {:?}", t),
+            DiagnosticKind::DateWithoutLocalDate => write!(f, "date is set but local_date is not"),
+            DiagnosticKind::UnresolvedConflict => write!(f, "unresolved merge conflict"),
+            DiagnosticKind::NestedLink => write!(f, "link inside another link"),
+            DiagnosticKind::InvalidMediaType(ref t) => write!(f, "invalid media type {:?}", t),
+            DiagnosticKind::EmptyTag => write!(f, "tag with an empty term"),
+            DiagnosticKind::DuplicateTag(ref t) => write!(f, "duplicate tag {:?}", t),
+            DiagnosticKind::MissingAltText => write!(f, "figure has no alt text"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+/// Checks the syntax of a MIME type, like `image/png` or
+/// `text/plain; charset=utf-8`.  Doesn't check that it's registered.
+pub fn is_media_type(media_type: &str) -> bool {
+    fn is_name(s: &str) -> bool {
+        s.chars().next().is_some_and(|c| c.is_ascii_alphanumeric())
+            && s.len() <= 127
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    }
+    let mut params = media_type.split(';');
+    let essence = params.next().unwrap_or("");
+    let essence_ok = match essence.find('/') {
+        Some(i) => is_name(&essence[..i]) && is_name(&essence[i + 1..]),
+        None => false,
+    };
+    essence_ok && params.all(|p| {
+        let p = p.trim_start();
+        match p.find('=') {
+            Some(i) => is_name(&p[..i]) && i + 1 < p.len(),
+            None => false,
+        }
+    })
+}
+
+impl Document {
+    /// Checks the document's structure, returning everything wrong with it.
+    /// An empty list means the document is fine.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut v = Validator {
+            anchors: HashSet::new(),
+            section_level: None,
+            in_link: false,
+            diagnostics: Vec::new(),
+        };
+        v.visit_document(&mut Path::new(), self);
+        v.diagnostics
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_empty()
+    }
+
+    /// Looks for things that are allowed but make the document harder
+    /// to use, like figures without alt text.  These don't make the
+    /// document invalid.
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let mut l = Linter { diagnostics: Vec::new() };
+        l.visit_document(&mut Path::new(), self);
+        l.diagnostics
+    }
+}
+
+struct Validator<'a> {
+    anchors: HashSet<&'a str>,
+    /// Level of the previous section, if any
+    section_level: Option<u32>,
+    in_link: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, path: Path, kind: DiagnosticKind) {
+        self.diagnostics.push(Diagnostic { path, kind });
+    }
+}
+
+impl<'a> Visit<'a> for Validator<'a> {
+    fn visit_document(&mut self, path: &mut Path, doc: &'a Document) {
+        if doc.contents.is_empty() {
+            self.report(path.join(Step::Field("contents")), DiagnosticKind::EmptyContents);
+        }
+        if let Some(ref lang) = doc.language {
+            if !lang.is_well_formed() {
+                self.report(path.join(Step::Field("language")),
+                            DiagnosticKind::InvalidLanguageTag(lang.to_string()));
+            }
+        }
+        if doc.date.is_some() && doc.local_date.is_none() {
+            self.report(path.join(Step::Field("local_date")), DiagnosticKind::DateWithoutLocalDate);
+        }
+        for (i, tag) in doc.tags.iter().enumerate() {
+            let at = || Path(vec![Step::Field("tags"), Step::Index(i)]);
+            if tag.term.is_empty() {
+                self.report(at(), DiagnosticKind::EmptyTag);
+            } else if doc.tags[..i].contains(tag) {
+                self.report(at(), DiagnosticKind::DuplicateTag(tag.term.clone()));
+            }
+        }
+        visit::visit_document(self, path, doc);
+    }
+
+    fn visit_part(&mut self, path: &mut Path, part: &'a Part) {
+        if let Part::Section { level, .. } = *part {
+            if level == 0 {
+                self.report(path.clone(), DiagnosticKind::InvalidSectionLevel(level));
+            } else if let Some(prev) = self.section_level {
+                if level > prev + 1 {
+                    self.report(path.clone(), DiagnosticKind::SectionLevelJump { from: prev, to: level });
+                }
+            }
+            self.section_level = Some(level);
+        }
+        visit::visit_part(self, path, part);
+    }
+
+    fn visit_segment(&mut self, path: &mut Path, segment: &'a Segment) {
+        if let Segment::Conflict { .. } = *segment {
+            self.report(path.clone(), DiagnosticKind::UnresolvedConflict);
+        }
+        if let Segment::Figure { media_type: Some(ref t), .. } = *segment {
+            if !is_media_type(t) {
+                self.report(path.join(Step::Field("media_type")), DiagnosticKind::InvalidMediaType(t.clone()));
+            }
+        }
+        if let Segment::Table { ref header, ref body, ref footer } = *segment {
+            // Without a header, the first row decides how wide the table is.
+            let expected = if header.is_empty() {
+                body.first().map(|r| r.len())
+            } else {
+                Some(header.len())
+            };
+            if let Some(expected) = expected {
+                for (i, row) in body.iter().enumerate() {
+                    if row.len() != expected {
+                        self.report(path.join(Step::TableRow(i)),
+                                    DiagnosticKind::TableWidth { expected, found: row.len() });
+                    }
+                }
+                if !footer.is_empty() && footer.len() != expected {
+                    self.report(path.join(Step::TableFooter(0)),
+                                DiagnosticKind::TableWidth { expected, found: footer.len() });
+                }
+            }
+        }
+        visit::visit_segment(self, path, segment);
+    }
+
+    fn visit_element(&mut self, path: &mut Path, element: &'a Element) {
+        if let Element::Anchor(ref name) = *element {
+            if name.is_empty() {
+                self.report(path.clone(), DiagnosticKind::EmptyAnchor);
+            } else if !self.anchors.insert(name) {
+                self.report(path.clone(), DiagnosticKind::DuplicateAnchor(name.clone()));
+            }
+        }
+        if let Element::Span { lang: Some(ref lang), .. } = *element {
+            if !lang.is_well_formed() {
+                self.report(path.join(Step::Field("lang")), DiagnosticKind::InvalidLanguageTag(lang.to_string()));
+            }
+        }
+        if let Element::Xref { .. } | Element::Link { .. } = *element {
+            if self.in_link {
+                self.report(path.clone(), DiagnosticKind::NestedLink);
+            }
+            let outer = self.in_link;
+            self.in_link = true;
+            visit::visit_element(self, path, element);
+            self.in_link = outer;
+        } else {
+            visit::visit_element(self, path, element);
+        }
+    }
+}
+
+
+struct Linter {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Visit<'a> for Linter {
+    fn visit_segment(&mut self, path: &mut Path, segment: &'a Segment) {
+        if let Segment::Figure { alt: None, .. } = *segment {
+            self.diagnostics.push(Diagnostic { path: path.clone(), kind: DiagnosticKind::MissingAltText });
+        }
+        visit::visit_segment(self, path, segment);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+
+    use document::tests::{cid, kitchen_sink, text, CID_A};
+    use document::*;
+    use language::LanguageTag;
+    use path::{Path, Step};
+    use url::Url;
+    use super::*;
+
+    fn section(level: u32) -> Part {
+        Part::Section { level, heading: vec![text("h")], contents: vec![] }
+    }
+
+    fn kinds(doc: &Document) -> Vec<DiagnosticKind> {
+        doc.validate().into_iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        assert_eq!(kitchen_sink().validate(), vec![]);
+        assert!(Document::new(vec![Part::Body(vec![])]).is_valid());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut doc = Document::new(vec![]);
+        doc.language = Some(LanguageTag::lossy("english please"));
+        doc.date = Some(Utc::now());
+        assert_eq!(doc.validate(), vec![
+            Diagnostic {
+                path: Path(vec![Step::Field("contents")]),
+                kind: DiagnosticKind::EmptyContents,
+            },
+            Diagnostic {
+                path: Path(vec![Step::Field("language")]),
+                kind: DiagnosticKind::InvalidLanguageTag("english please".to_string()),
+            },
+            Diagnostic {
+                path: Path(vec![Step::Field("local_date")]),
+                kind: DiagnosticKind::DateWithoutLocalDate,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_section_levels() {
+        let doc = Document::new(vec![section(1), section(2), section(1), section(5), section(0)]);
+        let diagnostics = doc.validate();
+        assert_eq!(diagnostics, vec![
+            Diagnostic {
+                path: Path(vec![Step::Part(3)]),
+                kind: DiagnosticKind::SectionLevelJump { from: 1, to: 5 },
+            },
+            Diagnostic {
+                path: Path(vec![Step::Part(4)]),
+                kind: DiagnosticKind::InvalidSectionLevel(0),
+            },
+        ]);
+        assert_eq!(diagnostics[0].to_string(), "part[3]: section level jumps from 1 to 5");
+    }
+
+    #[test]
+    fn test_table_width() {
+        let cell = || Segment::Para(vec![text("x")]);
+        let doc = Document::new(vec![Part::Body(vec![Segment::Table {
+            header: vec![vec![text("a")], vec![text("b")]],
+            body: vec![vec![cell(), cell()], vec![cell()]],
+            footer: vec![vec![text("c")], vec![text("d")], vec![text("e")]],
+        }])]);
+        let diagnostics = doc.validate();
+        assert_eq!(diagnostics, vec![
+            Diagnostic {
+                path: Path(vec![Step::Part(0), Step::Segment(0), Step::TableRow(1)]),
+                kind: DiagnosticKind::TableWidth { expected: 2, found: 1 },
+            },
+            Diagnostic {
+                path: Path(vec![Step::Part(0), Step::Segment(0), Step::TableFooter(0)]),
+                kind: DiagnosticKind::TableWidth { expected: 2, found: 3 },
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_anchors() {
+        let doc = Document::new(vec![
+            Part::Body(vec![Segment::Para(vec![Element::Anchor("a".to_string())])]),
+            Part::Section {
+                level: 1,
+                heading: vec![Element::Anchor("".to_string())],
+                contents: vec![Segment::Quote(Box::new(Segment::Para(vec![
+                    Element::Strong(vec![Element::Anchor("a".to_string())]),
+                ])))],
+            },
+        ]);
+        let diagnostics = doc.validate();
+        assert_eq!(kinds(&doc), vec![
+            DiagnosticKind::EmptyAnchor,
+            DiagnosticKind::DuplicateAnchor("a".to_string()),
+        ]);
+        assert_eq!(diagnostics[1].path.to_string(),
+                   "part[1]/segment[0]/quote/element[0]/element[0]");
+    }
+
+    #[test]
+    fn test_nested_links() {
+        let url = || Url::parse("https://example.com").unwrap();
+        let doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![
+            Element::Link { contents: vec![text("a")], url: url() },
+            Element::Link {
+                contents: vec![Element::Strong(vec![Element::Xref {
+                    contents: vec![text("b")],
+                    target: cid(CID_A),
+                    fragment: None,
+                }])],
+                url: url(),
+            },
+        ])])]);
+        assert_eq!(doc.validate(), vec![Diagnostic {
+            path: Path(vec![Step::Part(0), Step::Segment(0), Step::Element(1), Step::Element(0), Step::Element(0)]),
+            kind: DiagnosticKind::NestedLink,
+        }]);
+    }
+
+    fn figure(media_type: Option<&str>, alt: Option<&str>) -> Segment {
+        Segment::Figure {
+            caption: vec![],
+            source: cid(CID_A),
+            media_type: media_type.map(|t| t.to_string()),
+            alt: alt.map(|a| a.to_string()),
+            dimensions: None,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn test_media_types() {
+        for good in &["image/png", "image/svg+xml", "application/vnd.ms-excel", "text/plain; charset=utf-8"] {
+            assert!(is_media_type(good), "{}", good);
+        }
+        for bad in &["", "png", "image/", "/png", "image/png/x", "image/p ng", "text/plain; charset"] {
+            assert!(!is_media_type(bad), "{}", bad);
+        }
+        let doc = Document::new(vec![Part::Body(vec![figure(Some("image"), Some("x"))])]);
+        assert_eq!(doc.validate(), vec![Diagnostic {
+            path: Path(vec![Step::Part(0), Step::Segment(0), Step::Field("media_type")]),
+            kind: DiagnosticKind::InvalidMediaType("image".to_string()),
+        }]);
+    }
+
+    #[test]
+    fn test_lint_alt_text() {
+        let doc = Document::new(vec![Part::Body(vec![
+            figure(Some("image/png"), Some("A cat")),
+            figure(None, Some("")),
+            Segment::Quote(Box::new(figure(Some("image/png"), None))),
+        ])]);
+        assert!(doc.is_valid());
+        assert_eq!(doc.lint(), vec![Diagnostic {
+            path: Path(vec![Step::Part(0), Step::Segment(2), Step::Quote]),
+            kind: DiagnosticKind::MissingAltText,
+        }]);
+        assert_eq!(kitchen_sink().lint().len(), 1);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut doc = Document::new(vec![Part::Body(vec![])]);
+        doc.tags = vec![
+            Tag::new("a"),
+            Tag::new(""),
+            Tag::from_vocabulary("a", &cid(CID_A)),
+            Tag::new("a"),
+        ];
+        let diagnostics = doc.validate();
+        assert_eq!(kinds(&doc), vec![DiagnosticKind::EmptyTag, DiagnosticKind::DuplicateTag("a".to_string())]);
+        assert_eq!(diagnostics[1].path.to_string(), "tags/[3]");
+    }
+}
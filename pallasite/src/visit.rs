@@ -0,0 +1,267 @@
+//! Walking a document tree without changing it.
+//!
+//! Each method of `Visit` has a default that just recurses into the
+//! node's children by calling the free function with the same name.
+//! Override the methods for the nodes you care about, and call the
+//! free function from your override if you still want to go deeper.
+//! `path` always points at the node being visited.
+//!
+//! See also `visit_mut` and `fold` for rewriting documents.
+
+use cid::Cid;
+
+use document::{Document, Element, Part, Segment};
+use path::{Path, Step};
+
+pub trait Visit<'ast> {
+    fn visit_document(&mut self, path: &mut Path, node: &'ast Document) {
+        visit_document(self, path, node)
+    }
+
+    fn visit_part(&mut self, path: &mut Path, node: &'ast Part) {
+        visit_part(self, path, node)
+    }
+
+    fn visit_segment(&mut self, path: &mut Path, node: &'ast Segment) {
+        visit_segment(self, path, node)
+    }
+
+    fn visit_element(&mut self, path: &mut Path, node: &'ast Element) {
+        visit_element(self, path, node)
+    }
+
+    /// Called for every link out of the document: previous revisions,
+    /// the document it responds to, figure sources and xref targets.
+    fn visit_cid(&mut self, _path: &mut Path, _node: &'ast Cid) {}
+}
+
+pub fn visit_document<'ast, V>(v: &mut V, path: &mut Path, node: &'ast Document)
+    where V: Visit<'ast> + ?Sized
+{
+    for (i, part) in node.contents.iter().enumerate() {
+        path.push(Step::Part(i));
+        v.visit_part(path, part);
+        path.pop();
+    }
+    if let Some(ref revisions) = node.previous_revisions {
+        path.push(Step::Field("previous_revisions"));
+        for (i, cid) in revisions.iter().enumerate() {
+            path.push(Step::Index(i));
+            v.visit_cid(path, cid);
+            path.pop();
+        }
+        path.pop();
+    }
+    path.push(Step::Field("tags"));
+    for (i, tag) in node.tags.iter().enumerate() {
+        if let Some(ref cid) = tag.vocabulary {
+            path.push(Step::Index(i));
+            path.push(Step::Field("vocabulary"));
+            v.visit_cid(path, cid);
+            path.pop();
+            path.pop();
+        }
+    }
+    path.pop();
+    if let Some(ref cid) = node.in_response_to {
+        path.push(Step::Field("in_response_to"));
+        v.visit_cid(path, cid);
+        path.pop();
+    }
+}
+
+pub fn visit_part<'ast, V>(v: &mut V, path: &mut Path, node: &'ast Part)
+    where V: Visit<'ast> + ?Sized
+{
+    match *node {
+        Part::Body(ref segments) => visit_segments(v, path, segments),
+        Part::Section { ref heading, ref contents, .. } => {
+            path.push(Step::Heading);
+            visit_elements(v, path, heading);
+            path.pop();
+            visit_segments(v, path, contents);
+        }
+    }
+}
+
+pub fn visit_segment<'ast, V>(v: &mut V, path: &mut Path, node: &'ast Segment)
+    where V: Visit<'ast> + ?Sized
+{
+    match *node {
+        Segment::Para(ref e) | Segment::Abstract(ref e) => visit_elements(v, path, e),
+        Segment::Table { ref header, ref body, ref footer } => {
+            for (i, cell) in header.iter().enumerate() {
+                path.push(Step::TableHeader(i));
+                visit_elements(v, path, cell);
+                path.pop();
+            }
+            for (i, row) in body.iter().enumerate() {
+                path.push(Step::TableRow(i));
+                visit_segments(v, path, row);
+                path.pop();
+            }
+            for (i, cell) in footer.iter().enumerate() {
+                path.push(Step::TableFooter(i));
+                visit_elements(v, path, cell);
+                path.pop();
+            }
+        }
+        Segment::Figure { ref caption, ref source, .. } => {
+            for (i, c) in caption.iter().enumerate() {
+                path.push(Step::Caption(i));
+                visit_elements(v, path, c);
+                path.pop();
+            }
+            path.push(Step::Field("source"));
+            v.visit_cid(path, source);
+            path.pop();
+        }
+        Segment::List { ref elements, .. } => visit_segments(v, path, elements),
+        Segment::Code { .. } | Segment::Math(_) => (),
+        Segment::Transclusion { ref source, .. } => {
+            path.push(Step::Field("source"));
+            v.visit_cid(path, source);
+            path.pop();
+        }
+        Segment::Quote(ref s) => {
+            path.push(Step::Quote);
+            v.visit_segment(path, s);
+            path.pop();
+        }
+        Segment::Conflict { ref ours, ref theirs } => {
+            for (i, s) in ours.iter().enumerate() {
+                path.push(Step::Ours(i));
+                v.visit_segment(path, s);
+                path.pop();
+            }
+            for (i, s) in theirs.iter().enumerate() {
+                path.push(Step::Theirs(i));
+                v.visit_segment(path, s);
+                path.pop();
+            }
+        }
+    }
+}
+
+pub fn visit_element<'ast, V>(v: &mut V, path: &mut Path, node: &'ast Element)
+    where V: Visit<'ast> + ?Sized
+{
+    match *node {
+        Element::Text(_) | Element::Math(_) | Element::Comment(_) | Element::Anchor(_) => (),
+        Element::Xref { ref contents, ref target, .. } => {
+            visit_elements(v, path, contents);
+            path.push(Step::Field("target"));
+            v.visit_cid(path, target);
+            path.pop();
+        }
+        Element::Link { contents: ref e, .. } |
+        Element::Span { contents: ref e, .. } |
+        Element::Strong(ref e) |
+        Element::Emphasized(ref e) |
+        Element::Footnote(ref e) |
+        Element::Subscript(ref e) |
+        Element::Superscript(ref e) |
+        Element::Insertion(ref e) |
+        Element::Deletion(ref e) |
+        Element::Preformatted(ref e) => visit_elements(v, path, e),
+    }
+}
+
+fn visit_segments<'ast, V>(v: &mut V, path: &mut Path, segments: &'ast [Segment])
+    where V: Visit<'ast> + ?Sized
+{
+    for (i, s) in segments.iter().enumerate() {
+        path.push(Step::Segment(i));
+        v.visit_segment(path, s);
+        path.pop();
+    }
+}
+
+fn visit_elements<'ast, V>(v: &mut V, path: &mut Path, elements: &'ast [Element])
+    where V: Visit<'ast> + ?Sized
+{
+    for (i, e) in elements.iter().enumerate() {
+        path.push(Step::Element(i));
+        v.visit_element(path, e);
+        path.pop();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cid::Cid;
+
+    use document::tests::{cid, kitchen_sink, CID_A, CID_B};
+    use document::*;
+    use path::Path;
+    use super::*;
+
+    /// Collects every text element and link, and where it found them.
+    #[derive(Default)]
+    struct Collector {
+        text: Vec<(String, String)>,
+        cids: Vec<(String, String)>,
+    }
+
+    impl<'ast> Visit<'ast> for Collector {
+        fn visit_element(&mut self, path: &mut Path, node: &'ast Element) {
+            if let Element::Text(ref s) = *node {
+                self.text.push((path.to_string(), s.clone()));
+            }
+            visit_element(self, path, node);
+        }
+
+        fn visit_cid(&mut self, path: &mut Path, node: &'ast Cid) {
+            self.cids.push((path.to_string(), node.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_visit_paths() {
+        let doc = Document::new(vec![
+            Part::Body(vec![Segment::Para(vec![
+                Element::Text("a".to_string()),
+                Element::Strong(vec![Element::Text("b".to_string())]),
+            ])]),
+            Part::Section {
+                level: 1,
+                heading: vec![Element::Text("c".to_string())],
+                contents: vec![Segment::Quote(Box::new(Segment::Table {
+                    header: vec![],
+                    body: vec![vec![Segment::Para(vec![Element::Text("d".to_string())])]],
+                    footer: vec![],
+                }))],
+            },
+        ]);
+        let mut c = Collector::default();
+        c.visit_document(&mut Path::new(), &doc);
+        let text: Vec<_> = c.text.iter().map(|(p, s)| (p.as_str(), s.as_str())).collect();
+        assert_eq!(text, vec![
+            ("part[0]/segment[0]/element[0]", "a"),
+            ("part[0]/segment[0]/element[1]/element[0]", "b"),
+            ("part[1]/heading/element[0]", "c"),
+            ("part[1]/segment[0]/quote/row[0]/segment[0]/element[0]", "d"),
+        ]);
+    }
+
+    #[test]
+    fn test_visit_cids() {
+        let mut c = Collector::default();
+        c.visit_document(&mut Path::new(), &kitchen_sink());
+        let a = cid(CID_A).to_string();
+        let b = cid(CID_B).to_string();
+        let paths: Vec<_> = c.cids.iter().map(|(p, _)| p.as_str()).collect();
+        assert!(c.cids.iter().all(|(_, cid)| *cid == a || *cid == b));
+        assert!(paths.contains(&"previous_revisions/[0]"));
+        assert!(paths.contains(&"in_response_to"));
+        assert!(paths.iter().any(|p| p.ends_with("/source")));
+        assert!(paths.iter().any(|p| p.ends_with("/target")));
+
+        let mut doc = kitchen_sink();
+        doc.tags = vec![Tag::new("free"), Tag::from_vocabulary("term", &cid(CID_B))];
+        let mut c = Collector::default();
+        c.visit_document(&mut Path::new(), &doc);
+        assert!(c.cids.contains(&("tags/[1]/vocabulary".to_string(), b)));
+    }
+}
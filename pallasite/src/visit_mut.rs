@@ -0,0 +1,244 @@
+//! Walking a document tree and changing it in place.
+//!
+//! Works just like `visit`, but every node is handed over as `&mut`.
+
+use cid::Cid;
+
+use document::{Document, Element, Part, Segment};
+use path::{Path, Step};
+
+pub trait VisitMut {
+    fn visit_document_mut(&mut self, path: &mut Path, node: &mut Document) {
+        visit_document_mut(self, path, node)
+    }
+
+    fn visit_part_mut(&mut self, path: &mut Path, node: &mut Part) {
+        visit_part_mut(self, path, node)
+    }
+
+    fn visit_segment_mut(&mut self, path: &mut Path, node: &mut Segment) {
+        visit_segment_mut(self, path, node)
+    }
+
+    fn visit_element_mut(&mut self, path: &mut Path, node: &mut Element) {
+        visit_element_mut(self, path, node)
+    }
+
+    fn visit_cid_mut(&mut self, _path: &mut Path, _node: &mut Cid) {}
+}
+
+pub fn visit_document_mut<V>(v: &mut V, path: &mut Path, node: &mut Document)
+    where V: VisitMut + ?Sized
+{
+    for (i, part) in node.contents.iter_mut().enumerate() {
+        path.push(Step::Part(i));
+        v.visit_part_mut(path, part);
+        path.pop();
+    }
+    if let Some(ref mut revisions) = node.previous_revisions {
+        path.push(Step::Field("previous_revisions"));
+        for (i, cid) in revisions.iter_mut().enumerate() {
+            path.push(Step::Index(i));
+            v.visit_cid_mut(path, cid);
+            path.pop();
+        }
+        path.pop();
+    }
+    path.push(Step::Field("tags"));
+    for (i, tag) in node.tags.iter_mut().enumerate() {
+        if let Some(ref mut cid) = tag.vocabulary {
+            path.push(Step::Index(i));
+            path.push(Step::Field("vocabulary"));
+            v.visit_cid_mut(path, cid);
+            path.pop();
+            path.pop();
+        }
+    }
+    path.pop();
+    if let Some(ref mut cid) = node.in_response_to {
+        path.push(Step::Field("in_response_to"));
+        v.visit_cid_mut(path, cid);
+        path.pop();
+    }
+}
+
+pub fn visit_part_mut<V>(v: &mut V, path: &mut Path, node: &mut Part)
+    where V: VisitMut + ?Sized
+{
+    match *node {
+        Part::Body(ref mut segments) => visit_segments_mut(v, path, segments),
+        Part::Section { ref mut heading, ref mut contents, .. } => {
+            path.push(Step::Heading);
+            visit_elements_mut(v, path, heading);
+            path.pop();
+            visit_segments_mut(v, path, contents);
+        }
+    }
+}
+
+pub fn visit_segment_mut<V>(v: &mut V, path: &mut Path, node: &mut Segment)
+    where V: VisitMut + ?Sized
+{
+    match *node {
+        Segment::Para(ref mut e) | Segment::Abstract(ref mut e) => visit_elements_mut(v, path, e),
+        Segment::Table { ref mut header, ref mut body, ref mut footer } => {
+            for (i, cell) in header.iter_mut().enumerate() {
+                path.push(Step::TableHeader(i));
+                visit_elements_mut(v, path, cell);
+                path.pop();
+            }
+            for (i, row) in body.iter_mut().enumerate() {
+                path.push(Step::TableRow(i));
+                visit_segments_mut(v, path, row);
+                path.pop();
+            }
+            for (i, cell) in footer.iter_mut().enumerate() {
+                path.push(Step::TableFooter(i));
+                visit_elements_mut(v, path, cell);
+                path.pop();
+            }
+        }
+        Segment::Figure { ref mut caption, ref mut source, .. } => {
+            for (i, c) in caption.iter_mut().enumerate() {
+                path.push(Step::Caption(i));
+                visit_elements_mut(v, path, c);
+                path.pop();
+            }
+            path.push(Step::Field("source"));
+            v.visit_cid_mut(path, source);
+            path.pop();
+        }
+        Segment::List { ref mut elements, .. } => visit_segments_mut(v, path, elements),
+        Segment::Code { .. } | Segment::Math(_) => (),
+        Segment::Transclusion { ref mut source, .. } => {
+            path.push(Step::Field("source"));
+            v.visit_cid_mut(path, source);
+            path.pop();
+        }
+        Segment::Quote(ref mut s) => {
+            path.push(Step::Quote);
+            v.visit_segment_mut(path, s);
+            path.pop();
+        }
+        Segment::Conflict { ref mut ours, ref mut theirs } => {
+            for (i, s) in ours.iter_mut().enumerate() {
+                path.push(Step::Ours(i));
+                v.visit_segment_mut(path, s);
+                path.pop();
+            }
+            for (i, s) in theirs.iter_mut().enumerate() {
+                path.push(Step::Theirs(i));
+                v.visit_segment_mut(path, s);
+                path.pop();
+            }
+        }
+    }
+}
+
+pub fn visit_element_mut<V>(v: &mut V, path: &mut Path, node: &mut Element)
+    where V: VisitMut + ?Sized
+{
+    match *node {
+        Element::Text(_) | Element::Math(_) | Element::Comment(_) | Element::Anchor(_) => (),
+        Element::Xref { ref mut contents, ref mut target, .. } => {
+            visit_elements_mut(v, path, contents);
+            path.push(Step::Field("target"));
+            v.visit_cid_mut(path, target);
+            path.pop();
+        }
+        Element::Link { contents: ref mut e, .. } |
+        Element::Span { contents: ref mut e, .. } |
+        Element::Strong(ref mut e) |
+        Element::Emphasized(ref mut e) |
+        Element::Footnote(ref mut e) |
+        Element::Subscript(ref mut e) |
+        Element::Superscript(ref mut e) |
+        Element::Insertion(ref mut e) |
+        Element::Deletion(ref mut e) |
+        Element::Preformatted(ref mut e) => visit_elements_mut(v, path, e),
+    }
+}
+
+fn visit_segments_mut<V>(v: &mut V, path: &mut Path, segments: &mut [Segment])
+    where V: VisitMut + ?Sized
+{
+    for (i, s) in segments.iter_mut().enumerate() {
+        path.push(Step::Segment(i));
+        v.visit_segment_mut(path, s);
+        path.pop();
+    }
+}
+
+fn visit_elements_mut<V>(v: &mut V, path: &mut Path, elements: &mut [Element])
+    where V: VisitMut + ?Sized
+{
+    for (i, e) in elements.iter_mut().enumerate() {
+        path.push(Step::Element(i));
+        v.visit_element_mut(path, e);
+        path.pop();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use cid::Cid;
+
+    use document::tests::{cid, kitchen_sink, CID_A, CID_B};
+    use document::*;
+    use path::Path;
+    use super::*;
+
+    struct Shout;
+
+    impl VisitMut for Shout {
+        fn visit_element_mut(&mut self, path: &mut Path, node: &mut Element) {
+            if let Element::Text(ref mut s) = *node {
+                *s = s.to_uppercase();
+            }
+            visit_element_mut(self, path, node);
+        }
+    }
+
+    /// Points every link at the same place.
+    struct Redirect(Cid);
+
+    impl VisitMut for Redirect {
+        fn visit_cid_mut(&mut self, _path: &mut Path, node: &mut Cid) {
+            *node = self.0.clone();
+        }
+    }
+
+    #[test]
+    fn test_rewrite_text() {
+        let mut doc = Document::new(vec![Part::Section {
+            level: 1,
+            heading: vec![Element::Text("title".to_string())],
+            contents: vec![Segment::Quote(Box::new(Segment::Para(vec![
+                Element::Emphasized(vec![Element::Text("quiet".to_string())]),
+                Element::Comment("not text".to_string()),
+            ])))],
+        }]);
+        Shout.visit_document_mut(&mut Path::new(), &mut doc);
+        assert_eq!(doc, Document::new(vec![Part::Section {
+            level: 1,
+            heading: vec![Element::Text("TITLE".to_string())],
+            contents: vec![Segment::Quote(Box::new(Segment::Para(vec![
+                Element::Emphasized(vec![Element::Text("QUIET".to_string())]),
+                Element::Comment("not text".to_string()),
+            ])))],
+        }]));
+    }
+
+    #[test]
+    fn test_rewrite_cids() {
+        let mut doc = kitchen_sink();
+        Redirect(cid(CID_B)).visit_document_mut(&mut Path::new(), &mut doc);
+        assert_ne!(doc, kitchen_sink());
+        assert_eq!(doc.in_response_to, Some(cid(CID_B)));
+        assert_eq!(doc.previous_revisions, Some(vec![cid(CID_B), cid(CID_B)]));
+
+        Redirect(cid(CID_A)).visit_document_mut(&mut Path::new(), &mut doc);
+        assert_eq!(doc.in_response_to, Some(cid(CID_A)));
+    }
+}
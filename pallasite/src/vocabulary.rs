@@ -0,0 +1,235 @@
+//! Controlled vocabularies: published lists of the terms documents
+//! may be tagged with.
+//!
+//! A vocabulary is stored as its own DAG-CBOR block, and a `Tag`
+//! refers to it by CID, so everyone using a taxonomy agrees on exactly
+//! which list of terms they mean.  Publishing a new version of the
+//! list gives it a new CID.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use cid::Cid;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::Error as DeError;
+use serde::ser::SerializeStruct;
+
+use cbor;
+use document::Document;
+use path::{Path, Step};
+use store::{self, CidKey, Lookup};
+
+/// Version of the serialized form of a `Vocabulary`.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Serialized as `{ "version": FORMAT_VERSION, "vocabulary": { ... } }`,
+/// like a `Document`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
+pub struct Vocabulary {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub terms: Vec<Term>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Term {
+    pub term: String,
+    /// What the term means, for people choosing between them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl Vocabulary {
+    pub fn new(name: &str, terms: &[&str]) -> Vocabulary {
+        Vocabulary {
+            name: name.to_string(),
+            description: None,
+            terms: terms.iter().map(|t| Term { term: t.to_string(), description: None }).collect(),
+        }
+    }
+
+    /// Whether the term is in the vocabulary.  Terms are case sensitive.
+    pub fn contains(&self, term: &str) -> bool {
+        self.terms.iter().any(|t| t.term == term)
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        cbor::to_vec(self).expect("vocabularies contain nothing DAG-CBOR can't represent")
+    }
+
+    pub fn from_cbor(bytes: &[u8]) -> Result<Vocabulary, cbor::Error> {
+        cbor::from_block(bytes)
+    }
+
+    pub fn cid(&self) -> Cid {
+        cbor::block_cid(&self.to_cbor())
+    }
+
+    pub fn load<L>(store: &L, cid: &Cid) -> Result<Vocabulary, store::Error>
+        where L: Lookup + ?Sized
+    {
+        let bytes = store.get(cid).ok_or_else(|| store::Error::Missing(cid.clone()))?;
+        Vocabulary::from_cbor(&bytes).map_err(|e| store::Error::Decode(cid.clone(), e))
+    }
+}
+
+struct Unversioned<'a>(&'a Vocabulary);
+
+impl<'a> Serialize for Unversioned<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        Vocabulary::serialize(self.0, serializer)
+    }
+}
+
+impl Serialize for Vocabulary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut s = serializer.serialize_struct("VersionedVocabulary", 2)?;
+        s.serialize_field("version", &FORMAT_VERSION)?;
+        s.serialize_field("vocabulary", &Unversioned(self))?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Vocabulary {
+    fn deserialize<D>(deserializer: D) -> Result<Vocabulary, D::Error>
+        where D: Deserializer<'de>
+    {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u32,
+            #[serde(deserialize_with = "Vocabulary::deserialize")]
+            vocabulary: Vocabulary,
+        }
+        let v = Versioned::deserialize(deserializer)?;
+        if v.version != FORMAT_VERSION {
+            return Err(D::Error::custom(format!("unsupported vocabulary format version {}", v.version)));
+        }
+        Ok(v.vocabulary)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// We couldn't get the vocabulary.
+    Store(store::Error),
+    /// The vocabulary doesn't have the term.
+    UnknownTerm { term: String, vocabulary: Cid },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Store(ref e) => write!(f, "{}", e),
+            Error::UnknownTerm { ref term, ref vocabulary } => {
+                write!(f, "term {:?} is not in vocabulary {}", term, vocabulary)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A tag that doesn't check out.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Problem {
+    /// Where the tag is, like `tags/[2]`.
+    pub path: Path,
+    pub error: Error,
+}
+
+/// Checks that every tag with a vocabulary uses a term from it.
+/// Free-form tags are left alone.
+pub fn check<L>(store: &L, doc: &Document) -> Vec<Problem>
+    where L: Lookup + ?Sized
+{
+    let mut vocabularies: HashMap<CidKey, Result<Vocabulary, store::Error>> = HashMap::new();
+    let mut problems = Vec::new();
+    for (i, tag) in doc.tags.iter().enumerate() {
+        let cid = match tag.vocabulary {
+            Some(ref cid) => cid,
+            None => continue,
+        };
+        let vocabulary = vocabularies.entry(CidKey::new(cid)).or_insert_with(|| Vocabulary::load(store, cid));
+        let error = match *vocabulary {
+            Ok(ref v) if v.contains(&tag.term) => continue,
+            Ok(_) => Error::UnknownTerm { term: tag.term.clone(), vocabulary: cid.clone() },
+            Err(ref e) => Error::Store(e.clone()),
+        };
+        problems.push(Problem {
+            path: Path(vec![Step::Field("tags"), Step::Index(i)]),
+            error,
+        });
+    }
+    problems
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use document::Tag;
+    use store::MemoryStore;
+    use super::*;
+
+    fn colors() -> Vocabulary {
+        let mut v = Vocabulary::new("Colors", &["red", "green"]);
+        v.terms[0].description = Some("Like a tomato".to_string());
+        v
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let v = colors();
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(json, r#"{"version":1,"vocabulary":{"name":"Colors","terms":[{"term":"red","description":"Like a tomato"},{"term":"green"}]}}"#);
+        assert_eq!(serde_json::from_str::<Vocabulary>(&json).unwrap(), v);
+        assert_eq!(Vocabulary::from_cbor(&v.to_cbor()).unwrap(), v);
+
+        let mut store = MemoryStore::new();
+        let c = store.put(v.to_cbor());
+        assert_eq!(c, v.cid());
+        assert_eq!(Vocabulary::load(&store, &c), Ok(v));
+    }
+
+    #[test]
+    fn test_not_a_vocabulary() {
+        let mut store = MemoryStore::new();
+        let c = store.put_document(&Document::new(vec![]));
+        match Vocabulary::load(&store, &c) {
+            Err(store::Error::Decode(ref d, _)) => assert_eq!(*d, c),
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let mut store = MemoryStore::new();
+        let colors = store.put(colors().to_cbor());
+        let gone = Vocabulary::new("Gone", &[]).cid();
+        let mut doc = Document::new(vec![]);
+        doc.tags = vec![
+            Tag::from_vocabulary("red", &colors),
+            Tag::new("anything"),
+            Tag::from_vocabulary("Red", &colors),
+            Tag::from_vocabulary("x", &gone),
+        ];
+        assert_eq!(check(&store, &doc), vec![
+            Problem {
+                path: Path(vec![Step::Field("tags"), Step::Index(2)]),
+                error: Error::UnknownTerm { term: "Red".to_string(), vocabulary: colors.clone() },
+            },
+            Problem {
+                path: Path(vec![Step::Field("tags"), Step::Index(3)]),
+                error: Error::Store(store::Error::Missing(gone)),
+            },
+        ]);
+    }
+}
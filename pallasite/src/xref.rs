@@ -0,0 +1,214 @@
+//! Following cross references into other documents.
+
+use std::error;
+use std::fmt;
+
+use cid::Cid;
+
+use document::{Document, Element};
+use path::{Path, Step};
+use store::{self, Lookup};
+use toc;
+use visit::{self, Visit};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// We couldn't get the target document.
+    Store(store::Error),
+    /// The target document has no anchor by that name.
+    DanglingFragment { target: Cid, fragment: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Store(ref e) => write!(f, "{}", e),
+            Error::DanglingFragment { ref target, ref fragment } => {
+                write!(f, "document {} has no anchor {:?}", target, fragment)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<store::Error> for Error {
+    fn from(e: store::Error) -> Error {
+        Error::Store(e)
+    }
+}
+
+/// Where a cross reference leads.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Resolved {
+    pub document: Document,
+    /// Where the anchor is in the document, if the reference had a fragment.
+    pub anchor: Option<Path>,
+}
+
+/// A cross reference that doesn't lead anywhere.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Broken {
+    /// Where the `Element::Xref` is in the document that has it.
+    pub path: Path,
+    pub error: Error,
+}
+
+/// Where the `Element::Anchor` with the given name is, if there is one.
+/// Failing that, a section whose generated anchor has the name will do,
+/// so links to `#introduction` work whether or not the author put an
+/// anchor in the heading.
+pub fn find_anchor(doc: &Document, name: &str) -> Option<Path> {
+    struct Finder<'n> {
+        name: &'n str,
+        found: Option<Path>,
+    }
+
+    impl<'n, 'ast> Visit<'ast> for Finder<'n> {
+        fn visit_element(&mut self, path: &mut Path, node: &'ast Element) {
+            if self.found.is_some() {
+                return;
+            }
+            if let Element::Anchor(ref a) = *node {
+                if a == self.name {
+                    self.found = Some(path.clone());
+                    return;
+                }
+            }
+            visit::visit_element(self, path, node);
+        }
+    }
+
+    let mut f = Finder { name, found: None };
+    f.visit_document(&mut Path::new(), doc);
+    f.found.or_else(|| toc::section(doc, name).map(|i| Path(vec![Step::Part(i), Step::Heading])))
+}
+
+/// Fetches the target of a cross reference and finds the anchor it
+/// points at.
+pub fn resolve<L>(store: &L, target: &Cid, fragment: Option<&str>) -> Result<Resolved, Error>
+    where L: Lookup + ?Sized
+{
+    let document = store.document(target)?;
+    let anchor = match fragment {
+        Some(name) => match find_anchor(&document, name) {
+            Some(path) => Some(path),
+            None => {
+                return Err(Error::DanglingFragment {
+                    target: target.clone(),
+                    fragment: name.to_string(),
+                })
+            }
+        },
+        None => None,
+    };
+    Ok(Resolved { document, anchor })
+}
+
+/// Tries to resolve every cross reference in a document, returning the
+/// ones that don't work.
+pub fn check<L>(store: &L, doc: &Document) -> Vec<Broken>
+    where L: Lookup + ?Sized
+{
+    struct Checker<'s, L: 's + ?Sized> {
+        store: &'s L,
+        broken: Vec<Broken>,
+    }
+
+    impl<'s, 'ast, L: Lookup + ?Sized> Visit<'ast> for Checker<'s, L> {
+        fn visit_element(&mut self, path: &mut Path, node: &'ast Element) {
+            if let Element::Xref { ref target, ref fragment, .. } = *node {
+                let fragment = fragment.as_ref().map(|f| &**f);
+                if let Err(error) = resolve(self.store, target, fragment) {
+                    self.broken.push(Broken { path: path.clone(), error });
+                }
+            }
+            visit::visit_element(self, path, node);
+        }
+    }
+
+    let mut c = Checker { store, broken: Vec::new() };
+    c.visit_document(&mut Path::new(), doc);
+    c.broken
+}
+
+
+#[cfg(test)]
+mod tests {
+    use document::tests::text;
+    use document::*;
+    use path::{Path, Step};
+    use store::MemoryStore;
+    use super::*;
+
+    fn xref(target: &Cid, fragment: Option<&str>) -> Element {
+        Element::Xref {
+            contents: vec![text("see")],
+            target: target.clone(),
+            fragment: fragment.map(|f| f.to_string()),
+        }
+    }
+
+    fn target() -> Document {
+        Document::new(vec![
+            Part::Body(vec![Segment::Para(vec![text("intro")])]),
+            Part::Section {
+                level: 1,
+                heading: vec![Element::Anchor("usage".to_string()), text("Usage")],
+                contents: vec![Segment::Para(vec![
+                    Element::Strong(vec![Element::Anchor("warning".to_string())]),
+                ])],
+            },
+        ])
+    }
+
+    #[test]
+    fn test_find_anchor() {
+        let doc = target();
+        assert_eq!(find_anchor(&doc, "usage"), Some(Path(vec![Step::Part(1), Step::Heading, Step::Element(0)])));
+        assert_eq!(find_anchor(&doc, "warning").unwrap().to_string(),
+                   "part[1]/segment[0]/element[0]/element[0]");
+        assert_eq!(find_anchor(&doc, "Usage"), None);
+
+        let doc = Document::new(vec![Part::Section { level: 1, heading: vec![text("Getting started")], contents: vec![] }]);
+        assert_eq!(find_anchor(&doc, "getting-started"), Some(Path(vec![Step::Part(0), Step::Heading])));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut store = MemoryStore::new();
+        let c = store.put_document(&target());
+        let r = resolve(&store, &c, Some("usage")).unwrap();
+        assert_eq!(r.document, target());
+        assert!(r.anchor.is_some());
+        assert_eq!(resolve(&store, &c, None).unwrap().anchor, None);
+        assert_eq!(resolve(&store, &c, Some("nope")), Err(Error::DanglingFragment {
+            target: c.clone(),
+            fragment: "nope".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_check() {
+        let mut store = MemoryStore::new();
+        let good = store.put_document(&target());
+        let gone = Document::new(vec![]).cid();
+        let doc = Document::new(vec![Part::Body(vec![Segment::Para(vec![
+            xref(&good, Some("warning")),
+            xref(&good, None),
+            xref(&good, Some("dangling")),
+            xref(&gone, None),
+        ])])]);
+        let broken = check(&store, &doc);
+        assert_eq!(broken, vec![
+            Broken {
+                path: Path(vec![Step::Part(0), Step::Segment(0), Step::Element(2)]),
+                error: Error::DanglingFragment { target: good.clone(), fragment: "dangling".to_string() },
+            },
+            Broken {
+                path: Path(vec![Step::Part(0), Step::Segment(0), Step::Element(3)]),
+                error: Error::Store(store::Error::Missing(gone)),
+            },
+        ]);
+    }
+}
@@ -0,0 +1,202 @@
+//! Just enough of the ZIP format to write an EPUB: files are stored
+//! without compression, in the order they're added.  There's no ZIP64,
+//! so archives are limited to 4 GiB and 65,535 files.
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+
+/// The date and time MS-DOS style, as 1980-01-01 00:00, so the same
+/// files always make the same archive.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A file, or the archive as a whole, is 4 GiB or more.
+    TooLarge,
+    /// There are more than 65,535 files.
+    TooManyFiles,
+    /// A file name is more than 65,535 bytes long.
+    NameTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::TooLarge => write!(f, "archive is too large for ZIP without ZIP64"),
+            Error::TooManyFiles => write!(f, "too many files for ZIP without ZIP64"),
+            Error::NameTooLong => write!(f, "file name is too long for ZIP"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+pub struct Writer {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    count: u16,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer { out: Vec::new(), central: Vec::new(), count: 0 }
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<(), Error> {
+        if self.count == u16::MAX {
+            return Err(Error::TooManyFiles);
+        }
+        let name_len = u16::try_from(name.len()).map_err(|_| Error::NameTooLong)?;
+        let offset = u32::try_from(self.out.len()).map_err(|_| Error::TooLarge)?;
+        let size = u32::try_from(data.len()).map_err(|_| Error::TooLarge)?;
+        let crc = crc32(data);
+
+        put32(&mut self.out, 0x0403_4b50);
+        put16(&mut self.out, 10); // version needed: 1.0, plain storage
+        put16(&mut self.out, 0); // flags
+        put16(&mut self.out, 0); // method: stored
+        put16(&mut self.out, DOS_TIME);
+        put16(&mut self.out, DOS_DATE);
+        put32(&mut self.out, crc);
+        put32(&mut self.out, size);
+        put32(&mut self.out, size);
+        put16(&mut self.out, name_len);
+        put16(&mut self.out, 0); // extra field length
+        self.out.extend_from_slice(name.as_bytes());
+        self.out.extend_from_slice(data);
+
+        put32(&mut self.central, 0x0201_4b50);
+        put16(&mut self.central, 10); // version made by
+        put16(&mut self.central, 10); // version needed
+        put16(&mut self.central, 0);
+        put16(&mut self.central, 0);
+        put16(&mut self.central, DOS_TIME);
+        put16(&mut self.central, DOS_DATE);
+        put32(&mut self.central, crc);
+        put32(&mut self.central, size);
+        put32(&mut self.central, size);
+        put16(&mut self.central, name_len);
+        put16(&mut self.central, 0); // extra field length
+        put16(&mut self.central, 0); // comment length
+        put16(&mut self.central, 0); // disk number
+        put16(&mut self.central, 0); // internal attributes
+        put32(&mut self.central, 0); // external attributes
+        put32(&mut self.central, offset);
+        self.central.extend_from_slice(name.as_bytes());
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, Error> {
+        let offset = u32::try_from(self.out.len()).map_err(|_| Error::TooLarge)?;
+        let size = u32::try_from(self.central.len()).map_err(|_| Error::TooLarge)?;
+        self.out.append(&mut self.central);
+        put32(&mut self.out, 0x0605_4b50);
+        put16(&mut self.out, 0); // this disk
+        put16(&mut self.out, 0); // disk with the central directory
+        put16(&mut self.out, self.count);
+        put16(&mut self.out, self.count);
+        put32(&mut self.out, size);
+        put32(&mut self.out, offset);
+        put16(&mut self.out, 0); // comment length
+        Ok(self.out)
+    }
+}
+
+fn put16(out: &mut Vec<u8>, n: u16) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+/// The CRC-32 ZIP uses, the same as Ethernet's.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// The files in an archive `Writer` made, in order, checking the
+/// central directory agrees with them.
+#[cfg(test)]
+pub fn read(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+    fn get16(b: &[u8], at: usize) -> usize {
+        u16::from_le_bytes([b[at], b[at + 1]]) as usize
+    }
+    fn get32(b: &[u8], at: usize) -> usize {
+        u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]]) as usize
+    }
+
+    let end = zip.len() - 22;
+    assert_eq!(get32(zip, end), 0x0605_4b50);
+    let count = get16(zip, end + 10);
+    let mut central = get32(zip, end + 16);
+    let mut files = Vec::new();
+    for _ in 0..count {
+        assert_eq!(get32(zip, central), 0x0201_4b50);
+        let offset = get32(zip, central + 42);
+        let name_len = get16(zip, central + 28);
+        let central_name = &zip[central + 46..central + 46 + name_len];
+        central += 46 + name_len;
+
+        assert_eq!(get32(zip, offset), 0x0403_4b50);
+        assert_eq!(get16(zip, offset + 8), 0, "stored");
+        let size = get32(zip, offset + 18);
+        let name_len = get16(zip, offset + 26);
+        let extra_len = get16(zip, offset + 28);
+        let name = &zip[offset + 30..offset + 30 + name_len];
+        assert_eq!(name, central_name);
+        let start = offset + 30 + name_len + extra_len;
+        let data = zip[start..start + size].to_vec();
+        assert_eq!(get32(zip, offset + 14) as u32, crc32(&data));
+        files.push((String::from_utf8(name.to_vec()).unwrap(), data));
+    }
+    files
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut w = Writer::new();
+        w.add("mimetype", b"application/epub+zip").unwrap();
+        w.add("a/b.txt", b"").unwrap();
+        let zip = w.finish().unwrap();
+        // The mimetype has to be readable at a fixed offset.
+        assert_eq!(&zip[30..38], b"mimetype");
+        assert_eq!(&zip[38..58], b"application/epub+zip");
+        assert_eq!(read(&zip), vec![
+            ("mimetype".to_string(), b"application/epub+zip".to_vec()),
+            ("a/b.txt".to_string(), Vec::new()),
+        ]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut w = Writer::new();
+        assert_eq!(w.add(&"x".repeat(65536), b""), Err(Error::NameTooLong));
+        for _ in 0..65535 {
+            w.add("", b"").unwrap();
+        }
+        assert_eq!(w.add("", b""), Err(Error::TooManyFiles));
+        assert_eq!(read(&w.finish().unwrap()).len(), 65535);
+    }
+}